@@ -4,7 +4,38 @@ extern crate kankyo;
 extern crate test;
 
 use self::test::Bencher;
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The system allocator, counting the allocations made so that benches can
+// assert that a code path does not allocate.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Returns the number of allocations made by the function.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
 
 #[bench]
 fn snapshot(b: &mut Bencher) {
@@ -26,6 +57,7 @@ fn unload_from_reader(b: &mut Bencher) {
 
 mod utils {
     use kankyo;
+    use super::allocations;
     use test::Bencher;
 
     #[bench]
@@ -44,6 +76,54 @@ mod utils {
         });
     }
 
+    #[bench]
+    fn parse_line_cow_plain(b: &mut Bencher) {
+        let parse = || {
+            kankyo::utils::parse_line_cow("KEY=VALUE");
+            kankyo::utils::parse_line_cow("KEY2=\"VALUE # 2\"");
+        };
+
+        assert_eq!(allocations(parse), 0);
+
+        b.iter(parse);
+    }
+
+    #[bench]
+    fn parse_line_cow_escaped(b: &mut Bencher) {
+        let parse = || {
+            kankyo::utils::parse_line_cow(r#"KEY="VALUE\nVALUE""#);
+        };
+
+        assert_eq!(allocations(parse), 1);
+
+        b.iter(parse);
+    }
+
+    // A large file of ASCII lines, some with comments.
+    fn large_ascii() -> String {
+        (0..1000)
+            .map(|i| format!("SERVICE_KEY_{}=some-value-{} # note\n", i, i))
+            .collect()
+    }
+
+    #[bench]
+    fn parse_line_large_ascii(b: &mut Bencher) {
+        let s = large_ascii();
+
+        b.iter(|| {
+            s.lines().filter_map(kankyo::utils::parse_line).count()
+        });
+    }
+
+    #[bench]
+    fn parse_line_large_ascii_find(b: &mut Bencher) {
+        let s = large_ascii();
+
+        b.iter(|| {
+            s.lines().filter_map(kankyo::__private::parse_line_find).count()
+        });
+    }
+
     #[bench]
     fn parse_line_multi(b: &mut Bencher) {
         b.iter(|| {
@@ -64,6 +144,41 @@ mod utils {
         });
     }
 
+    // A generated file in which a handful of keys are each defined many times.
+    fn repeated_keys() -> String {
+        (0..1000)
+            .map(|i| format!("APP_SERVICE_KEY_{}={}\n", i % 10, i))
+            .collect()
+    }
+
+    #[bench]
+    fn parse_lines_owned_repeated(b: &mut Bencher) {
+        let s = repeated_keys();
+
+        b.iter(|| {
+            kankyo::utils::parse_lines_owned(&s);
+        });
+    }
+
+    #[bench]
+    fn parse_lines_interned_repeated(b: &mut Bencher) {
+        let s = repeated_keys();
+
+        // Each line's key allocates when owned, but only each distinct key
+        // does when interned.
+        let owned = allocations(|| {
+            kankyo::utils::parse_lines_owned(&s);
+        });
+        let interned = allocations(|| {
+            kankyo::utils::parse_lines_interned(&s);
+        });
+        assert!(interned + 900 < owned, "{} vs {}", interned, owned);
+
+        b.iter(|| {
+            kankyo::utils::parse_lines_interned(&s);
+        });
+    }
+
     #[bench]
     fn set_variables(b: &mut Bencher) {
         let s = "KEY=VALUE\nKEY2=VALUE2\nKEY3=VALUE3\nKEY4=VALUE4#abc";
@@ -0,0 +1,364 @@
+use error::{ConfigError, SchemaError};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::io::Read;
+use std::result::Result as StdResult;
+use std::str::FromStr;
+use super::{read_to_string, Result};
+use utils;
+
+/// A set of parsed variables with typed accessors, which does not write to the
+/// environment.
+///
+/// If a key is defined multiple times, the last definition wins. By default,
+/// the environment is not read either; refer to [`with_env_precedence`] to
+/// also consult it.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::Config;
+///
+/// let config: Config = "HOST=localhost\nPORT=8080\nDEBUG=true".parse()
+///     .unwrap();
+///
+/// assert_eq!(config.get_str("HOST"), Some("localhost".to_owned()));
+/// assert_eq!(config.get_int::<u16>("PORT").unwrap(), Some(8080));
+/// assert_eq!(config.get_bool("DEBUG").unwrap(), Some(true));
+/// assert_eq!(config.get_or("WORKERS", 4).unwrap(), 4);
+/// ```
+///
+/// [`with_env_precedence`]: #method.with_env_precedence
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    values: HashMap<String, String>,
+    // Whether the environment takes precedence over the parsed values, or
+    // `None` if the environment is not consulted.
+    env_first: Option<bool>,
+}
+
+impl Config {
+    /// Reads the content of a reader and parses it into a config.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if there was an error reading from the
+    /// reader.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        let s = read_to_string(reader)?;
+
+        Ok(Self::from(utils::parse_to_map(&s)))
+    }
+
+    /// Consults the environment as well as the parsed values in the getters,
+    /// returning the config.
+    ///
+    /// If `env_first` is `true`, a variable in the environment takes
+    /// precedence over a parsed value, falling back to the parsed value if the
+    /// variable does not exist. This lets runtime overrides win over a file.
+    /// If `false`, the parsed value takes precedence, falling back to the
+    /// environment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::Config;
+    /// use std::env;
+    ///
+    /// env::set_var("CONFIG_DOC_PORT", "3000");
+    ///
+    /// let config: Config = "CONFIG_DOC_PORT=8080".parse().unwrap();
+    /// assert_eq!(config.get_str("CONFIG_DOC_PORT").unwrap(), "8080");
+    ///
+    /// let config = config.with_env_precedence(true);
+    /// assert_eq!(config.get_str("CONFIG_DOC_PORT").unwrap(), "3000");
+    /// ```
+    pub fn with_env_precedence(mut self, env_first: bool) -> Self {
+        self.env_first = Some(env_first);
+
+        self
+    }
+
+    /// Returns the value of a key, if it exists.
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        let parsed = || self.values.get(key).cloned();
+
+        match self.env_first {
+            None => parsed(),
+            Some(true) => env::var(key).ok().or_else(parsed),
+            Some(false) => parsed().or_else(|| env::var(key).ok()),
+        }
+    }
+
+    /// Validates that the config has the keys required by a schema.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Config, Schema, SchemaError};
+    ///
+    /// let config: Config = "HOST=localhost\nAPI_KEY=".parse().unwrap();
+    /// let schema = Schema::new()
+    ///     .required("HOST")
+    ///     .required("PORT")
+    ///     .required_non_empty("API_KEY");
+    ///
+    /// assert_eq!(config.validate(&schema).unwrap_err(), vec![
+    ///     SchemaError::Missing("PORT".to_owned()),
+    ///     SchemaError::Empty("API_KEY".to_owned()),
+    /// ]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SchemaError`] for each required key that does not exist,
+    /// and for each required non-empty key whose value is empty, in the order
+    /// that the keys were added to the schema.
+    ///
+    /// [`SchemaError`]: enum.SchemaError.html
+    pub fn validate(&self, schema: &Schema) -> StdResult<(), Vec<SchemaError>> {
+        let errors = schema.keys
+            .iter()
+            .filter_map(|&(ref key, non_empty)| match self.get_str(key) {
+                None => Some(SchemaError::Missing(key.clone())),
+                Some(ref value) if non_empty && value.is_empty() => {
+                    Some(SchemaError::Empty(key.clone()))
+                },
+                Some(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the value of a key parsed as a number, if it exists.
+    ///
+    /// Any type implementing `FromStr` can be used, such as `u16` for a port.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if the value could not be parsed.
+    ///
+    /// [`ConfigError`]: struct.ConfigError.html
+    pub fn get_int<T: FromStr>(
+        &self,
+        key: &str,
+    ) -> StdResult<Option<T>, ConfigError> {
+        self.get_str(key).map(|value| parse(key, value)).transpose()
+    }
+
+    /// Returns the value of a key parsed as a boolean, if it exists.
+    ///
+    /// Like [`Value::infer`], only exactly `true` or `false` is a boolean.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if the value is neither `true` nor `false`.
+    ///
+    /// [`ConfigError`]: struct.ConfigError.html
+    /// [`Value::infer`]: utils/enum.Value.html#method.infer
+    pub fn get_bool(&self, key: &str) -> StdResult<Option<bool>, ConfigError> {
+        self.get_str(key).map(|value| parse(key, value)).transpose()
+    }
+
+    /// Returns the value of a key parsed as the type of the default, or the
+    /// default if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`] if the key exists but its value could not be
+    /// parsed. The default is _not_ used in this case, so that a typo in a
+    /// value is not silently ignored.
+    ///
+    /// [`ConfigError`]: struct.ConfigError.html
+    pub fn get_or<T: FromStr>(
+        &self,
+        key: &str,
+        default: T,
+    ) -> StdResult<T, ConfigError> {
+        Ok(self.get_int(key)?.unwrap_or(default))
+    }
+}
+
+impl From<HashMap<String, String>> for Config {
+    fn from(values: HashMap<String, String>) -> Self {
+        Config {
+            values,
+            env_first: None,
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> StdResult<Self, Infallible> {
+        Ok(Self::from(utils::parse_to_map(s)))
+    }
+}
+
+/// The keys that a [`Config`] is required to have, for use with
+/// [`Config::validate`].
+///
+/// [`Config`]: struct.Config.html
+/// [`Config::validate`]: struct.Config.html#method.validate
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Schema {
+    // Each required key, alongside whether its value must be non-empty.
+    keys: Vec<(String, bool)>,
+}
+
+impl Schema {
+    /// Creates a new schema with no required keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires a key to exist, returning the schema.
+    ///
+    /// An empty value satisfies this; use [`required_non_empty`] to also
+    /// reject empty values.
+    ///
+    /// [`required_non_empty`]: #method.required_non_empty
+    pub fn required(mut self, key: &str) -> Self {
+        self.keys.push((key.to_owned(), false));
+
+        self
+    }
+
+    /// Requires a key to exist with a non-empty value, returning the schema.
+    ///
+    /// This catches lines such as `API_KEY=`, where a value was meant to be
+    /// filled in.
+    pub fn required_non_empty(mut self, key: &str) -> Self {
+        self.keys.push((key.to_owned(), true));
+
+        self
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: String) -> StdResult<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError {
+        key: key.to_owned(),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::str::FromStr;
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_str("S=text\nN=8080\nNEG=-3\nB=true\nX=yes").unwrap()
+    }
+
+    #[test]
+    fn from_reader() {
+        let mut cursor = Cursor::new("A=1\nA=2 # last wins");
+        let config = Config::from_reader(&mut cursor).unwrap();
+
+        assert_eq!(config.get_str("A"), Some("2".to_owned()));
+    }
+
+    #[test]
+    fn get_str() {
+        let config = config();
+
+        assert_eq!(config.get_str("S"), Some("text".to_owned()));
+        assert_eq!(config.get_str("MISSING"), None);
+    }
+
+    #[test]
+    fn get_int() {
+        let config = config();
+
+        assert_eq!(config.get_int::<u16>("N").unwrap(), Some(8080));
+        assert_eq!(config.get_int::<i8>("NEG").unwrap(), Some(-3));
+        assert_eq!(config.get_int::<u8>("MISSING").unwrap(), None);
+
+        let err = config.get_int::<u8>("N").unwrap_err();
+        assert_eq!(err.key, "N");
+        assert_eq!(err.value, "8080");
+        assert!(config.get_int::<u32>("NEG").is_err());
+        assert!(config.get_int::<u32>("S").is_err());
+    }
+
+    #[test]
+    fn get_bool() {
+        let config = config();
+
+        assert_eq!(config.get_bool("B").unwrap(), Some(true));
+        assert_eq!(config.get_bool("MISSING").unwrap(), None);
+        assert_eq!(config.get_bool("X").unwrap_err().key, "X");
+    }
+
+    #[test]
+    fn get_or() {
+        let config = config();
+
+        assert_eq!(config.get_or("N", 1u16).unwrap(), 8080);
+        assert_eq!(config.get_or("MISSING", 1u16).unwrap(), 1);
+        assert_eq!(
+            config.get_or("S", "default".to_owned()).unwrap(),
+            "text",
+        );
+        assert!(config.get_or("S", 1u16).is_err());
+    }
+
+    #[test]
+    fn env_precedence() {
+        env::set_var("CONFIG_BOTH", "env");
+        env::set_var("CONFIG_ENV_ONLY", "env");
+
+        let config = Config::from_str("CONFIG_BOTH=file\nCONFIG_FILE_ONLY=file")
+            .unwrap();
+        assert_eq!(config.get_str("CONFIG_BOTH").unwrap(), "file");
+        assert_eq!(config.get_str("CONFIG_ENV_ONLY"), None);
+
+        let env_first = config.clone().with_env_precedence(true);
+        assert_eq!(env_first.get_str("CONFIG_BOTH").unwrap(), "env");
+        assert_eq!(env_first.get_str("CONFIG_FILE_ONLY").unwrap(), "file");
+        assert_eq!(env_first.get_str("CONFIG_ENV_ONLY").unwrap(), "env");
+
+        let file_first = config.with_env_precedence(false);
+        assert_eq!(file_first.get_str("CONFIG_BOTH").unwrap(), "file");
+        assert_eq!(file_first.get_str("CONFIG_FILE_ONLY").unwrap(), "file");
+        assert_eq!(file_first.get_str("CONFIG_ENV_ONLY").unwrap(), "env");
+
+        env::remove_var("CONFIG_BOTH");
+        env::remove_var("CONFIG_ENV_ONLY");
+    }
+
+    #[test]
+    fn validate() {
+        let config = Config::from_str("A=1\nEMPTY=\nOPTIONAL=").unwrap();
+
+        let schema = Schema::new().required("A").required("EMPTY");
+        assert!(config.validate(&schema).is_ok());
+
+        let schema = Schema::new()
+            .required_non_empty("A")
+            .required_non_empty("EMPTY")
+            .required_non_empty("MISSING")
+            .required("OPTIONAL");
+        assert_eq!(config.validate(&schema).unwrap_err(), vec![
+            SchemaError::Empty("EMPTY".to_owned()),
+            SchemaError::Missing("MISSING".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn does_not_touch_env() {
+        let config = Config::from_str("CONFIG_NOT_IN_ENV=1").unwrap();
+
+        assert_eq!(config.get_str("CONFIG_NOT_IN_ENV"), Some("1".to_owned()));
+        assert!(env::var("CONFIG_NOT_IN_ENV").is_err());
+    }
+}
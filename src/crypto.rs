@@ -0,0 +1,229 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use std::convert::TryFrom;
+use std::io::{Error as IoError, ErrorKind, Read};
+use super::Result;
+use utils;
+
+const NONCE_LEN: usize = 12;
+const PREFIX: &str = "enc:";
+
+/// Reads the content of a reader and parses it to find `.env` lines,
+/// decrypting values that are marked as encrypted.
+///
+/// A value is encrypted if it begins with `enc:`, followed by the hex-encoded
+/// 12-byte nonce and then the hex-encoded ciphertext, as produced by
+/// ChaCha20-Poly1305 with the given 32-byte key and the variable's name as the
+/// associated data. Binding the name means that an encrypted value can not be
+/// moved to another variable. Values without the prefix are used verbatim.
+///
+/// Every value is decrypted before any variable is set, so if one fails to
+/// decrypt then the environment is left untouched.
+///
+/// This requires the `crypto` feature to be enabled.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// use std::fs::File;
+///
+/// let key = std::env::var("ENV_KEY")?;
+/// let mut file = File::open(".env.encrypted")?;
+/// kankyo::load_encrypted_from_reader(&mut file, key.as_bytes(), false)?;
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidInput` if the key is not 32
+/// bytes long.
+///
+/// Returns an `std::io::Error` of kind `InvalidData` if an encrypted value is
+/// malformed or fails to decrypt, such as when the key is incorrect.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_encrypted_from_reader<R: Read>(
+    reader: &mut R,
+    key: &[u8],
+    overwrite: bool,
+) -> Result<()> {
+    let cipher = ChaCha20Poly1305::new_from_slice(key).map_err(|_| {
+        IoError::new(ErrorKind::InvalidInput, "key must be 32 bytes")
+    })?;
+
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let mut pairs = Vec::new();
+
+    for (key, value) in utils::parse_lines(&content) {
+        let value = match value.strip_prefix(PREFIX) {
+            Some(encrypted) => decrypt(&cipher, &key, encrypted)?,
+            None => value.to_owned(),
+        };
+
+        pairs.push((key, value));
+    }
+
+    let lines = pairs
+        .iter()
+        .map(|(k, v)| (&**k, v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+fn decrypt(
+    cipher: &ChaCha20Poly1305,
+    key: &str,
+    encrypted: &str,
+) -> Result<String> {
+    let invalid = || {
+        IoError::new(
+            ErrorKind::InvalidData,
+            format!("failed to decrypt value for {}", key),
+        )
+    };
+
+    let bytes = decode_hex(encrypted).ok_or_else(invalid)?;
+
+    if bytes.len() < NONCE_LEN {
+        return Err(invalid());
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let nonce = Nonce::try_from(nonce).map_err(|_| invalid())?;
+    let payload = Payload {
+        msg: ciphertext,
+        aad: key.as_bytes(),
+    };
+    let plaintext = cipher.decrypt(&nonce, payload).map_err(|_| invalid())?;
+
+    String::from_utf8(plaintext).map_err(|_| invalid())
+}
+
+// Decodes a hex string, returning `None` if it is not valid hex or has an odd
+// length.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let digit = |b: u8| char::from(b).to_digit(16);
+
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::Cursor;
+    use super::*;
+
+    fn encrypt(
+        key: &[u8],
+        nonce: &[u8],
+        name: &str,
+        plaintext: &str,
+    ) -> String {
+        let cipher = ChaCha20Poly1305::new_from_slice(key).unwrap();
+        let nonce = Nonce::try_from(nonce).unwrap();
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: name.as_bytes(),
+        };
+        let ciphertext = cipher.encrypt(&nonce, payload).unwrap();
+
+        nonce.iter()
+            .chain(&ciphertext)
+            .fold(PREFIX.to_owned(), |out, b| out + &format!("{:02x}", b))
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = [7u8; 32];
+        let content = format!(
+            "CRYPTO_SECRET={}\nCRYPTO_PLAIN=visible",
+            encrypt(&key, &[1; NONCE_LEN], "CRYPTO_SECRET", "hunter2"),
+        );
+
+        load_encrypted_from_reader(&mut Cursor::new(&content), &key, true)
+            .unwrap();
+        assert_eq!(env::var("CRYPTO_SECRET").unwrap(), "hunter2");
+        assert_eq!(env::var("CRYPTO_PLAIN").unwrap(), "visible");
+
+        utils::unload(&["CRYPTO_SECRET", "CRYPTO_PLAIN"]);
+    }
+
+    #[test]
+    fn wrong_key() {
+        let content = format!(
+            "CRYPTO_WRONG_SECRET={}\nCRYPTO_WRONG_PLAIN=visible",
+            encrypt(&[7; 32], &[2; NONCE_LEN], "CRYPTO_WRONG_SECRET", "pass"),
+        );
+
+        let mut cursor = Cursor::new(&content);
+        let err = load_encrypted_from_reader(&mut cursor, &[8; 32], true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(env::var("CRYPTO_WRONG_SECRET").is_err());
+        assert!(env::var("CRYPTO_WRONG_PLAIN").is_err());
+
+        let mut cursor = Cursor::new(&content);
+        let err = load_encrypted_from_reader(&mut cursor, &[7; 16], true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn wrong_name() {
+        let key = [7u8; 32];
+        let content = format!(
+            "CRYPTO_MOVED={}",
+            encrypt(&key, &[3; NONCE_LEN], "CRYPTO_ORIGINAL", "hunter2"),
+        );
+
+        let mut cursor = Cursor::new(&content);
+        let err = load_encrypted_from_reader(&mut cursor, &key, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(env::var("CRYPTO_MOVED").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        let key = [7u8; 32];
+
+        let values = &["enc:", "enc:abc", "enc:zz", "enc:00ff", "enc:+1+1"];
+
+        for value in values {
+            let content = format!("CRYPTO_MALFORMED={}", value);
+            let mut cursor = Cursor::new(&content);
+
+            assert!(load_encrypted_from_reader(&mut cursor, &key, true)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn decode_hex_digits() {
+        assert_eq!(decode_hex("00ffA0"), Some(vec![0x00, 0xff, 0xa0]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("+1"), None);
+        assert_eq!(decode_hex("-1"), None);
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("é0"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use error::Result;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use utils;
+
+type Decoder = Box<dyn Fn(&str) -> Result<String>>;
+
+/// A registry of value decoders, each applied to the keys matching a pattern,
+/// for use with [`load_decoded_from_reader`].
+///
+/// A pattern is a key where `*` matches any sequence of characters, such as
+/// `*_JSON` to match all keys ending in `_JSON`. A pattern without a `*` only
+/// matches the exact key.
+///
+/// If multiple patterns match a key, only the decoder of the first registered
+/// pattern is applied.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::Decoders;
+///
+/// let decoders = Decoders::new()
+///     .register("*_UPPER", |value| Ok(value.to_uppercase()));
+///
+/// assert_eq!(decoders.decode("NAME_UPPER", "abc").unwrap(), "ABC");
+/// assert_eq!(decoders.decode("NAME", "abc").unwrap(), "abc");
+/// ```
+///
+/// [`load_decoded_from_reader`]: fn.load_decoded_from_reader.html
+#[derive(Default)]
+pub struct Decoders {
+    decoders: Vec<(String, Decoder)>,
+}
+
+impl Decoders {
+    /// Creates a new registry with no decoders.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a decoder to be applied to the values of keys matching the
+    /// pattern.
+    ///
+    /// The decoder returns the decoded value, or an error if the value is
+    /// invalid.
+    pub fn register<F>(mut self, pattern: &str, decoder: F) -> Self
+        where F: Fn(&str) -> Result<String> + 'static {
+        self.decoders.push((pattern.to_owned(), Box::new(decoder)));
+
+        self
+    }
+
+    /// Registers [`utils::expand_tilde`] to be applied to the values of keys
+    /// matching the pattern, such as `*_DIR`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::Decoders;
+    /// # std::env::set_var("HOME", "/home/user");
+    ///
+    /// let decoders = Decoders::new().expand_tilde("*_DIR");
+    ///
+    /// assert_eq!(decoders.decode("DATA_DIR", "~/x").unwrap(), "/home/user/x");
+    /// assert_eq!(decoders.decode("NAME", "~/x").unwrap(), "~/x");
+    /// ```
+    ///
+    /// [`utils::expand_tilde`]: utils/fn.expand_tilde.html
+    pub fn expand_tilde(self, pattern: &str) -> Self {
+        self.register(pattern, |value| Ok(utils::expand_tilde(value)))
+    }
+
+    /// Decodes a value with the decoder of the first pattern matching the key.
+    ///
+    /// If no pattern matches, the value is returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the decoder if it fails.
+    pub fn decode(&self, key: &str, value: &str) -> Result<String> {
+        let decoder = self.decoders
+            .iter()
+            .find(|(pattern, _)| matches(pattern, key));
+
+        match decoder {
+            Some((_, decoder)) => decoder(value),
+            None => Ok(value.to_owned()),
+        }
+    }
+}
+
+impl Debug for Decoders {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_list()
+            .entries(self.decoders.iter().map(|(pattern, _)| pattern))
+            .finish()
+    }
+}
+
+// Returns whether the key matches the pattern, where `*` in the pattern
+// matches any sequence of characters.
+fn matches(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part.
+    let first = parts.next().unwrap_or("");
+
+    let mut rest = match key.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Error as IoError, ErrorKind};
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(matches("KEY", "KEY"));
+        assert!(!matches("KEY", "KEYS"));
+        assert!(matches("*_JSON", "A_JSON"));
+        assert!(matches("*_JSON", "_JSON"));
+        assert!(!matches("*_JSON", "A_JSON_B"));
+        assert!(matches("DB_*", "DB_HOST"));
+        assert!(matches("*", ""));
+        assert!(matches("A*B*C", "AxxBxxC"));
+        assert!(matches("A*B*C", "ABC"));
+        assert!(!matches("A*B*C", "AC"));
+        assert!(!matches("AB*BA", "ABA"));
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let decoders = Decoders::new()
+            .register("*_PATH", |value| Ok(format!("/root/{}", value)))
+            .register("*", |_| {
+                Err(IoError::new(ErrorKind::InvalidData, "rejected"))
+            });
+
+        assert_eq!(decoders.decode("A_PATH", "x").unwrap(), "/root/x");
+        assert!(decoders.decode("A", "x").is_err());
+        assert_eq!(format!("{:?}", decoders), r#"["*_PATH", "*"]"#);
+    }
+}
@@ -0,0 +1,253 @@
+use sink::LockedEnv;
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+use utils;
+
+/// Details of which variables a load set, as returned by
+/// [`load_from_reader_detailed`].
+///
+/// Each key is listed at most once, in the order that it was first loaded.
+///
+/// [`load_from_reader_detailed`]: fn.load_from_reader_detailed.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LoadDetails {
+    /// The keys that did not previously exist and were set.
+    pub created: Vec<String>,
+    /// The keys that previously existed and were overwritten.
+    pub overwritten: Vec<String>,
+    /// The keys that previously existed and were not overwritten, as
+    /// overwriting was disabled.
+    pub skipped: Vec<String>,
+}
+
+impl LoadDetails {
+    /// Loads the given parsed lines into the environment, recording which
+    /// variables were set.
+    pub(crate) fn apply<K: AsRef<str>>(
+        lines: &[(K, &str)],
+        overwrite: bool,
+    ) -> Self {
+        let locked = LockedEnv::new();
+        let mut details = LoadDetails::default();
+        // The keys that have been recorded in one of the lists.
+        let mut recorded = HashSet::new();
+
+        for &(ref key, value) in lines {
+            let key = key.as_ref();
+
+            if recorded.contains(key) {
+                if overwrite {
+                    locked.set_var(key, value);
+                }
+
+                continue;
+            }
+
+            let existed = env::var_os(key).is_some();
+
+            if existed && !overwrite {
+                details.skipped.push(key.to_owned());
+            } else if locked.set_var(key, value) {
+                if existed {
+                    details.overwritten.push(key.to_owned());
+                } else {
+                    details.created.push(key.to_owned());
+                }
+            } else {
+                continue;
+            }
+
+            recorded.insert(key);
+        }
+
+        details
+    }
+
+    /// Returns the number of variables that were set.
+    pub fn len(&self) -> usize {
+        self.created.len() + self.overwritten.len()
+    }
+
+    /// Returns whether no variables were set.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a human-readable summary of the load, such as
+    /// `"Loaded 12 variables from ./.env (3 overwrote existing)"`.
+    ///
+    /// Values are never included. If `include_keys` is `true`, each set key is
+    /// listed on its own line after the summary.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::LoadDetails;
+    ///
+    /// let details = LoadDetails {
+    ///     created: vec!["HOST".to_owned()],
+    ///     overwritten: vec!["PORT".to_owned()],
+    ///     skipped: vec![],
+    /// };
+    ///
+    /// assert_eq!(
+    ///     details.summary("./.env", true),
+    ///     "Loaded 2 variables from ./.env (1 overwrote existing)\n  \
+    ///      HOST\n  \
+    ///      PORT (overwritten)",
+    /// );
+    /// ```
+    pub fn summary(&self, source: &str, include_keys: bool) -> String {
+        let mut summary = format!(
+            "Loaded {} variable{} from {} ({} overwrote existing)",
+            self.len(),
+            if self.len() == 1 { "" } else { "s" },
+            source,
+            self.overwritten.len(),
+        );
+
+        if include_keys {
+            for key in &self.created {
+                summary.push_str("\n  ");
+                summary.push_str(key);
+            }
+
+            for key in &self.overwritten {
+                summary.push_str("\n  ");
+                summary.push_str(key);
+                summary.push_str(" (overwritten)");
+            }
+        }
+
+        summary
+    }
+}
+
+/// Statistics of a load, as returned by [`load_from_reader_report`].
+///
+/// [`load_from_reader_report`]: fn.load_from_reader_report.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LoadReport {
+    /// The number of lines that were read, including blank and comment lines.
+    pub lines_read: usize,
+    /// The number of lines whose variable was set.
+    pub keys_set: usize,
+    /// The number of lines defining a variable that was not set, either as the
+    /// variable already existed and overwriting was disabled, or as it was
+    /// invalid.
+    pub keys_skipped: usize,
+    /// The number of comment lines.
+    pub comments: usize,
+    /// The number of blank lines.
+    pub blanks: usize,
+    /// The time taken to read, parse, and load the content.
+    pub duration: Duration,
+}
+
+impl LoadReport {
+    /// Loads each line of the buffer into the environment, counting the kinds
+    /// of lines.
+    ///
+    /// The duration is left as zero for the caller to fill in.
+    pub(crate) fn apply(buf: &str, overwrite: bool) -> Self {
+        let locked = LockedEnv::new();
+        let mut report = LoadReport::default();
+
+        for line in buf.lines() {
+            report.lines_read += 1;
+
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                report.blanks += 1;
+            } else if trimmed.starts_with('#') {
+                report.comments += 1;
+            } else if let Some((key, value)) = utils::parse_line(line) {
+                let skip = !overwrite && env::var_os(&*key).is_some();
+
+                if !skip && locked.set_var(&key, value) {
+                    report.keys_set += 1;
+                } else {
+                    report.keys_skipped += 1;
+                }
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+
+    #[test]
+    fn apply() {
+        env::set_var("DETAILS_EXISTING", "1");
+        env::set_var("DETAILS_KEPT", "1");
+
+        let lines = [
+            ("DETAILS_NEW", "1"),
+            ("DETAILS_EXISTING", "2"),
+            ("DETAILS_NEW", "2"),
+        ];
+        let details = LoadDetails::apply(&lines, true);
+        assert_eq!(details.created, &["DETAILS_NEW"]);
+        assert_eq!(details.overwritten, &["DETAILS_EXISTING"]);
+        assert_eq!(env::var("DETAILS_NEW").unwrap(), "2");
+
+        let details = LoadDetails::apply(&[("DETAILS_KEPT", "2")], false);
+        assert_eq!(details.skipped, &["DETAILS_KEPT"]);
+        assert!(details.is_empty());
+        assert_eq!(env::var("DETAILS_KEPT").unwrap(), "1");
+
+        for key in &["DETAILS_NEW", "DETAILS_EXISTING", "DETAILS_KEPT"] {
+            env::remove_var(key);
+        }
+    }
+
+    #[test]
+    fn report() {
+        env::set_var("REPORT_EXISTING", "1");
+
+        let buf = "# header\n\nREPORT_NEW=1\n  \nREPORT_EXISTING=2\n\
+                   REPORT_NEW=3 # again\nnot a line\nREPORT\0NUL=1";
+        let report = LoadReport::apply(buf, false);
+
+        assert_eq!(report.lines_read, 8);
+        assert_eq!(report.comments, 1);
+        assert_eq!(report.blanks, 2);
+        assert_eq!(report.keys_set, 1);
+        assert_eq!(report.keys_skipped, 3);
+        assert_eq!(
+            report.keys_set + report.keys_skipped,
+            utils::parse_lines(buf).len(),
+        );
+        assert_eq!(env::var("REPORT_EXISTING").unwrap(), "1");
+        assert_eq!(env::var("REPORT_NEW").unwrap(), "1");
+
+        for key in &["REPORT_NEW", "REPORT_EXISTING"] {
+            env::remove_var(key);
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let details = LoadDetails {
+            created: vec!["A".to_owned()],
+            overwritten: vec![],
+            skipped: vec!["B".to_owned()],
+        };
+
+        assert_eq!(
+            details.summary("x.env", false),
+            "Loaded 1 variable from x.env (0 overwrote existing)",
+        );
+        assert_eq!(
+            details.summary("x.env", true),
+            "Loaded 1 variable from x.env (0 overwrote existing)\n  A",
+        );
+    }
+}
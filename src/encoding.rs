@@ -0,0 +1,91 @@
+use encoding_rs::Encoding;
+use std::io::{Error as IoError, ErrorKind, Read};
+use super::Result;
+use utils;
+
+/// Reads the content of a reader, decoding it with the given encoding, and
+/// parses it to find `.env` lines.
+///
+/// This allows loading files in legacy encodings, such as Latin-1 or
+/// Shift_JIS. The encoding is used as given: a byte order mark is not used to
+/// detect a different encoding.
+///
+/// This requires the `encoding` feature to be enabled.
+///
+/// # Examples
+///
+/// Load a file encoded in Shift_JIS:
+///
+/// ```rust,no_run
+/// # extern crate encoding_rs;
+/// # extern crate kankyo;
+/// #
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// use std::fs::File;
+///
+/// let mut file = File::open(".env")?;
+/// kankyo::load_from_reader_encoded(&mut file, encoding_rs::SHIFT_JIS, false)?;
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidData` if the content is not
+/// valid in the given encoding. Nothing is loaded in this case.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader_encoded<R: Read>(
+    reader: &mut R,
+    encoding: &'static Encoding,
+    overwrite: bool,
+) -> Result<()> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let content = encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .ok_or_else(|| IoError::new(
+            ErrorKind::InvalidData,
+            format!("content is not valid {}", encoding.name()),
+        ))?;
+
+    utils::set_variables(&utils::parse_lines(&content), overwrite);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+    use std::env;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn latin1() {
+        let buf = b"ENCODED_NAME=Jos\xe9 # caf\xe9".to_vec();
+        let mut cursor = Cursor::new(buf);
+        load_from_reader_encoded(&mut cursor, WINDOWS_1252, true).unwrap();
+
+        assert_eq!(env::var("ENCODED_NAME").unwrap(), "José");
+
+        utils::unload(&["ENCODED_NAME"]);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut cursor = Cursor::new(b"ENCODED_INVALID=\x82".to_vec());
+        let err = load_from_reader_encoded(&mut cursor, SHIFT_JIS, true)
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(env::var("ENCODED_INVALID").is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use error::ParseError;
+use parser::{Directive, Parser};
+use std::borrow::Cow;
+use std::result::Result as StdResult;
+use utils;
+
+/// A variable defined by a line of a .env file, as returned by
+/// [`entries_from_reader`].
+///
+/// Unlike a [`ParsedLine`], this owns its key and value, and records where and
+/// how the variable was defined.
+///
+/// [`ParsedLine`]: utils/type.ParsedLine.html
+/// [`entries_from_reader`]: fn.entries_from_reader.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// The key of the variable.
+    pub key: String,
+    /// The value of the variable, without any surrounding quotes.
+    pub value: String,
+    /// The 1-indexed number of the line that the variable was defined on.
+    pub line: usize,
+    /// Whether the value was surrounded by quotes.
+    pub quoted: bool,
+}
+
+impl Entry {
+    /// Parses each line of a buffer into entries, skipping lines that do not
+    /// define a variable.
+    pub(crate) fn parse_all(buf: &str) -> StdResult<Vec<Self>, ParseError> {
+        let parser = Parser::new().quotes(true);
+        let mut entries = Vec::new();
+
+        for (idx, line) in buf.lines().enumerate() {
+            let (pair, quoted) = match parser.parse_quoted(line) {
+                Ok(Some(Directive::Set(key, value))) => {
+                    (Some((key, value)), true)
+                },
+                Ok(_) => {
+                    let pair = utils::parse_line(line)
+                        .map(|(key, value)| (key, Cow::Borrowed(value)));
+
+                    (pair, false)
+                },
+                Err(kind) => return Err(ParseError {
+                    kind,
+                    line: idx + 1,
+                }),
+            };
+
+            if let Some((key, value)) = pair {
+                entries.push(Entry {
+                    key: key.into_owned(),
+                    value: value.into_owned(),
+                    line: idx + 1,
+                    quoted,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+}
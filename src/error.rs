@@ -1,5 +1,267 @@
-use std::io::Error as IoError;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
 use std::result::Result as StdResult;
 
 /// Common result type throughout the library.
 pub type Result<T> = StdResult<T, IoError>;
+
+/// An error returned by [`set_if_matches`] when a variable could not be set.
+///
+/// [`set_if_matches`]: fn.set_if_matches.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CasError {
+    /// The current value of the variable does not match the expected value.
+    Mismatch {
+        /// The actual current value of the variable, or `None` if it does not
+        /// exist.
+        actual: Option<String>,
+    },
+    /// The key or the new value can not be set in the environment, such as
+    /// because it contains a NUL byte.
+    Invalid,
+}
+
+impl Display for CasError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            CasError::Mismatch { actual: Some(ref actual) } => {
+                write!(f, "unexpected existing value: {}", actual)
+            },
+            CasError::Mismatch { actual: None } => {
+                f.write_str("variable does not exist")
+            },
+            CasError::Invalid => f.write_str("invalid variable"),
+        }
+    }
+}
+
+impl StdError for CasError {}
+
+/// An error returned by the typed getters of a [`Config`] when a value could
+/// not be parsed into the requested type.
+///
+/// [`Config`]: struct.Config.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigError {
+    /// The key whose value could not be parsed.
+    pub key: String,
+    /// The value that could not be parsed.
+    pub value: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "invalid value for {}: {}", self.key, self.value)
+    }
+}
+
+impl StdError for ConfigError {}
+
+impl From<ConfigError> for IoError {
+    fn from(err: ConfigError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// An error returned when a variable references itself, either directly or
+/// through other variables.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CycleError {
+    /// The name of a variable in the cycle.
+    pub key: String,
+}
+
+impl Display for CycleError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "cyclic reference to {}", self.key)
+    }
+}
+
+impl StdError for CycleError {}
+
+impl From<CycleError> for IoError {
+    fn from(err: CycleError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// An error returned when a file contains keys that are not in an allowlist.
+///
+/// Refer to [`load_strict_allowlist_from_reader`].
+///
+/// [`load_strict_allowlist_from_reader`]: fn.load_strict_allowlist_from_reader.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DisallowedKeysError {
+    /// The keys that are not in the allowlist, each once, in the order they
+    /// first appear.
+    pub keys: Vec<String>,
+}
+
+impl Display for DisallowedKeysError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "disallowed keys: {}", self.keys.join(", "))
+    }
+}
+
+impl StdError for DisallowedKeysError {}
+
+impl From<DisallowedKeysError> for IoError {
+    fn from(err: DisallowedKeysError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// An error returned when a line could not be parsed by a [`Parser`].
+///
+/// [`Parser`]: struct.Parser.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    /// The kind of error that occurred.
+    pub kind: ParseErrorKind,
+    /// The 1-indexed number of the line that the error occurred on.
+    pub line: usize,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl StdError for ParseError {}
+
+impl From<ParseError> for IoError {
+    fn from(err: ParseError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// An error returned by [`Config::validate`] for a key that does not satisfy
+/// a [`Schema`].
+///
+/// [`Config::validate`]: struct.Config.html#method.validate
+/// [`Schema`]: struct.Schema.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SchemaError {
+    /// A required key does not exist.
+    Missing(String),
+    /// A key that is required to be non-empty exists, but its value is empty.
+    Empty(String),
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            SchemaError::Missing(ref key) => {
+                write!(f, "missing required key {}", key)
+            },
+            SchemaError::Empty(ref key) => {
+                write!(f, "required key {} is empty", key)
+            },
+        }
+    }
+}
+
+impl StdError for SchemaError {}
+
+impl From<SchemaError> for IoError {
+    fn from(err: SchemaError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// An error that occurred while opening or reading a file or directory, along
+/// with its path.
+///
+/// Functions that read a file or directory by path return an `std::io::Error`
+/// wrapping this error, with the same kind as the underlying error, so that
+/// the message includes the path, such as
+/// `failed to read ./config/.env: No such file or directory`.
+#[derive(Debug)]
+pub struct PathError {
+    /// The path of the file or directory that could not be read.
+    pub path: PathBuf,
+    /// The underlying error.
+    pub source: IoError,
+}
+
+impl PathError {
+    /// Wraps an error that occurred while reading the file at the path.
+    pub(crate) fn wrap<P: AsRef<Path>>(path: P, source: IoError) -> IoError {
+        PathError {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }.into()
+    }
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "failed to read {}: {}", self.path.display(), self.source)
+    }
+}
+
+impl StdError for PathError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<PathError> for IoError {
+    fn from(err: PathError) -> IoError {
+        IoError::new(err.source.kind(), err)
+    }
+}
+
+/// An error returned when a value references a variable that it may not
+/// resolve, such as one not defined in the same file.
+///
+/// Refer to [`interpolate::resolve_local`].
+///
+/// [`interpolate::resolve_local`]: interpolate/fn.resolve_local.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnresolvedError {
+    /// The key whose value contains the reference.
+    pub key: String,
+    /// The name of the referenced variable.
+    pub name: String,
+}
+
+impl Display for UnresolvedError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{} references undefined {}", self.key, self.name)
+    }
+}
+
+impl StdError for UnresolvedError {}
+
+impl From<UnresolvedError> for IoError {
+    fn from(err: UnresolvedError) -> IoError {
+        IoError::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// The kind of a [`ParseError`].
+///
+/// [`ParseError`]: struct.ParseError.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    /// A quoted value was followed by characters other than whitespace or a
+    /// comment.
+    TrailingCharacters,
+    /// A quoted value did not have a closing quote.
+    UnterminatedQuote,
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(match *self {
+            ParseErrorKind::TrailingCharacters => {
+                "unexpected characters after closing quote"
+            },
+            ParseErrorKind::UnterminatedQuote => "unterminated quote",
+        })
+    }
+}
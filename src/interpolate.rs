@@ -0,0 +1,477 @@
+//! Functions for expanding references to other variables within values.
+//!
+//! A reference is either of the form `${NAME}` or `$NAME`, where the name
+//! consists of ASCII letters, digits, and underscores, and does not begin with
+//! a digit. A `$` that does not begin a reference is kept as-is.
+//!
+//! There are two ways of resolving the references of a parsed file:
+//!
+//! - [`resolve`], the default, resolves each line in a single pass, in order.
+//!   A reference can only refer to a key defined on an _earlier_ line, or to a
+//!   variable in the environment;
+//! - [`resolve_two_pass`] first collects every key in the file, and so a
+//!   reference can also refer to a key defined on a _later_ line.
+//!
+//! To keep a file self-contained, [`resolve_local`] resolves references like
+//! [`resolve`], but never from the environment.
+//!
+//! [`resolve`]: fn.resolve.html
+//! [`resolve_local`]: fn.resolve_local.html
+//! [`resolve_two_pass`]: fn.resolve_two_pass.html
+
+use error::{CycleError, UnresolvedError};
+use std::collections::HashMap;
+use std::env;
+use std::result::Result as StdResult;
+
+/// Expands the references in a value, looking up each referenced name via the
+/// given function.
+///
+/// A reference to a name that the function returns `None` for is expanded to
+/// an empty string.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::interpolate;
+///
+/// let value = interpolate::expand("${HOST}:$PORT/$", |name| match name {
+///     "HOST" => Some("localhost".to_owned()),
+///     _ => None,
+/// });
+///
+/// assert_eq!(value, "localhost:/$");
+/// ```
+pub fn expand<F>(value: &str, mut lookup: F) -> String
+    where F: FnMut(&str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        match parse_reference(after) {
+            Some((name, len)) => {
+                if let Some(value) = lookup(name) {
+                    out.push_str(&value);
+                }
+
+                rest = &after[len..];
+            },
+            None => {
+                out.push('$');
+                rest = after;
+            },
+        }
+    }
+
+    out.push_str(rest);
+
+    out
+}
+
+/// Returns the names referenced by a value, in order.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::interpolate;
+///
+/// assert_eq!(
+///     interpolate::references("${HOST}:$PORT/$1"),
+///     vec!["HOST", "PORT"],
+/// );
+/// ```
+pub fn references(value: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('$') {
+        rest = &rest[pos + 1..];
+
+        if let Some((name, len)) = parse_reference(rest) {
+            names.push(name);
+            rest = &rest[len..];
+        }
+    }
+
+    names
+}
+
+/// Resolves the references in each of the given lines in a single pass.
+///
+/// Lines are resolved in order. A reference resolves to the value of the
+/// closest earlier line with that key, falling back to the environment.
+/// References to keys defined on later lines are _not_ resolved from those
+/// lines.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{interpolate, utils};
+///
+/// let lines = utils::parse_lines("HOST=localhost\nURL=http://${HOST}");
+/// let resolved = interpolate::resolve(&lines);
+///
+/// assert_eq!(resolved[1].1, "http://localhost");
+/// ```
+pub fn resolve<K: AsRef<str>>(lines: &[(K, &str)]) -> Vec<(String, String)> {
+    let mut seen: HashMap<&str, String> = HashMap::new();
+    let mut resolved = Vec::with_capacity(lines.len());
+
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        let value = expand(value, |name| {
+            seen.get(name).cloned().or_else(|| env::var(name).ok())
+        });
+
+        seen.insert(key, value.clone());
+        resolved.push((key.to_owned(), value));
+    }
+
+    resolved
+}
+
+/// Resolves the references in each of the given lines in a single pass, only
+/// from keys defined on earlier lines.
+///
+/// This is like [`resolve`], but a reference never falls back to the
+/// environment, so that the result does not depend on the shell that the file
+/// is loaded from. If `strict` is `false`, a reference to a key not defined on
+/// an earlier line is expanded to an empty string.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{interpolate, utils};
+/// use std::env;
+///
+/// env::set_var("LOCAL_EXAMPLE_ENV", "env");
+///
+/// let lines = utils::parse_lines("A=1\nB=$A-$LOCAL_EXAMPLE_ENV");
+/// let resolved = interpolate::resolve_local(&lines, false).unwrap();
+/// assert_eq!(resolved[1].1, "1-");
+///
+/// let err = interpolate::resolve_local(&lines, true).unwrap_err();
+/// assert_eq!(err.name, "LOCAL_EXAMPLE_ENV");
+/// ```
+///
+/// # Errors
+///
+/// Returns an [`UnresolvedError`] for the first reference to a key not defined
+/// on an earlier line if `strict` is `true`.
+///
+/// [`UnresolvedError`]: ../struct.UnresolvedError.html
+/// [`resolve`]: fn.resolve.html
+pub fn resolve_local<K: AsRef<str>>(
+    lines: &[(K, &str)],
+    strict: bool,
+) -> StdResult<Vec<(String, String)>, UnresolvedError> {
+    let mut seen: HashMap<&str, String> = HashMap::new();
+    let mut resolved = Vec::with_capacity(lines.len());
+
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        let mut missing = None;
+
+        let value = expand(value, |name| {
+            let value = seen.get(name).cloned();
+
+            if value.is_none() && missing.is_none() {
+                missing = Some(name.to_owned());
+            }
+
+            value
+        });
+
+        if let (true, Some(name)) = (strict, missing) {
+            return Err(UnresolvedError {
+                key: key.to_owned(),
+                name,
+            });
+        }
+
+        seen.insert(key, value.clone());
+        resolved.push((key.to_owned(), value));
+    }
+
+    Ok(resolved)
+}
+
+/// Resolves the references in each of the given lines, where a reference may
+/// refer to a key defined on any line.
+///
+/// Unlike [`resolve`], this first collects every key in the lines, so the
+/// order of the lines does not matter. If a key is defined on multiple lines,
+/// references to it resolve to its _last_ definition. References to keys not
+/// defined in the lines fall back to the environment.
+///
+/// A key that references itself, as in `PATH=$PATH:/bin`, refers to its
+/// previous definition in the lines, or else to the environment.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{interpolate, utils};
+///
+/// let lines = utils::parse_lines("URL=http://${HOST}\nHOST=localhost");
+/// let resolved = interpolate::resolve_two_pass(&lines).unwrap();
+///
+/// assert_eq!(resolved[0].1, "http://localhost");
+/// ```
+///
+/// # Errors
+///
+/// Returns a [`CycleError`] if a key references itself through other keys.
+///
+/// [`CycleError`]: ../struct.CycleError.html
+/// [`resolve`]: fn.resolve.html
+pub fn resolve_two_pass<K: AsRef<str>>(
+    lines: &[(K, &str)],
+) -> StdResult<Vec<(String, String)>, CycleError> {
+    let lines = lines
+        .iter()
+        .map(|&(ref k, v)| (k.as_ref(), v))
+        .collect::<Vec<_>>();
+    let mut last = HashMap::new();
+    let mut previous = Vec::with_capacity(lines.len());
+
+    for (index, &(key, _)) in lines.iter().enumerate() {
+        previous.push(last.insert(key, index));
+    }
+
+    let mut state = TwoPass {
+        resolved: vec![None; lines.len()],
+        lines,
+        last,
+        previous,
+        stack: Vec::new(),
+    };
+    let mut resolved = Vec::with_capacity(state.lines.len());
+
+    for index in 0..state.lines.len() {
+        let value = state.resolve_line(index)?;
+
+        resolved.push((state.lines[index].0.to_owned(), value));
+    }
+
+    Ok(resolved)
+}
+
+struct TwoPass<'a> {
+    lines: Vec<(&'a str, &'a str)>,
+    // The index of the last line defining each key.
+    last: HashMap<&'a str, usize>,
+    // The index of the previous line defining the key of each line, if any.
+    previous: Vec<Option<usize>>,
+    resolved: Vec<Option<String>>,
+    // The lines currently being resolved, used to detect cycles.
+    stack: Vec<usize>,
+}
+
+impl<'a> TwoPass<'a> {
+    fn resolve_line(&mut self, index: usize) -> StdResult<String, CycleError> {
+        if let Some(ref value) = self.resolved[index] {
+            return Ok(value.clone());
+        }
+
+        let (key, raw) = self.lines[index];
+
+        if self.stack.contains(&index) {
+            return Err(CycleError {
+                key: key.to_owned(),
+            });
+        }
+
+        let mut err = None;
+
+        self.stack.push(index);
+        let value = expand(raw, |name| {
+            if err.is_some() {
+                return None;
+            }
+
+            self.resolve_name(index, name).unwrap_or_else(|why| {
+                err = Some(why);
+
+                None
+            })
+        });
+        self.stack.pop();
+
+        if let Some(err) = err {
+            return Err(err);
+        }
+
+        self.resolved[index] = Some(value.clone());
+
+        Ok(value)
+    }
+
+    // Resolves a reference to a name from the line at the given index.
+    fn resolve_name(
+        &mut self,
+        index: usize,
+        name: &str,
+    ) -> StdResult<Option<String>, CycleError> {
+        // A key referencing itself refers to its previous definition.
+        let line = if name == self.lines[index].0 {
+            self.previous[index]
+        } else {
+            self.last.get(name).cloned()
+        };
+
+        match line {
+            Some(line) => self.resolve_line(line).map(Some),
+            None => Ok(env::var(name).ok()),
+        }
+    }
+}
+
+// Parses a reference following a `$`, returning the name and the number of
+// bytes that the reference spans.
+fn parse_reference(s: &str) -> Option<(&str, usize)> {
+    let (name, len) = if let Some(braced) = s.strip_prefix('{') {
+        let end = braced.find('}')?;
+
+        (&braced[..end], end + 2)
+    } else {
+        let end = s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(s.len());
+
+        (&s[..end], end)
+    };
+
+    if is_name(name) {
+        Some((name, len))
+    } else {
+        None
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    match name.bytes().next() {
+        Some(first) if !first.is_ascii_digit() => {
+            name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+    use utils;
+
+    #[test]
+    fn expand_syntax() {
+        let lookup = |name: &str| Some(format!("<{}>", name));
+
+        assert_eq!(expand("${A}", lookup), "<A>");
+        assert_eq!(expand("$A", lookup), "<A>");
+        assert_eq!(expand("x${A_1}y$B-z", lookup), "x<A_1>y<B>-z");
+        assert_eq!(expand("$", lookup), "$");
+        assert_eq!(expand("$$A", lookup), "$<A>");
+        assert_eq!(expand("$1", lookup), "$1");
+        assert_eq!(expand("${A", lookup), "${A");
+        assert_eq!(expand("${}", lookup), "${}");
+        assert_eq!(expand("${A B}", lookup), "${A B}");
+        assert_eq!(expand("é$Aé", lookup), "é<A>é");
+    }
+
+    #[test]
+    fn resolve_single_pass() {
+        env::set_var("INTERPOLATE_ENV", "env");
+
+        let lines = utils::parse_lines(
+            "A=${INTERPOLATE_SINGLE_B}\nINTERPOLATE_SINGLE_B=2\n\
+             C=${INTERPOLATE_SINGLE_B}${INTERPOLATE_ENV}\n\
+             INTERPOLATE_SINGLE_B=3\nD=$INTERPOLATE_SINGLE_B",
+        );
+        let resolved = resolve(&lines);
+
+        assert_eq!(resolved[0], ("A".to_owned(), "".to_owned()));
+        assert_eq!(resolved[2], ("C".to_owned(), "2env".to_owned()));
+        assert_eq!(resolved[4], ("D".to_owned(), "3".to_owned()));
+    }
+
+    #[test]
+    fn resolve_local() {
+        env::set_var("INTERPOLATE_LOCAL_ENV", "env");
+
+        let lines = utils::parse_lines(
+            "A=${B}\nB=2\nC=${B}${INTERPOLATE_LOCAL_ENV}\nD=$C",
+        );
+        let resolved = super::resolve_local(&lines, false).unwrap();
+
+        assert_eq!(resolved[0], ("A".to_owned(), "".to_owned()));
+        assert_eq!(resolved[2], ("C".to_owned(), "2".to_owned()));
+        assert_eq!(resolved[3], ("D".to_owned(), "2".to_owned()));
+
+        let err = super::resolve_local(&lines[1..], true).unwrap_err();
+        assert_eq!(err.key, "C");
+        assert_eq!(err.name, "INTERPOLATE_LOCAL_ENV");
+
+        let lines = utils::parse_lines("A=1\nB=${A}$$");
+        let resolved = super::resolve_local(&lines, true).unwrap();
+        assert_eq!(resolved[1].1, "1$$");
+    }
+
+    #[test]
+    fn resolve_two_pass_forward_reference() {
+        let lines = utils::parse_lines("A=${B}\nB=2");
+        let resolved = resolve_two_pass(&lines).unwrap();
+
+        assert_eq!(resolved[0], ("A".to_owned(), "2".to_owned()));
+        assert_eq!(resolved[1], ("B".to_owned(), "2".to_owned()));
+    }
+
+    #[test]
+    fn resolve_two_pass_chain() {
+        env::set_var("INTERPOLATE_TWO_PASS_ENV", "env");
+
+        let lines = utils::parse_lines(
+            "A=${B}-${C}\nB=${C}\nC=${INTERPOLATE_TWO_PASS_ENV}",
+        );
+        let resolved = resolve_two_pass(&lines).unwrap();
+
+        assert_eq!(resolved[0].1, "env-env");
+        assert_eq!(resolved[1].1, "env");
+    }
+
+    #[test]
+    fn resolve_two_pass_cycle() {
+        let lines = utils::parse_lines("A=${B}\nB=${C}\nC=$A");
+        assert!(resolve_two_pass(&lines).is_err());
+
+        let lines = utils::parse_lines("A=${B}\nB=$A");
+        let err = resolve_two_pass(&lines).unwrap_err();
+        assert_eq!(err.key, "A");
+    }
+
+    #[test]
+    fn resolve_two_pass_self_reference() {
+        env::set_var("INTERPOLATE_TWO_PASS_SELF", "env");
+
+        let lines = utils::parse_lines(
+            "INTERPOLATE_TWO_PASS_SELF=$INTERPOLATE_TWO_PASS_SELF:a\n\
+             B=$A\nA=1\nA=${A}2\nA=$A-$INTERPOLATE_TWO_PASS_SELF",
+        );
+        let resolved = resolve_two_pass(&lines).unwrap();
+
+        assert_eq!(resolved[0].1, "env:a");
+        assert_eq!(resolved[1].1, "12-env:a");
+        assert_eq!(resolved[3].1, "12");
+        assert_eq!(resolved[4].1, "12-env:a");
+
+        let lines = utils::parse_lines(
+            "INTERPOLATE_TWO_PASS_UNSET=${INTERPOLATE_TWO_PASS_UNSET}x",
+        );
+        assert_eq!(resolve_two_pass(&lines).unwrap()[0].1, "x");
+    }
+}
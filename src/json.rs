@@ -0,0 +1,130 @@
+use serde_json::{self, Map, Value};
+use std::io::{Error as IoError, ErrorKind, Read};
+use super::Result;
+use utils;
+
+/// Reads a JSON object from a reader and loads its values into the
+/// environment.
+///
+/// The keys of the object are used as the variable names. Strings are used
+/// verbatim, while numbers and booleans are converted to their string form.
+/// `null` values are skipped.
+///
+/// Nested objects are flattened by joining the keys with an underscore (`_`),
+/// so that `{"DB": {"HOST": "localhost"}}` loads the variable `DB_HOST` with
+/// the value `localhost`. This applies recursively to any level of nesting.
+///
+/// This requires the `json` feature to be enabled.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new(r#"{"JSON_DOC": {"PORT": 8080}}"#);
+/// kankyo::load_from_json_reader(&mut cursor, true).unwrap();
+///
+/// assert_eq!(kankyo::key("JSON_DOC_PORT").unwrap(), "8080");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader,
+/// if the content is not valid JSON, if the top-level value is not an object,
+/// or if a value is an array.
+pub fn load_from_json_reader<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    let value: Value = serde_json::from_reader(reader)?;
+
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err(invalid_data("expected a JSON object")),
+    };
+
+    let mut pairs = Vec::with_capacity(object.len());
+    flatten(None, object, &mut pairs)?;
+
+    let lines = pairs
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+fn flatten(
+    prefix: Option<&str>,
+    object: Map<String, Value>,
+    pairs: &mut Vec<(String, String)>,
+) -> Result<()> {
+    for (key, value) in object {
+        let key = match prefix {
+            Some(prefix) => format!("{}_{}", prefix, key),
+            None => key,
+        };
+
+        match value {
+            Value::Null => {},
+            Value::Bool(v) => pairs.push((key, v.to_string())),
+            Value::Number(v) => pairs.push((key, v.to_string())),
+            Value::String(v) => pairs.push((key, v)),
+            Value::Object(v) => flatten(Some(&key), v, pairs)?,
+            Value::Array(_) => {
+                return Err(invalid_data(&format!("array value for {}", key)));
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid_data(msg: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn flat() {
+        let mut cursor = Cursor::new(r#"{
+            "JSON_FLAT_STR": "a",
+            "JSON_FLAT_NUM": 1.5,
+            "JSON_FLAT_BOOL": true,
+            "JSON_FLAT_NULL": null
+        }"#);
+        load_from_json_reader(&mut cursor, true).unwrap();
+
+        assert_eq!(env::var("JSON_FLAT_STR").unwrap(), "a");
+        assert_eq!(env::var("JSON_FLAT_NUM").unwrap(), "1.5");
+        assert_eq!(env::var("JSON_FLAT_BOOL").unwrap(), "true");
+        assert!(env::var("JSON_FLAT_NULL").is_err());
+    }
+
+    #[test]
+    fn nested() {
+        let mut cursor = Cursor::new(r#"{
+            "JSON_NESTED": {"HOST": "localhost", "PORT": 5432}
+        }"#);
+        load_from_json_reader(&mut cursor, true).unwrap();
+
+        assert_eq!(env::var("JSON_NESTED_HOST").unwrap(), "localhost");
+        assert_eq!(env::var("JSON_NESTED_PORT").unwrap(), "5432");
+        assert!(env::var("JSON_NESTED").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        let mut array = Cursor::new(r#"{"JSON_ARRAY": [1, 2]}"#);
+        assert!(load_from_json_reader(&mut array, true).is_err());
+
+        let mut top_level = Cursor::new("[1, 2]");
+        assert!(load_from_json_reader(&mut top_level, true).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use keyring::Entry;
+use std::fmt::Display;
+use std::result::Result as StdResult;
+use std::io::{Error as IoError, ErrorKind, Read};
+use super::Result;
+use utils;
+
+const PREFIX: &str = "keyring:";
+
+/// Reads the content of a reader and parses it to find `.env` lines, fetching
+/// values that reference a secret from the system keyring.
+///
+/// A value references a secret if it is of the form `keyring:service/account`,
+/// in which case the secret stored for that service and account is used as
+/// the value. Other values are used verbatim.
+///
+/// Every secret is fetched before any variable is set, so if one can not be
+/// fetched then the environment is left untouched.
+///
+/// This requires the `keyring` feature to be enabled. No keyring backend is
+/// enabled by default: enable the platform backend that you need, such as the
+/// `apple-native` or `windows-native` feature, on the `keyring` crate in your
+/// own `Cargo.toml`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("DB_PASSWORD=keyring:my-app/db");
+/// kankyo::load_keyring_from_reader(&mut cursor, false)?;
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidData` if a reference is not of
+/// the form `keyring:service/account`.
+///
+/// Returns an `std::io::Error` naming the key if its secret could not be
+/// fetched from the keyring.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_keyring_from_reader<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    load_with_lookup(reader, overwrite, |service, account| {
+        Entry::new(service, account)?.get_password()
+    })
+}
+
+// Loads from a reader, fetching referenced secrets via the given function.
+//
+// This is separate from `load_keyring_from_reader` so that the lookup can be
+// mocked.
+fn load_with_lookup<R, F, E>(
+    reader: &mut R,
+    overwrite: bool,
+    mut lookup: F,
+) -> Result<()>
+    where R: Read, F: FnMut(&str, &str) -> StdResult<String, E>, E: Display {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let mut pairs = Vec::new();
+
+    for (key, value) in utils::parse_lines(&content) {
+        let value = match parse_reference(&key, value)? {
+            Some((service, account)) => {
+                lookup(service, account).map_err(|why| {
+                    IoError::new(ErrorKind::Other, format!(
+                        "failed to fetch keyring secret for {}: {}",
+                        key,
+                        why,
+                    ))
+                })?
+            },
+            None => value.to_owned(),
+        };
+
+        pairs.push((key, value));
+    }
+
+    let lines = pairs
+        .iter()
+        .map(|(k, v)| (&**k, v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+// Parses a value as a keyring reference into its service and account,
+// returning `None` if the value is not a reference.
+fn parse_reference<'a>(
+    key: &str,
+    value: &'a str,
+) -> Result<Option<(&'a str, &'a str)>> {
+    let reference = match value.strip_prefix(PREFIX) {
+        Some(reference) => reference,
+        None => return Ok(None),
+    };
+
+    match reference.find('/') {
+        Some(pos) if pos > 0 && pos + 1 < reference.len() => {
+            Ok(Some((&reference[..pos], &reference[pos + 1..])))
+        },
+        _ => Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("invalid keyring reference for {}", key),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn references() {
+        assert_eq!(parse_reference("K", "plain").unwrap(), None);
+        assert_eq!(
+            parse_reference("K", "keyring:app/db").unwrap(),
+            Some(("app", "db")),
+        );
+        assert_eq!(
+            parse_reference("K", "keyring:app/db/primary").unwrap(),
+            Some(("app", "db/primary")),
+        );
+
+        let invalid = ["keyring:", "keyring:app", "keyring:/db", "keyring:app/"];
+
+        for value in &invalid {
+            assert!(parse_reference("K", value).is_err());
+        }
+    }
+
+    #[test]
+    fn mocked_lookup() {
+        let lookup = |service: &str, account: &str| match (service, account) {
+            ("app", "db") => Ok("hunter2".to_owned()),
+            _ => Err("no entry"),
+        };
+
+        let content = "KEYRING_SECRET=keyring:app/db\nKEYRING_PLAIN=visible";
+        load_with_lookup(&mut Cursor::new(content), true, lookup).unwrap();
+        assert_eq!(env::var("KEYRING_SECRET").unwrap(), "hunter2");
+        assert_eq!(env::var("KEYRING_PLAIN").unwrap(), "visible");
+
+        let content = "KEYRING_OTHER=1\nKEYRING_MISSING=keyring:app/missing";
+        let err = load_with_lookup(&mut Cursor::new(content), true, lookup)
+            .unwrap_err();
+        assert!(err.to_string().contains("KEYRING_MISSING"));
+        assert!(env::var("KEYRING_OTHER").is_err());
+
+        utils::unload(&["KEYRING_SECRET", "KEYRING_PLAIN"]);
+    }
+}
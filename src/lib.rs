@@ -11,7 +11,10 @@
 //!
 //! ### Installation
 //!
-//! This library requires at least Rust 1.0.0.
+//! This library requires at least Rust 1.63.0. Some optional features depend
+//! on crates that require a newer version: with their latest releases, the
+//! `crypto`, `http`, and `toml` features require Rust 1.85.0, and the
+//! `encoding` feature requires Rust 1.88.0.
 //!
 //! Add the following dependency to your project's `Cargo.toml`:
 //!
@@ -80,6 +83,20 @@
 //! # }
 //! ```
 //!
+//! ### Thread safety
+//!
+//! Every change that the library makes to the environment is serialized by an
+//! internal lock, so concurrent calls to functions such as [`load`] and
+//! [`unload`] from multiple threads are safe with respect to each other. A
+//! load holds the lock while checking and setting all of its variables, so a
+//! load that does not overwrite never overwrites a variable set by another
+//! load running at the same time.
+//!
+//! The lock can not cover changes made outside of the library, such as by
+//! calling `std::env::set_var` directly, which may still race with a load.
+//!
+//! [`load`]: fn.load.html
+//! [`unload`]: fn.unload.html
 //! [ci]: https://travis-ci.org/rusty-crates/kankyo
 //! [ci-badge]: https://img.shields.io/travis/rusty-crates/kankyo.svg?style=flat-square
 //! [docs]: https://docs.rs/kankyo
@@ -90,17 +107,160 @@
 //! [rust link]: https://blog.rust-lang.org/2015/05/15/Rust-1.0.html
 #![deny(missing_docs)]
 
+#[cfg(feature = "crypto")]
+extern crate chacha20poly1305;
+#[cfg(feature = "encoding")]
+extern crate encoding_rs;
+#[cfg(feature = "keyring")]
+extern crate keyring;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "http")]
+extern crate ureq;
+
+#[macro_use]
+mod macros;
+
+pub mod interpolate;
 pub mod utils;
 
+mod config;
+#[cfg(feature = "crypto")]
+mod crypto;
+mod decoders;
+mod details;
+#[cfg(feature = "encoding")]
+mod encoding;
+mod entry;
 mod error;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "keyring")]
+mod keyring_store;
+mod ordered;
+mod parser;
+mod sink;
+#[cfg(feature = "tokio")]
+mod tokio_fs;
+#[cfg(feature = "toml")]
+mod toml_table;
+mod transaction;
+mod typed;
+
+pub use config::{Config, Schema};
+#[cfg(feature = "crypto")]
+pub use crypto::load_encrypted_from_reader;
+pub use decoders::Decoders;
+pub use details::{LoadDetails, LoadReport};
+#[cfg(feature = "encoding")]
+pub use encoding::load_from_reader_encoded;
+pub use entry::Entry;
+pub use error::{
+    CasError,
+    ConfigError,
+    CycleError,
+    DisallowedKeysError,
+    ParseError,
+    ParseErrorKind,
+    PathError,
+    Result,
+    SchemaError,
+    UnresolvedError,
+};
+#[cfg(feature = "http")]
+pub use http::{
+    load_from_url,
+    load_from_url_with,
+    HttpClient,
+    HttpResponse,
+    Transport,
+};
+#[cfg(feature = "json")]
+pub use json::load_from_json_reader;
+#[cfg(feature = "keyring")]
+pub use keyring_store::load_keyring_from_reader;
+pub use ordered::OrderedLoad;
+pub use parser::{
+    parse_auto,
+    Dialect,
+    Directive,
+    Overrides,
+    ParseReport,
+    Parser,
+};
+pub use sink::{
+    EnvSink,
+    HashMapSink,
+    Operation,
+    ProcessEnv,
+    RecordingSink,
+};
+#[cfg(feature = "tokio")]
+pub use tokio_fs::load_from_path_async;
+#[cfg(feature = "toml")]
+pub use toml_table::load_from_toml_reader;
+pub use transaction::{Mutation, Transaction};
+pub use typed::{TypedParsers, TypedValues};
+
+// Items used by the expansions of the crate's macros and by its benches, which
+// are not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use std::io::ErrorKind;
+    use super::{open, Config, Result};
+
+    // Reads the `./.env` file into a config for `config!`'s `load`, treating a
+    // file that does not exist as empty.
+    pub fn dotenv_config() -> Result<Config> {
+        let config = match open(".env") {
+            Ok(mut file) => Config::from_reader(&mut file)?,
+            Err(ref why) if why.kind() == ErrorKind::NotFound => {
+                Config::default()
+            },
+            Err(why) => return Err(why),
+        };
 
-pub use error::Result;
+        Ok(config.with_env_precedence(true))
+    }
+
+    // The previous, `str::find`-based implementation of `utils::parse_line`,
+    // which the byte-scanning implementation is tested and benched against.
+    pub fn parse_line_find(line: &str) -> Option<(&str, &str)> {
+        let (equals, comment) = (line.find('='), line.find('#'));
+
+        if let (Some(comment), Some(equals)) = (comment, equals) {
+            if comment < equals {
+                return None;
+            }
+        }
+
+        equals.map(|pos_equals| {
+            let value = comment
+                .map(|pos_pound| &line[pos_equals + 1..pos_pound])
+                .unwrap_or_else(|| &line[pos_equals + 1..]);
 
+            (line[..pos_equals].trim(), value.trim())
+        })
+    }
+}
+
+use sink::LockedEnv;
 use std::env;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Error as IoError, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::result::Result as StdResult;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Loads a key from the current environment. This is more or less an alias of
 /// `std::env::var`, but the benefit - slightly - is one less possible use
@@ -135,6 +295,365 @@ fn _key(name: &str) -> Option<String> {
     env::var(name).ok()
 }
 
+/// Returns whether a variable in the current environment is set to a truthy
+/// value, as is common for feature flags.
+///
+/// A value is truthy if, ignoring ASCII case and surrounding whitespace, it is
+/// one of `1`, `true`, `yes`, or `on`. Any other value, including an empty
+/// one, is falsy, as is a variable that is not present or not valid unicode.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+///
+/// env::set_var("FLAG_EXAMPLE_ON", "Yes");
+/// env::set_var("FLAG_EXAMPLE_OFF", "0");
+///
+/// assert!(kankyo::flag("FLAG_EXAMPLE_ON"));
+/// assert!(!kankyo::flag("FLAG_EXAMPLE_OFF"));
+/// assert!(!kankyo::flag("FLAG_EXAMPLE_MISSING"));
+/// ```
+pub fn flag(name: &str) -> bool {
+    _key(name).map_or(false, |value| is_truthy(&value))
+}
+
+/// Loads a key from the current environment as a list, split on the given
+/// separator.
+///
+/// Each element is trimmed of surrounding whitespace. Empty elements, such as
+/// from a trailing separator, are skipped, so an empty value is an empty list.
+/// Returns `None` if the key is not present, like with [`key`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+///
+/// env::set_var("KEY_LIST_EXAMPLE", "a.example, b.example,,c.example,");
+///
+/// assert_eq!(
+///     kankyo::key_list("KEY_LIST_EXAMPLE", ','),
+///     Some(vec![
+///         "a.example".to_owned(),
+///         "b.example".to_owned(),
+///         "c.example".to_owned(),
+///     ]),
+/// );
+/// ```
+///
+/// [`key`]: fn.key.html
+pub fn key_list(name: &str, sep: char) -> Option<Vec<String>> {
+    _key(name).map(|value| {
+        value
+            .split(sep)
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .map(str::to_owned)
+            .collect()
+    })
+}
+
+/// Loads several keys from the current environment at once.
+///
+/// Each requested name maps to its value, or `None` if it is not present (or
+/// is not valid unicode), like with [`key`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+///
+/// env::set_var("KEYS_VALUES_EXAMPLE", "1");
+///
+/// let values = kankyo::keys_values(&["KEYS_VALUES_EXAMPLE", "MISSING"]);
+///
+/// assert_eq!(values["KEYS_VALUES_EXAMPLE"], Some("1".to_owned()));
+/// assert_eq!(values["MISSING"], None);
+/// ```
+///
+/// [`key`]: fn.key.html
+pub fn keys_values(names: &[&str]) -> HashMap<String, Option<String>> {
+    names.iter().map(|&name| (name.to_owned(), _key(name))).collect()
+}
+
+/// Returns the names of the variables in the current environment that satisfy
+/// the given predicate, sorted.
+///
+/// Variables whose name is not valid unicode are skipped.
+///
+/// # Examples
+///
+/// Find all variables relating to a database:
+///
+/// ```rust
+/// use std::env;
+///
+/// env::set_var("MATCHING_EXAMPLE_DB_HOST", "localhost");
+/// env::set_var("MATCHING_EXAMPLE_DB_PORT", "5432");
+///
+/// let keys = kankyo::keys_matching(|key| {
+///     key.starts_with("MATCHING_EXAMPLE") && key.contains("DB")
+/// });
+///
+/// assert_eq!(keys, vec![
+///     "MATCHING_EXAMPLE_DB_HOST",
+///     "MATCHING_EXAMPLE_DB_PORT",
+/// ]);
+/// ```
+pub fn keys_matching<F: Fn(&str) -> bool>(pred: F) -> Vec<String> {
+    let mut keys = env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .filter(|key| pred(key))
+        .collect::<Vec<_>>();
+    keys.sort();
+
+    keys
+}
+
+/// Checks that each of the given keys is present in the current environment,
+/// returning the keys that are missing.
+///
+/// A key whose value is not valid unicode is considered missing, like with
+/// [`key`]. This is typically called right after loading, to fail fast if the
+/// configuration is incomplete.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+///
+/// env::set_var("REQUIRE_EXAMPLE_HOST", "localhost");
+///
+/// let result = kankyo::require_keys(&[
+///     "REQUIRE_EXAMPLE_HOST",
+///     "REQUIRE_EXAMPLE_PORT",
+/// ]);
+///
+/// assert_eq!(result, Err(vec!["REQUIRE_EXAMPLE_PORT".to_owned()]));
+/// ```
+///
+/// # Errors
+///
+/// Returns the missing keys, in the order given, if any are missing.
+///
+/// [`key`]: fn.key.html
+pub fn require_keys(keys: &[&str]) -> StdResult<(), Vec<String>> {
+    let missing = keys
+        .iter()
+        .filter(|&&key| _key(key).is_none())
+        .map(|&key| key.to_owned())
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+/// Returns whether a `.env` file exists at the current working directory
+/// (`./.env`).
+///
+/// This is [`exists`] called with the default path.
+///
+/// # Examples
+///
+/// Only load the `.env` file if it exists:
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// if kankyo::default_env_exists() {
+///     kankyo::load(false)?;
+/// }
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// [`exists`]: fn.exists.html
+#[inline]
+pub fn default_env_exists() -> bool {
+    exists(".env")
+}
+
+/// Returns whether a file exists at the given path, without loading it.
+///
+/// This returns `false` if the path exists but is not a file, such as a
+/// directory.
+///
+/// # Examples
+///
+/// ```rust
+/// assert!(!kankyo::exists("./does-not-exist.env"));
+/// ```
+#[inline]
+pub fn exists<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().is_file()
+}
+
+/// Compares the current environment to a previous [`snapshot`], returning
+/// how it changed.
+///
+/// This is [`utils::diff`] applied to the snapshot and a new snapshot, so that
+/// taking a snapshot before a load reveals exactly what the load did. Changes
+/// are returned in order of their key.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::Change;
+/// use std::io::Cursor;
+///
+/// let snapshot = kankyo::snapshot();
+///
+/// let mut cursor = Cursor::new("CHANGES_SINCE_DOC=1");
+/// kankyo::load_from_reader(&mut cursor, false).unwrap();
+///
+/// assert_eq!(kankyo::changes_since(&snapshot), vec![Change::Added {
+///     key: "CHANGES_SINCE_DOC".to_owned(),
+///     value: "1".to_owned(),
+/// }]);
+/// ```
+///
+/// [`snapshot`]: fn.snapshot.html
+/// [`utils::diff`]: utils/fn.diff.html
+pub fn changes_since(snapshot: &HashMap<String, String>) -> Vec<utils::Change> {
+    let current = self::snapshot();
+
+    utils::diff(&sorted_lines(snapshot), &sorted_lines(&current))
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// the keys that are already present in the environment, without modifying
+/// it.
+///
+/// These are the keys that loading the reader with overwriting enabled would
+/// overwrite, making this a pre-flight check before such a load. Each key is
+/// returned once, in the order that it is first defined.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("CONFLICTS_EXAMPLE", "1");
+///
+/// let mut cursor = Cursor::new("CONFLICTS_EXAMPLE=2\nCONFLICTS_NEW=3");
+/// let conflicts = kankyo::conflicts_from_reader(&mut cursor).unwrap();
+///
+/// assert_eq!(conflicts, vec!["CONFLICTS_EXAMPLE"]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn conflicts_from_reader<R: Read>(reader: &mut R) -> Result<Vec<String>> {
+    let content = read_to_string(reader)?;
+    let mut seen = HashSet::new();
+    let mut conflicts = Vec::new();
+
+    for (key, _) in utils::parse_lines(&content) {
+        if env::var_os(&*key).is_some() && seen.insert(key.clone()) {
+            conflicts.push(key.into_owned());
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// Reads the content of two readers and computes how the current one differs
+/// from the baseline, without modifying the environment.
+///
+/// This is [`utils::diff`] applied to the parsed content of the readers, and
+/// can be used to detect drift between a committed baseline and a deployed
+/// file.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::Change;
+/// use std::io::Cursor;
+///
+/// let mut baseline = Cursor::new("PORT=80");
+/// let mut current = Cursor::new("PORT=8080");
+/// let changes = kankyo::drift_from_readers(&mut baseline, &mut current)
+///     .unwrap();
+///
+/// assert_eq!(changes, vec![Change::Modified {
+///     key: "PORT".to_owned(),
+///     old: "80".to_owned(),
+///     new: "8080".to_owned(),
+/// }]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from either
+/// reader.
+///
+/// [`utils::diff`]: utils/fn.diff.html
+pub fn drift_from_readers<R1: Read, R2: Read>(
+    baseline: &mut R1,
+    current: &mut R2,
+) -> Result<Vec<utils::Change>> {
+    let baseline = read_to_string(baseline)?;
+    let current = read_to_string(current)?;
+
+    Ok(utils::diff(
+        &utils::parse_lines(&baseline),
+        &utils::parse_lines(&current),
+    ))
+}
+
+/// Computes what a [`snapshot`] would contain after loading the given reader,
+/// without modifying the environment.
+///
+/// The parsed values are overlaid onto a snapshot of the current environment
+/// using the same rules as [`load_from_reader`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("EFFECTIVE_EXAMPLE=1");
+/// let effective = kankyo::effective_after_load(&mut cursor, true).unwrap();
+///
+/// assert_eq!(effective["EFFECTIVE_EXAMPLE"], "1");
+/// assert!(kankyo::key("EFFECTIVE_EXAMPLE").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`load_from_reader`]: fn.load_from_reader.html
+/// [`snapshot`]: fn.snapshot.html
+pub fn effective_after_load<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<HashMap<String, String>> {
+    let content = read_to_string(reader)?;
+    let mut map = snapshot();
+
+    for (key, value) in utils::parse_lines(&content) {
+        if overwrite {
+            map.insert(key.into_owned(), value.to_owned());
+        } else {
+            map.entry(key.into_owned()).or_insert_with(|| value.to_owned());
+        }
+    }
+
+    Ok(map)
+}
+
 /// Loads a `.env` file at the current working directory (`./.env`), overwriting
 /// existing variables.
 ///
@@ -161,7 +680,7 @@ fn _key(name: &str) -> Option<String> {
 /// [`load`]: fn.load.html
 #[inline]
 pub fn init() -> Result<()> {
-    let mut file = try!(File::open(Path::new(".env")));
+    let mut file = open(".env")?;
 
     load_from_reader(&mut file, true)
 }
@@ -190,30 +709,18 @@ pub fn init() -> Result<()> {
 /// Returns an `std::io::Error` if there was an error reading the file.
 #[inline]
 pub fn load(overwrite: bool) -> Result<()> {
-    let mut file = try!(File::open(Path::new(".env")));
+    let mut file = open(".env")?;
 
     load_from_reader(&mut file, overwrite)
 }
 
-/// Reads the content of a reader and parses it to find `.env` lines.
-///
-/// # Errors
-///
-/// Returns an `std::io::Error` if there was an error reading from the reader.
-pub fn load_from_reader<R: Read>(
-    reader: &mut R,
-    overwrite: bool,
-) -> Result<()> {
-    let content = try!(read_to_string(reader));
-    utils::set_variables(&utils::parse_lines(&content), overwrite);
-
-    Ok(())
-}
-
-/// Creates a snapshot of the present environment variables.
+/// Loads a `.env` file at the current working directory (`./.env`) only if the
+/// given guard variable is set to a truthy value, returning whether it loaded.
 ///
-/// This is similar to `std::env::vars`, but will instead return a HashMap over
-/// only the environment variables that are valid UTF-8.
+/// The guard is checked with [`flag`], so it must be one of `1`, `true`, `yes`,
+/// or `on`, ignoring case. If the guard is unset or has any other value, the
+/// file is not read at all. This allows disabling `.env` loading in production
+/// with a single switch, while enabling it in development.
 ///
 /// # Examples
 ///
@@ -221,9 +728,9 @@ pub fn load_from_reader<R: Read>(
 /// # use std::error::Error;
 /// #
 /// # fn try_main() -> Result<(), Box<Error>> {
-/// let snapshot = kankyo::snapshot();
-///
-/// try!(kankyo::load(false));
+/// if kankyo::load_if_env("KANKYO_ENABLE", false)? {
+///     println!("Loaded!");
+/// }
 /// #     Ok(())
 /// # }
 /// #
@@ -231,12 +738,31 @@ pub fn load_from_reader<R: Read>(
 /// #     try_main().unwrap();
 /// # }
 /// ```
-pub fn snapshot() -> HashMap<String, String> {
-    env::vars_os().into_iter().filter_map(utils::parse_kv).collect()
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the guard is truthy and there was an error
+/// reading the file.
+///
+/// [`flag`]: fn.flag.html
+pub fn load_if_env(guard: &str, overwrite: bool) -> Result<bool> {
+    if !flag(guard) {
+        return Ok(false);
+    }
+
+    load(overwrite)?;
+
+    Ok(true)
 }
 
-/// Unloads all environment variables in the default `./.env` file from the
-/// current environment.
+/// Loads a `.env` file at the given path, refusing to load it if it is
+/// accessible by users other than its owner.
+///
+/// This is intended for files holding secrets, where loose permissions, such
+/// as the common `0644` mode, may leak them to other users. On Unix, the file
+/// must not have any permission bits set for its group or others, such as a
+/// mode of `0600`. On other platforms, the permissions are not checked and the
+/// file is simply loaded.
 ///
 /// # Examples
 ///
@@ -244,11 +770,7 @@ pub fn snapshot() -> HashMap<String, String> {
 /// # use std::error::Error;
 /// #
 /// # fn try_main() -> Result<(), Box<Error>> {
-/// try!(kankyo::load(false));
-/// println!("Loaded!");
-///
-/// try!(kankyo::unload());
-/// println!("Unloaded!");
+/// kankyo::load_checking_permissions("secrets.env", false)?;
 /// #     Ok(())
 /// # }
 /// #
@@ -259,34 +781,36 @@ pub fn snapshot() -> HashMap<String, String> {
 ///
 /// # Errors
 ///
-/// Returns an `std::io::Error` if there was an error reading from the reader.
-#[inline]
-pub fn unload() -> Result<()> {
-    unload_from_reader(&mut try!(File::open(".env")))
+/// Returns an `std::io::Error` of kind `PermissionDenied` if the file is
+/// accessible by its group or others. Nothing is loaded in this case.
+///
+/// Returns an `std::io::Error` if there was an error reading the file.
+pub fn load_checking_permissions<P: AsRef<Path>>(
+    path: P,
+    overwrite: bool,
+) -> Result<()> {
+    let mut file = open(path)?;
+    check_permissions(&file.file)?;
+
+    load_from_reader(&mut file, overwrite)
 }
 
-/// Unloads from the read content of the given reader.
+/// Loads a `.env` file at the given path, where the path may reference
+/// environment variables.
 ///
-/// The reader should contain content that of a `.env` file.
-///
-/// If you need to unload a given slice of keys, prefer [`utils::unload`].
+/// References are of the form `${NAME}` or `$NAME`, as described in the
+/// [`interpolate`] module, and are expanded against the current environment
+/// before the file is opened.
 ///
 /// # Examples
 ///
-/// Unload from a file at the path `./.env`:
+/// Load the `.env` file in the directory stored in `CONFIG_DIR`:
 ///
 /// ```rust,no_run
 /// # use std::error::Error;
 /// #
 /// # fn try_main() -> Result<(), Box<Error>> {
-/// #
-/// use std::fs::File;
-///
-/// let mut file = try!(File::open("./.env"));
-///
-/// try!(kankyo::unload_from_reader(&mut file));
-///
-/// println!("Successfully unloaded from `./.env`");
+/// kankyo::load_from_path("${CONFIG_DIR}/.env", false)?;
 /// #     Ok(())
 /// # }
 /// #
@@ -297,48 +821,2942 @@ pub fn unload() -> Result<()> {
 ///
 /// # Errors
 ///
-/// Returns an `std::io::Error` if there is an error reading from the reader.
+/// Returns an `std::io::Error` of kind `NotFound` if the path references a
+/// variable that is not set, rather than expanding it to an empty string and
+/// possibly loading an unintended file.
 ///
-/// [`utils::unload`]: utils/fn.unload.html
-pub fn unload_from_reader<R: Read>(reader: &mut R) -> Result<()> {
-    let buf = try!(read_to_string(reader));
-    let lines = utils::parse_lines(&buf);
-    utils::unload_from_parsed_lines(&lines);
-
-    Ok(())
-}
+/// Returns an `std::io::Error` if there was an error reading the file.
+///
+/// [`interpolate`]: interpolate/index.html
+pub fn load_from_path(path: &str, overwrite: bool) -> Result<()> {
+    let mut unset = None;
 
-fn read_to_string<R: Read>(reader: &mut R) -> Result<String> {
-    let mut s = String::new();
-    try!(reader.read_to_string(&mut s));
+    let path = interpolate::expand(path, |name| {
+        let value = env::var(name).ok();
 
-    Ok(s)
-}
+        if value.is_none() && unset.is_none() {
+            unset = Some(name.to_owned());
+        }
 
-#[cfg(test)]
-mod test {
-    use std::io::Cursor;
-    use super::*;
+        value
+    });
 
-    #[test]
-    fn test_key() {
-        utils::set_variables(&[("foo", "1")], true);
-        assert!(key("foo").is_some());
-        utils::unload(&["foo"]);
+    if let Some(name) = unset {
+        return Err(IoError::new(
+            ErrorKind::NotFound,
+            format!("path references unset variable {}", name),
+        ));
     }
 
-    #[test]
-    fn test_reader_loaders() {
-        let text = "A=B\nC=D".to_owned().into_bytes();
-
-        let mut cursor = Cursor::new(text);
-
-        load_from_reader(&mut cursor, true).unwrap();
+    load_from_reader(&mut open(path)?, overwrite)
+}
 
-        cursor.set_position(0);
+/// Reads the content of a reader and parses it to find `.env` lines.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    utils::set_variables(&utils::parse_lines(&content), overwrite);
+
+    Ok(())
+}
+
+/// Reads the entirety of standard input and parses it to find `.env` lines,
+/// returning the keys that were set.
+///
+/// This allows piping a file into a program, such as `cat .env | app`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// let keys = kankyo::load_from_stdin(false)?;
+///
+/// println!("Loaded {} keys", keys.len());
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from standard
+/// input.
+pub fn load_from_stdin(overwrite: bool) -> Result<Vec<String>> {
+    let stdin = io::stdin();
+    let mut lock = stdin.lock();
+
+    load_keys_from_reader(&mut lock, overwrite)
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, loading
+/// them into the given map rather than the environment.
+///
+/// This is like [`load_from_reader`], but does not touch the environment at
+/// all, via [`utils::set_variables_in`]. Keys that already exist in the map
+/// are overwritten.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::io::Cursor;
+///
+/// let mut map = HashMap::new();
+/// map.insert("HOST".to_owned(), "example.com".to_owned());
+///
+/// let mut cursor = Cursor::new("HOST=localhost\nPORT=8080");
+/// kankyo::load_into_map(&mut cursor, &mut map).unwrap();
+///
+/// assert_eq!(map["HOST"], "localhost");
+/// assert_eq!(map["PORT"], "8080");
+/// assert!(kankyo::key("PORT").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+/// The map is not modified in this case.
+///
+/// [`load_from_reader`]: fn.load_from_reader.html
+/// [`utils::set_variables_in`]: utils/fn.set_variables_in.html
+pub fn load_into_map<R: Read>(
+    reader: &mut R,
+    map: &mut HashMap<String, String>,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    utils::set_variables_in(map, &utils::parse_lines(&content), true);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, replacing
+/// invalid UTF-8 rather than returning an error.
+///
+/// Each invalid UTF-8 sequence is replaced with the replacement character
+/// (`U+FFFD`), via `String::from_utf8_lossy`. This loses data: a key or value
+/// containing an invalid sequence is loaded with the replacement character in
+/// its place, and can not be recovered from the environment. Use
+/// [`load_from_reader`] to instead reject such content.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new(&b"LOSSY_EXAMPLE=caf\xe9"[..]);
+/// kankyo::load_from_reader_lossy(&mut cursor, true).unwrap();
+///
+/// assert_eq!(kankyo::key("LOSSY_EXAMPLE").unwrap(), "caf\u{fffd}");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`load_from_reader`]: fn.load_from_reader.html
+pub fn load_from_reader_lossy<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let content = String::from_utf8_lossy(&bytes);
+    utils::set_variables(&utils::parse_lines(&content), overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// an error if any key is not in the given allowlist.
+///
+/// Returns the keys that were set.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let allowed = ["ALLOWLIST_HOST", "ALLOWLIST_PORT"];
+///
+/// let mut cursor = Cursor::new("ALLOWLIST_HOST=localhost\nALLOWLIST_POTR=80");
+/// let result = kankyo::load_strict_allowlist_from_reader(
+///     &mut cursor,
+///     &allowed,
+///     true,
+/// );
+///
+/// assert!(result.is_err());
+/// assert!(kankyo::key("ALLOWLIST_HOST").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// Returns an `std::io::Error` of kind `InvalidData`, wrapping a
+/// [`DisallowedKeysError`] naming every key not in the allowlist once. Nothing
+/// is loaded in this case.
+///
+/// [`DisallowedKeysError`]: struct.DisallowedKeysError.html
+pub fn load_strict_allowlist_from_reader<R: Read>(
+    reader: &mut R,
+    allowed: &[&str],
+    overwrite: bool,
+) -> Result<Vec<String>> {
+    let content = read_to_string(reader)?;
+    let lines = utils::parse_lines(&content);
+
+    let mut seen = HashSet::new();
+    let disallowed = lines
+        .iter()
+        .filter(|(key, _)| !allowed.contains(&&**key) && seen.insert(&**key))
+        .map(|(key, _)| key.to_string())
+        .collect::<Vec<_>>();
+
+    if !disallowed.is_empty() {
+        return Err(DisallowedKeysError {
+            keys: disallowed,
+        }.into());
+    }
+
+    Ok(set_variables_collect(&lines, overwrite))
+}
+
+/// Reads the content of a reader in the format of `/proc/self/environ` and
+/// loads the variables into the environment, returning the keys that were set.
+///
+/// This can be used to import the environment of another process. Refer to
+/// [`utils::parse_environ`] for how the content is parsed; notably, values are
+/// taken verbatim with no comment handling.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// use std::fs::File;
+///
+/// let mut file = File::open("/proc/1234/environ")?;
+/// kankyo::load_from_proc_environ(&mut file, false)?;
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`utils::parse_environ`]: utils/fn.parse_environ.html
+pub fn load_from_proc_environ<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<Vec<String>> {
+    let content = read_to_string(reader)?;
+
+    Ok(set_variables_collect(&utils::parse_environ(&content), overwrite))
+}
+
+/// Reads the content of several readers as one logical `.env` file, returning
+/// the keys that were set.
+///
+/// The readers are read in order. If a key is defined by multiple readers, the
+/// definition from the later reader is used. `overwrite` only applies to
+/// variables that existed in the environment prior to loading.
+///
+/// # Examples
+///
+/// Load a base set of variables, overridden by a more specific set:
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let base = Cursor::new("READERS_HOST=localhost\nREADERS_PORT=80");
+/// let local = Cursor::new("READERS_PORT=8080");
+///
+/// kankyo::load_from_readers(vec![base, local], true).unwrap();
+///
+/// assert_eq!(kankyo::key("READERS_PORT").unwrap(), "8080");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from a reader.
+/// Nothing is loaded in this case.
+pub fn load_from_readers<I, R>(
+    readers: I,
+    overwrite: bool,
+) -> Result<Vec<String>> where I: IntoIterator<Item = R>, R: Read {
+    let mut content = String::new();
+
+    for mut reader in readers {
+        reader.read_to_string(&mut content)?;
+
+        // Make sure that the last line of a reader isn't joined with the first
+        // line of the next.
+        content.push('\n');
+    }
+
+    let mut lines: Vec<utils::ParsedLine> = Vec::new();
+    let mut positions = HashMap::new();
+
+    for (key, value) in utils::parse_lines(&content) {
+        match positions.get(&key) {
+            Some(&idx) => lines[idx] = (key, value),
+            None => {
+                positions.insert(key.clone(), lines.len());
+                lines.push((key, value));
+            },
+        }
+    }
+
+    Ok(set_variables_collect(&lines, overwrite))
+}
+
+/// Loads a `.env` file that may extend a parent file, returning the keys that
+/// were set.
+///
+/// A file extends a parent via a comment line of the form:
+///
+/// ```text
+/// # kankyo:extends ../base.env
+/// ```
+///
+/// where a relative path is relative to the directory of the file containing
+/// the directive. Only the first such line of a file is used. The parent is
+/// loaded first, and may itself extend another file, and then the file's own
+/// definitions override the parent's, as with [`load_from_readers`].
+///
+/// As the directive is a comment, other loading functions ignore it.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// let keys = kankyo::load_with_extends("config/production.env", false)?;
+///
+/// println!("Loaded {} keys", keys.len());
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidData` if a file extends
+/// itself, either directly or through other files.
+///
+/// Returns an `std::io::Error` if there was an error reading any of the files.
+/// Nothing is loaded in these cases.
+///
+/// [`load_from_readers`]: fn.load_from_readers.html
+pub fn load_with_extends<P: AsRef<Path>>(
+    path: P,
+    overwrite: bool,
+) -> Result<Vec<String>> {
+    let mut contents = Vec::new();
+    let mut visited = Vec::new();
+    let mut next = Some(path.as_ref().to_path_buf());
+
+    while let Some(path) = next.take() {
+        let canonical = fs::canonicalize(&path)
+            .map_err(|err| PathError::wrap(&path, err))?;
+
+        if visited.contains(&canonical) {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("cyclic extends of {}", path.display()),
+            ));
+        }
+
+        let content = fs::read_to_string(&canonical)
+            .map_err(|err| PathError::wrap(&canonical, err))?;
+
+        if let Some(parent) = extends_directive(&content) {
+            let dir = canonical.parent().unwrap_or_else(|| Path::new(""));
+            next = Some(dir.join(parent));
+        }
+
+        visited.push(canonical);
+        contents.push(content);
+    }
+
+    let readers = contents.iter().rev().map(|content| content.as_bytes());
+
+    load_from_readers(readers, overwrite)
+}
+
+/// Loads every `.env` fragment in a directory, returning the keys that were
+/// set.
+///
+/// A fragment is a file named `.env` or with the `env` extension, such as
+/// `10-database.env`; other files and subdirectories are skipped. Fragments are
+/// loaded in order of their file name via [`load_from_readers`], so a key
+/// defined by multiple fragments takes the value of the last one. Variables
+/// that already exist in the environment are not overwritten.
+///
+/// # Examples
+///
+/// Load the fragments of a `conf.d`-style directory:
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// let keys = kankyo::load_from_dir("conf.d")?;
+///
+/// println!("Loaded {} keys", keys.len());
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading the directory or
+/// a fragment. Nothing is loaded in this case.
+///
+/// [`load_from_readers`]: fn.load_from_readers.html
+pub fn load_from_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<String>> {
+    let dir = dir.as_ref();
+    let mut paths = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(|err| PathError::wrap(dir, err))? {
+        let path = entry.map_err(|err| PathError::wrap(dir, err))?.path();
+        let is_fragment = path.file_name() == Some(OsStr::new(".env"))
+            || path.extension() == Some(OsStr::new("env"));
+
+        if is_fragment && path.is_file() {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    let files = paths
+        .iter()
+        .map(open)
+        .collect::<Result<Vec<_>>>()?;
+
+    load_from_readers(files, false)
+}
+
+/// Reads the content of a reader and parses it with the given [`Parser`],
+/// applying the resulting directives to the environment.
+///
+/// # Examples
+///
+/// Load from a reader where a bare key unsets the variable:
+///
+/// ```rust
+/// use kankyo::Parser;
+/// use std::io::Cursor;
+///
+/// let parser = Parser::new().unset_directives(true);
+/// let mut cursor = Cursor::new("WITH_EXAMPLE=1\nWITH_EXAMPLE");
+///
+/// kankyo::load_from_reader_with(&mut cursor, &parser, true).unwrap();
+/// assert!(kankyo::key("WITH_EXAMPLE").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// Returns an `std::io::Error` of kind `InvalidData`, wrapping a
+/// [`ParseError`], if a line could not be parsed. Nothing is loaded in this
+/// case.
+///
+/// [`ParseError`]: struct.ParseError.html
+/// [`Parser`]: struct.Parser.html
+pub fn load_from_reader_with<R: Read>(
+    reader: &mut R,
+    parser: &Parser,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    utils::set_directives(&parser.parse_lines(&content)?, overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it with the given [`Parser`] into
+/// [`Overrides`], applying both the sets and unsets in one pass.
+///
+/// # Examples
+///
+/// Load an override file that sets one variable and unsets another:
+///
+/// ```rust
+/// use kankyo::Parser;
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("OVERRIDES_EXAMPLE_DEBUG", "1");
+///
+/// let parser = Parser::new().empty_unsets(true);
+/// let mut cursor = Cursor::new(
+///     "OVERRIDES_EXAMPLE_HOST=localhost\nOVERRIDES_EXAMPLE_DEBUG=",
+/// );
+/// kankyo::load_overrides_from_reader(&mut cursor, &parser, true).unwrap();
+///
+/// assert!(env::var("OVERRIDES_EXAMPLE_HOST").is_ok());
+/// assert!(env::var("OVERRIDES_EXAMPLE_DEBUG").is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidData` if the content could not
+/// be parsed by the parser. Nothing is loaded in this case.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`Overrides`]: struct.Overrides.html
+/// [`Parser`]: struct.Parser.html
+pub fn load_overrides_from_reader<R: Read>(
+    reader: &mut R,
+    parser: &Parser,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    parser.parse_overrides(&content)?.apply(overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, expanding
+/// references to other variables in the values.
+///
+/// If `two_pass` is `false`, references are resolved via
+/// [`interpolate::resolve`], and can only refer to keys on earlier lines or to
+/// variables in the environment. If `true`, they are resolved via
+/// [`interpolate::resolve_two_pass`], and can also refer to keys on later
+/// lines.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("INTERP_URL=http://$INTERP_HOST\n\
+///                               INTERP_HOST=localhost");
+///
+/// kankyo::load_from_reader_interpolated(&mut cursor, true, true).unwrap();
+///
+/// assert_eq!(kankyo::key("INTERP_URL").unwrap(), "http://localhost");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// Returns an `std::io::Error` of kind `InvalidData`, wrapping a
+/// [`CycleError`], if `two_pass` is `true` and a key references itself through
+/// other keys.
+/// Nothing is loaded in this case.
+///
+/// [`CycleError`]: struct.CycleError.html
+/// [`interpolate::resolve`]: interpolate/fn.resolve.html
+/// [`interpolate::resolve_two_pass`]: interpolate/fn.resolve_two_pass.html
+pub fn load_from_reader_interpolated<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+    two_pass: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    let lines = utils::parse_lines(&content);
+
+    let resolved = if two_pass {
+        interpolate::resolve_two_pass(&lines)?
+    } else {
+        interpolate::resolve(&lines)
+    };
+
+    let lines = resolved
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, expanding
+/// references in the values only to keys defined on earlier lines of the
+/// reader.
+///
+/// References are resolved via [`interpolate::resolve_local`], and never from
+/// the environment, so that the loaded values do not depend on the shell. If
+/// `strict` is `false`, a reference to a key not defined in the reader is
+/// expanded to an empty string.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("LOCAL_INTERP_SHELL", "shell");
+///
+/// let mut cursor = Cursor::new("LOCAL_INTERP_A=1\n\
+///                               LOCAL_INTERP_B=$LOCAL_INTERP_A\
+///                                              $LOCAL_INTERP_SHELL");
+///
+/// kankyo::load_from_reader_interpolated_local(&mut cursor, true, false)
+///     .unwrap();
+///
+/// assert_eq!(kankyo::key("LOCAL_INTERP_B").unwrap(), "1");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// Returns an `std::io::Error` of kind `InvalidData`, wrapping an
+/// [`UnresolvedError`], if `strict` is `true` and a value references a key not
+/// defined on an earlier line. Nothing is loaded in this case.
+///
+/// [`UnresolvedError`]: struct.UnresolvedError.html
+/// [`interpolate::resolve_local`]: interpolate/fn.resolve_local.html
+pub fn load_from_reader_interpolated_local<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+    strict: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    let resolved = interpolate::resolve_local(
+        &utils::parse_lines(&content),
+        strict,
+    )?;
+
+    let lines = resolved
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, passing
+/// each key-value pair through the given function before loading it.
+///
+/// The function may return a new key and value to load in place of the parsed
+/// pair, or `None` to skip the pair entirely.
+///
+/// # Examples
+///
+/// Uppercase all keys, and skip any with an empty value:
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("transform_example=1\ntransform_empty=");
+///
+/// kankyo::load_transform_from_reader(&mut cursor, true, |key, value| {
+///     if value.is_empty() {
+///         None
+///     } else {
+///         Some((key.to_uppercase(), value.to_owned()))
+///     }
+/// }).unwrap();
+///
+/// assert!(kankyo::key("TRANSFORM_EXAMPLE").is_some());
+/// assert!(kankyo::key("TRANSFORM_EMPTY").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_transform_from_reader<R, F>(
+    reader: &mut R,
+    overwrite: bool,
+    mut f: F,
+) -> Result<()>
+    where F: FnMut(&str, &str) -> Option<(String, String)>, R: Read {
+    let content = read_to_string(reader)?;
+
+    // The pairs are transformed before locking the environment, as the
+    // transform may itself use the library.
+    let pairs = utils::parse_lines(&content)
+        .into_iter()
+        .filter_map(|(key, value)| f(&key, value))
+        .collect::<Vec<_>>();
+    let locked = LockedEnv::new();
+
+    for (key, value) in pairs {
+        if !overwrite && env::var_os(&key).is_some() {
+            continue;
+        }
+
+        locked.set_var(&key, &value);
+    }
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, decoding
+/// each value with the decoder registered for its key.
+///
+/// Values of keys that no decoder is registered for are loaded unchanged. All
+/// values are decoded before any variable is set, so if a decoder fails then
+/// the environment is left untouched.
+///
+/// # Examples
+///
+/// Trim a trailing slash from all keys ending in `_URL`:
+///
+/// ```rust
+/// use kankyo::Decoders;
+/// use std::io::Cursor;
+///
+/// let decoders = Decoders::new().register("*_URL", |value| {
+///     Ok(value.trim_end_matches('/').to_owned())
+/// });
+///
+/// let mut cursor = Cursor::new("DECODED_URL=http://host/\nDECODED=a/");
+/// kankyo::load_decoded_from_reader(&mut cursor, &decoders, true).unwrap();
+///
+/// assert_eq!(kankyo::key("DECODED_URL").unwrap(), "http://host");
+/// assert_eq!(kankyo::key("DECODED").unwrap(), "a/");
+/// ```
+///
+/// # Errors
+///
+/// Returns the error of a decoder if it fails.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_decoded_from_reader<R: Read>(
+    reader: &mut R,
+    decoders: &Decoders,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    let mut decoded = Vec::new();
+
+    for (key, value) in utils::parse_lines(&content) {
+        let value = decoders.decode(&key, value)?;
+        decoded.push((key, value));
+    }
+
+    let lines = decoded
+        .iter()
+        .map(|(k, v)| (&**k, v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+/// Loads the `.env` file at the path stored in the given environment variable,
+/// returning the keys that were set.
+///
+/// If the environment variable is not set, nothing is loaded and `None` is
+/// returned. Variables that already exist in the environment are not
+/// overwritten, so the file only provides defaults.
+///
+/// # Examples
+///
+/// Load the file pointed to by `MY_APP_ENV`, if set:
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// match kankyo::load_from_env_path("MY_APP_ENV")? {
+///     Some(keys) => println!("Loaded {} keys", keys.len()),
+///     None => println!("MY_APP_ENV is not set"),
+/// }
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading the file.
+pub fn load_from_env_path<K: AsRef<OsStr>>(
+    var: K,
+) -> Result<Option<Vec<String>>> {
+    let path = match env::var_os(var) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    load_keys_from_reader(&mut open(path)?, false).map(Some)
+}
+
+/// Loads the `.env` file at the path stored in the `DOTENV_PATH` environment
+/// variable, returning the keys that were set.
+///
+/// This is [`load_from_env_path`] called with `"DOTENV_PATH"`.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading the file.
+///
+/// [`load_from_env_path`]: fn.load_from_env_path.html
+#[inline]
+pub fn load_from_dotenv_path() -> Result<Option<Vec<String>>> {
+    load_from_env_path("DOTENV_PATH")
+}
+
+/// Reads the content of a reader and parses it into [`Entry`]s, without
+/// modifying the environment.
+///
+/// Values may be quoted, as with [`Parser::quotes`], and each entry records
+/// whether its value was quoted along with its line number.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("# comment\nHOST=localhost\nNAME=\"my app\"");
+/// let entries = kankyo::entries_from_reader(&mut cursor).unwrap();
+///
+/// assert_eq!(entries[1].value, "my app");
+/// assert_eq!(entries[1].line, 3);
+/// assert!(entries[1].quoted);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidData`, wrapping a
+/// [`ParseError`], if a quoted value is malformed.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`Entry`]: struct.Entry.html
+/// [`ParseError`]: struct.ParseError.html
+/// [`Parser::quotes`]: struct.Parser.html#method.quotes
+pub fn entries_from_reader<R: Read>(reader: &mut R) -> Result<Vec<Entry>> {
+    let content = read_to_string(reader)?;
+
+    Ok(Entry::parse_all(&content)?)
+}
+
+/// Loads a `.env` file at the current working directory (`./.env`), overwriting
+/// existing variables, and returns a human-readable summary of the load.
+///
+/// The summary is in the form of
+/// `"Loaded 12 variables from ./.env (3 overwrote existing)"`, and does not
+/// include any keys or values. Use [`load_from_reader_detailed`] and
+/// [`LoadDetails::summary`] to include the keys.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// println!("{}", kankyo::load_and_summarize()?);
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading the file.
+///
+/// [`LoadDetails::summary`]: struct.LoadDetails.html#method.summary
+/// [`load_from_reader_detailed`]: fn.load_from_reader_detailed.html
+pub fn load_and_summarize() -> Result<String> {
+    let mut file = open(".env")?;
+    let details = load_from_reader_detailed(&mut file, true)?;
+
+    Ok(details.summary("./.env", false))
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// details of which variables were set.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("DETAILED_EXAMPLE=1");
+/// let details = kankyo::load_from_reader_detailed(&mut cursor, true)
+///     .unwrap();
+///
+/// assert_eq!(details.created, vec!["DETAILED_EXAMPLE"]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader_detailed<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<LoadDetails> {
+    let content = read_to_string(reader)?;
+
+    Ok(LoadDetails::apply(&utils::parse_lines(&content), overwrite))
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// the key-value pairs that were set.
+///
+/// This is like [`load_from_reader`], but allows building a config from what
+/// was loaded without a separate [`snapshot`]. Lines whose key already exists
+/// are excluded if `overwrite` is `false`, as are lines that were not valid to
+/// set. A pair is returned for each line that was set, in order.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("RETURNING_EXAMPLE = 1 # comment");
+/// let pairs = kankyo::load_from_reader_returning(&mut cursor, true)
+///     .unwrap();
+///
+/// assert_eq!(pairs, vec![
+///     ("RETURNING_EXAMPLE".to_owned(), "1".to_owned()),
+/// ]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`load_from_reader`]: fn.load_from_reader.html
+/// [`snapshot`]: fn.snapshot.html
+pub fn load_from_reader_returning<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<Vec<(String, String)>> {
+    let content = read_to_string(reader)?;
+    let lines = utils::parse_lines(&content);
+    let locked = LockedEnv::new();
+    let mut pairs = Vec::with_capacity(lines.len());
+
+    for &(ref key, value) in &lines {
+        let key = &**key;
+
+        if !overwrite && env::var_os(key).is_some() {
+            continue;
+        }
+
+        if locked.set_var(key, value) {
+            pairs.push((key.to_owned(), value.to_owned()));
+        }
+    }
+
+    Ok(pairs)
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// statistics of the load.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("# comment\n\nREPORT_EXAMPLE=1");
+/// let report = kankyo::load_from_reader_report(&mut cursor, true).unwrap();
+///
+/// assert_eq!(report.lines_read, 3);
+/// assert_eq!(report.keys_set, 1);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader_report<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<LoadReport> {
+    let start = Instant::now();
+    let content = read_to_string(reader)?;
+
+    let mut report = LoadReport::apply(&content, overwrite);
+    report.duration = start.elapsed();
+
+    Ok(report)
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// the values that the set variables had prior to loading.
+///
+/// Each key that was set maps to its prior value, or `None` if it did not
+/// previously exist. Pass the result to [`unload_restoring`] to undo the load.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("CAPTURING_EXAMPLE=1");
+/// let captured = kankyo::load_from_reader_capturing(&mut cursor, true)
+///     .unwrap();
+///
+/// assert_eq!(captured.get("CAPTURING_EXAMPLE"), Some(&None));
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`unload_restoring`]: fn.unload_restoring.html
+pub fn load_from_reader_capturing<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<HashMap<String, Option<String>>> {
+    let content = read_to_string(reader)?;
+    let locked = LockedEnv::new();
+    let mut captured = HashMap::new();
+
+    for (key, value) in utils::parse_lines(&content) {
+        let previous = env::var(&*key).ok();
+
+        if !overwrite && previous.is_some() {
+            continue;
+        }
+
+        if locked.set_var(&key, value) {
+            // Only the first prior value is the one from before the load.
+            captured.entry(key.into_owned()).or_insert(previous);
+        }
+    }
+
+    Ok(captured)
+}
+
+/// Reads the content of a reader and parses the value of each key registered
+/// in the given [`TypedParsers`] into its type, without modifying the
+/// environment.
+///
+/// If a key is defined multiple times, its last definition is parsed. Every
+/// registered key is parsed, so the errors of all invalid keys are reported
+/// at once via [`TypedValues::errors`].
+///
+/// # Examples
+///
+/// Refer to [`TypedParsers`] for an example.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`TypedParsers`]: struct.TypedParsers.html
+/// [`TypedValues::errors`]: struct.TypedValues.html#method.errors
+pub fn parse_typed_from_reader<R: Read>(
+    reader: &mut R,
+    parsers: &TypedParsers,
+) -> Result<TypedValues> {
+    let content = read_to_string(reader)?;
+
+    Ok(parsers.parse(&utils::parse_to_map(&content)))
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, writing
+/// each variable that was set to the given sink.
+///
+/// Each variable is written on its own line, formatted via
+/// [`utils::format_line`]. Variables that were not set due to already existing
+/// while `overwrite` is `false` are not written.
+///
+/// # Examples
+///
+/// Load from a reader, printing the loaded variables to stdout:
+///
+/// ```rust
+/// use std::io::{self, Cursor};
+///
+/// let mut cursor = Cursor::new("ECHO_EXAMPLE=1");
+/// let stdout = io::stdout();
+///
+/// kankyo::load_from_reader_echo(&mut cursor, true, &mut stdout.lock())
+///     .unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader
+/// or writing to the sink.
+///
+/// [`utils::format_line`]: utils/fn.format_line.html
+pub fn load_from_reader_echo<R: Read, W: Write>(
+    reader: &mut R,
+    overwrite: bool,
+    sink: &mut W,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    let mut set = Vec::new();
+
+    // The environment is not kept locked while writing to the sink.
+    {
+        let locked = LockedEnv::new();
+
+        for (key, value) in utils::parse_lines(&content) {
+            if !overwrite && env::var_os(&*key).is_some() {
+                continue;
+            }
+
+            if locked.set_var(&key, value) {
+                set.push((key, value));
+            }
+        }
+    }
+
+    for (key, value) in set {
+        writeln!(sink, "{}", utils::format_line(&key, value))?;
+    }
+
+    Ok(())
+}
+
+/// Reads the content of an INI-like reader and parses it to find `.env` lines
+/// under the given section, ignoring other sections.
+///
+/// Sections are parsed as described by [`utils::parse_section`]. Lines before
+/// the first header belong to the default section, which can be loaded by
+/// passing an empty string.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new(
+///     "[database]\nSECTION_EXAMPLE=db\n[env]\nSECTION_EXAMPLE=env",
+/// );
+/// kankyo::load_section_from_reader(&mut cursor, "env", true).unwrap();
+///
+/// assert_eq!(kankyo::key("SECTION_EXAMPLE").unwrap(), "env");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`utils::parse_section`]: utils/fn.parse_section.html
+pub fn load_section_from_reader<R: Read>(
+    reader: &mut R,
+    section: &str,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    utils::set_variables(&utils::parse_section(&content, section), overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, converting
+/// each key into a conventional name before setting it.
+///
+/// Keys are converted via [`utils::sanitize_key`], so that a key such as
+/// `db.host` is set as `DB_HOST`. If several keys convert to the same name,
+/// the last one wins.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("sanitized-example.key=1");
+/// kankyo::load_from_reader_sanitized(&mut cursor, true).unwrap();
+///
+/// assert_eq!(kankyo::key("SANITIZED_EXAMPLE_KEY").unwrap(), "1");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`utils::sanitize_key`]: utils/fn.sanitize_key.html
+pub fn load_from_reader_sanitized<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    let content = read_to_string(reader)?;
+    let lines = utils::parse_lines(&content)
+        .into_iter()
+        .map(|(key, value)| (utils::sanitize_key(&key), value))
+        .collect::<Vec<_>>();
+
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, calling
+/// the given function for each line that was skipped.
+///
+/// A line is skipped if it does not parse into a key-value pair, such as a
+/// blank line, a comment, or a malformed line. The function is called with the
+/// 1-indexed line number and the original text of the line. This helps debug
+/// why a variable is not being set, without rejecting such lines.
+///
+/// # Examples
+///
+/// Print each skipped line to stderr:
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("SKIPPED_EXAMPLE=1\nSKIPPED EXAMPLE");
+///
+/// kankyo::load_from_reader_on_skipped(&mut cursor, true, |line, text| {
+///     eprintln!("skipped line {}: {}", line, text);
+/// }).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader_on_skipped<R, F>(
+    reader: &mut R,
+    overwrite: bool,
+    mut on_skipped: F,
+) -> Result<()> where R: Read, F: FnMut(usize, &str) {
+    let content = read_to_string(reader)?;
+    let mut lines = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        match utils::parse_line(line) {
+            Some(parsed) => lines.push(parsed),
+            None => on_skipped(idx + 1, line),
+        }
+    }
+
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// an [`OrderedLoad`] recording the keys in the order that they were set.
+///
+/// Lines of the form `KEY+=value` append to the variable, as described by
+/// [`OrderedLoad`]; appends are applied regardless of `overwrite`. The load
+/// can later be undone in the exact reverse order via [`OrderedLoad::unload`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("ORDERED_EXAMPLE", "/usr/bin");
+///
+/// let mut cursor = Cursor::new("ORDERED_EXAMPLE+=/opt/bin");
+/// let load = kankyo::load_from_reader_ordered(&mut cursor, false).unwrap();
+/// assert_eq!(load.keys(), &["ORDERED_EXAMPLE"]);
+///
+/// load.unload();
+/// assert_eq!(env::var("ORDERED_EXAMPLE").unwrap(), "/usr/bin");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`OrderedLoad`]: struct.OrderedLoad.html
+/// [`OrderedLoad::unload`]: struct.OrderedLoad.html#method.unload
+pub fn load_from_reader_ordered<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<OrderedLoad> {
+    let content = read_to_string(reader)?;
+
+    Ok(OrderedLoad::apply(&utils::parse_lines(&content), overwrite))
+}
+
+/// Reads the content of a reader on a worker thread and parses it to find
+/// `.env` lines, giving up if the read does not complete within the timeout.
+///
+/// This prevents startup from hanging on a reader backed by a stalled source,
+/// such as a pipe or socket.
+///
+/// If the timeout elapses, the worker thread can not be interrupted, and is
+/// left running until the read completes. If the read never completes, the
+/// thread and the reader are leaked. Nothing is loaded once the timeout has
+/// elapsed, even if the read later completes.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use std::time::Duration;
+///
+/// let cursor = Cursor::new("TIMEOUT_EXAMPLE=1");
+/// kankyo::load_from_reader_timeout(cursor, Duration::from_secs(5), true)
+///     .unwrap();
+///
+/// assert_eq!(kankyo::key("TIMEOUT_EXAMPLE").unwrap(), "1");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `TimedOut` if the read did not complete
+/// within the timeout.
+///
+/// Returns an `std::io::Error` of kind `Other` if the reader panicked.
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+pub fn load_from_reader_timeout<R: Read + Send + 'static>(
+    mut reader: R,
+    timeout: Duration,
+    overwrite: bool,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        // The receiver is gone if the timeout elapsed, so nothing can be done
+        // with the content.
+        let _ = tx.send(read_to_string(&mut reader));
+    });
+
+    let content = match rx.recv_timeout(timeout) {
+        Ok(content) => content?,
+        Err(RecvTimeoutError::Timeout) => return Err(IoError::new(
+            ErrorKind::TimedOut,
+            "timed out reading from the reader",
+        )),
+        // The worker thread only drops the sender without sending if the
+        // reader panicked.
+        Err(RecvTimeoutError::Disconnected) => {
+            return Err(IoError::new(
+                ErrorKind::Other,
+                "the reader panicked",
+            ));
+        },
+    };
+
+    utils::set_variables(&utils::parse_lines(&content), overwrite);
+
+    Ok(())
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// a [`Transaction`] recording each change made to the environment.
+///
+/// The changes can later be undone via [`Transaction::rollback`].
+///
+/// # Examples
+///
+/// Load from a reader, and then undo the load:
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("TRANSACTION_EXAMPLE=1");
+/// let transaction = kankyo::load_from_reader_transaction(&mut cursor, true)
+///     .unwrap();
+/// assert!(kankyo::key("TRANSACTION_EXAMPLE").is_some());
+///
+/// transaction.rollback();
+/// assert!(kankyo::key("TRANSACTION_EXAMPLE").is_none());
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`Transaction`]: struct.Transaction.html
+/// [`Transaction::rollback`]: struct.Transaction.html#method.rollback
+pub fn load_from_reader_transaction<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<Transaction> {
+    let content = read_to_string(reader)?;
+
+    Ok(Transaction::apply(&utils::parse_lines(&content), overwrite))
+}
+
+/// Sets a variable only if its current value matches the expected value.
+///
+/// An `expected` value of `None` means that the variable must not currently
+/// exist.
+///
+/// # Examples
+///
+/// Set a variable only if it is not already set:
+///
+/// ```rust
+/// kankyo::set_if_matches("CAS_EXAMPLE", None, "1").unwrap();
+/// assert!(kankyo::set_if_matches("CAS_EXAMPLE", None, "2").is_err());
+///
+/// assert_eq!(kankyo::key("CAS_EXAMPLE").unwrap(), "1");
+/// ```
+///
+/// # Errors
+///
+/// Returns a [`CasError`] containing the actual current value if it does not
+/// match the expected value, or if the key or the new value is not valid to
+/// set in the environment. The variable is left unchanged.
+///
+/// A current value that is not valid unicode never matches, and is contained
+/// in the error with invalid sequences replaced, as by
+/// `OsStr::to_string_lossy`.
+///
+/// [`CasError`]: enum.CasError.html
+pub fn set_if_matches(
+    key: &str,
+    expected: Option<&str>,
+    new: &str,
+) -> StdResult<(), CasError> {
+    if !utils::is_valid_var(key, new) {
+        return Err(CasError::Invalid);
+    }
+
+    // The variable is compared and set under one lock, so that no other load
+    // can change it in between.
+    let locked = LockedEnv::new();
+    let actual = env::var_os(key);
+
+    // A current value that is not valid unicode never matches.
+    let matches = match (&actual, expected) {
+        (Some(actual), Some(expected)) => actual == expected,
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !matches {
+        return Err(CasError::Mismatch {
+            actual: actual.map(|actual| actual.to_string_lossy().into_owned()),
+        });
+    }
+
+    locked.set_var(key, new);
+
+    Ok(())
+}
+
+/// Layers three sources of variables into one map, without reading or
+/// modifying the environment.
+///
+/// The precedence is, from highest to lowest:
+///
+/// 1. `overrides`, such as values given as command-line arguments;
+/// 2. `file`, such as values parsed via [`utils::parse_to_map`];
+/// 3. `defaults`, such as values hardcoded by the application.
+///
+/// A key present in any of the sources is present in the result, with the
+/// value of the source of highest precedence that has it.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::collections::HashMap;
+///
+/// let mut defaults = HashMap::new();
+/// defaults.insert("HOST".to_owned(), "localhost".to_owned());
+/// defaults.insert("PORT".to_owned(), "80".to_owned());
+///
+/// let file = utils::parse_to_map("PORT=8080");
+///
+/// let mut overrides = HashMap::new();
+/// overrides.insert("DEBUG".to_owned(), "1".to_owned());
+///
+/// let resolved = kankyo::resolve(&defaults, &file, &overrides);
+///
+/// assert_eq!(resolved["HOST"], "localhost");
+/// assert_eq!(resolved["PORT"], "8080");
+/// assert_eq!(resolved["DEBUG"], "1");
+/// ```
+///
+/// [`utils::parse_to_map`]: utils/fn.parse_to_map.html
+pub fn resolve(
+    defaults: &HashMap<String, String>,
+    file: &HashMap<String, String>,
+    overrides: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut resolved = defaults.clone();
+
+    for layer in &[file, overrides] {
+        for (key, value) in layer.iter() {
+            resolved.insert(key.clone(), value.clone());
+        }
+    }
+
+    resolved
+}
+
+/// Creates a snapshot of the present environment variables.
+///
+/// This is similar to `std::env::vars`, but will instead return a HashMap over
+/// only the environment variables that are valid UTF-8.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// let snapshot = kankyo::snapshot();
+///
+/// try!(kankyo::load(false));
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+pub fn snapshot() -> HashMap<String, String> {
+    env::vars_os().filter_map(utils::parse_kv).collect()
+}
+
+/// Unloads all environment variables in the default `./.env` file from the
+/// current environment.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// try!(kankyo::load(false));
+/// println!("Loaded!");
+///
+/// try!(kankyo::unload());
+/// println!("Unloaded!");
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+#[inline]
+pub fn unload() -> Result<()> {
+    unload_from_reader(&mut open(".env")?)
+}
+
+/// Unloads the given captured variables, restoring their prior values.
+///
+/// Keys with a prior value are set back to that value, while keys that did not
+/// previously exist are removed. This is the inverse of
+/// [`load_from_reader_capturing`].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("RESTORING_EXAMPLE", "3000");
+///
+/// let mut cursor = Cursor::new("RESTORING_EXAMPLE=8080");
+/// let captured = kankyo::load_from_reader_capturing(&mut cursor, true)
+///     .unwrap();
+/// assert_eq!(env::var("RESTORING_EXAMPLE").unwrap(), "8080");
+///
+/// kankyo::unload_restoring(&captured);
+/// assert_eq!(env::var("RESTORING_EXAMPLE").unwrap(), "3000");
+/// ```
+///
+/// [`load_from_reader_capturing`]: fn.load_from_reader_capturing.html
+pub fn unload_restoring(captured: &HashMap<String, Option<String>>) {
+    let locked = LockedEnv::new();
+
+    for (key, previous) in captured {
+        match *previous {
+            Some(ref value) => locked.set_var(key, value),
+            None => locked.remove_var(key),
+        };
+    }
+}
+
+/// Unloads from the read content of the given reader.
+///
+/// The reader should contain content that of a `.env` file.
+///
+/// If you need to unload a given slice of keys, prefer [`utils::unload`].
+///
+/// # Examples
+///
+/// Unload from a file at the path `./.env`:
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// #
+/// use std::fs::File;
+///
+/// let mut file = try!(File::open("./.env"));
+///
+/// try!(kankyo::unload_from_reader(&mut file));
+///
+/// println!("Successfully unloaded from `./.env`");
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there is an error reading from the reader.
+///
+/// [`utils::unload`]: utils/fn.unload.html
+pub fn unload_from_reader<R: Read>(reader: &mut R) -> Result<()> {
+    let buf = read_to_string(reader)?;
+    let lines = utils::parse_lines(&buf);
+    utils::unload_from_parsed_lines(&lines);
+
+    Ok(())
+}
+
+/// Writes the given parsed lines to a writer as a `.env` file.
+///
+/// Each line is formatted via [`utils::format_line`] and terminated by a
+/// newline, in the order given.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = Vec::new();
+/// kankyo::write_env(&mut out, &[("HOST", "localhost"), ("PORT", "80")])
+///     .unwrap();
+///
+/// assert_eq!(out, b"HOST=localhost\nPORT=80\n");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error writing to the writer.
+///
+/// [`utils::format_line`]: utils/fn.format_line.html
+pub fn write_env<W: Write, K: AsRef<str>>(
+    writer: &mut W,
+    lines: &[(K, &str)],
+) -> Result<()> {
+    for &(ref key, value) in lines {
+        writeln!(writer, "{}", utils::format_line(key.as_ref(), value))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the given parsed lines to a writer as a `.env` file, sorted by key.
+///
+/// This is like [`write_env`], but produces a canonical file: if a key is
+/// defined multiple times, only its last definition is written, and the lines
+/// are sorted by key.
+///
+/// # Examples
+///
+/// ```rust
+/// let mut out = Vec::new();
+/// let lines = [("PORT", "80"), ("HOST", "localhost"), ("PORT", "8080")];
+/// kankyo::write_env_sorted(&mut out, &lines).unwrap();
+///
+/// assert_eq!(out, b"HOST=localhost\nPORT=8080\n");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error writing to the writer.
+///
+/// [`write_env`]: fn.write_env.html
+pub fn write_env_sorted<W: Write, K: AsRef<str>>(
+    writer: &mut W,
+    lines: &[(K, &str)],
+) -> Result<()> {
+    let mut lines = lines
+        .iter()
+        .map(|&(ref key, value)| (key.as_ref(), value))
+        .collect::<HashMap<_, _>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    write_env(writer, &lines)
+}
+
+/// Sets the given variables for the duration of a closure, restoring their
+/// prior values afterwards.
+///
+/// Variables that did not previously exist are removed. The prior values are
+/// restored even if the closure panics. Only the given keys are restored, so
+/// other changes made by the closure are kept.
+///
+/// Invalid variables, such as those with an empty key, are skipped.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::env;
+///
+/// let port = kankyo::with_vars(&[("WITH_VARS_EXAMPLE", "8080")], || {
+///     env::var("WITH_VARS_EXAMPLE").unwrap()
+/// });
+///
+/// assert_eq!(port, "8080");
+/// assert!(env::var("WITH_VARS_EXAMPLE").is_err());
+/// ```
+pub fn with_vars<F: FnOnce() -> T, T>(pairs: &[(&str, &str)], f: F) -> T {
+    let mut guard = RestoreGuard(HashMap::new());
+
+    // The environment is unlocked before calling the closure, which may use
+    // the library.
+    {
+        let locked = LockedEnv::new();
+
+        for &(key, value) in pairs {
+            let previous = env::var(key).ok();
+
+            if locked.set_var(key, value) {
+                guard.0.entry(key.to_owned()).or_insert(previous);
+            }
+        }
+    }
+
+    f()
+}
+
+// Restores captured variables when dropped, including during a panic.
+struct RestoreGuard(HashMap<String, Option<String>>);
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        unload_restoring(&self.0);
+    }
+}
+
+// Returns the path of the first `# kankyo:extends <path>` line in a buffer.
+fn extends_directive(buf: &str) -> Option<&str> {
+    buf.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix('#')?.trim_start();
+        let path = rest.strip_prefix("kankyo:extends")?;
+
+        if path.starts_with(char::is_whitespace) && !path.trim().is_empty() {
+            Some(path.trim())
+        } else {
+            None
+        }
+    })
+}
+
+fn sorted_lines(map: &HashMap<String, String>) -> Vec<(&str, &str)> {
+    let mut lines = map
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    lines
+}
+
+fn load_keys_from_reader<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<Vec<String>> {
+    let content = read_to_string(reader)?;
+
+    Ok(set_variables_collect(&utils::parse_lines(&content), overwrite))
+}
+
+// Sets the given lines into the environment like `utils::set_variables`, but
+// also returns the keys that were set, in order.
+fn set_variables_collect(
+    lines: &[utils::ParsedLine],
+    overwrite: bool,
+) -> Vec<String> {
+    let locked = LockedEnv::new();
+    let mut keys = Vec::with_capacity(lines.len());
+
+    for &(ref key, value) in lines {
+        let key = &**key;
+
+        if !overwrite && env::var_os(key).is_some() {
+            continue;
+        }
+
+        if locked.set_var(key, value) {
+            keys.push(key.to_owned());
+        }
+    }
+
+    keys
+}
+
+fn is_truthy(value: &str) -> bool {
+    let value = value.trim();
+
+    ["1", "true", "yes", "on"]
+        .iter()
+        .any(|truthy| value.eq_ignore_ascii_case(truthy))
+}
+
+// Checks the permissions of the opened file, rather than of its path, so that
+// the file cannot be swapped out between the check and the read.
+#[cfg(unix)]
+fn check_permissions(file: &File) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = file.metadata()?.permissions().mode() & 0o777;
+
+    if mode & 0o077 != 0 {
+        return Err(IoError::new(
+            ErrorKind::PermissionDenied,
+            format!("file mode {:04o} is accessible by group or others", mode),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_: &File) -> Result<()> {
+    Ok(())
+}
+
+// Opens the file at the path, including the path in any error opening or
+// reading it.
+fn open<P: AsRef<Path>>(path: P) -> Result<PathFile> {
+    let path = path.as_ref().to_path_buf();
+
+    match File::open(&path) {
+        Ok(file) => Ok(PathFile { file, path }),
+        Err(err) => Err(PathError::wrap(&path, err)),
+    }
+}
+
+// A file opened by `open`, which includes its path in any error reading it.
+struct PathFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Read for PathFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.file.read(buf).map_err(|err| PathError::wrap(&self.path, err))
+    }
+}
+
+fn read_to_string<R: Read>(reader: &mut R) -> Result<String> {
+    let mut s = String::new();
+    reader.read_to_string(&mut s)?;
+
+    Ok(s)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn test_effective_after_load() {
+        utils::set_variables(&[("EFFECTIVE_EXISTING", "1")], true);
+        let content = "EFFECTIVE_EXISTING=2\nEFFECTIVE_NEW=3\nEFFECTIVE_NEW=4";
+
+        for &overwrite in &[false, true] {
+            let mut cursor = Cursor::new(content);
+            let predicted = effective_after_load(&mut cursor, overwrite)
+                .unwrap();
+            assert!(key("EFFECTIVE_NEW").is_none());
+
+            cursor.set_position(0);
+            let captured = load_from_reader_capturing(&mut cursor, overwrite)
+                .unwrap();
+            let actual = snapshot();
+            unload_restoring(&captured);
+
+            for name in &["EFFECTIVE_EXISTING", "EFFECTIVE_NEW"] {
+                assert_eq!(predicted.get(*name), actual.get(*name));
+            }
+        }
+
+        utils::unload(&["EFFECTIVE_EXISTING"]);
+    }
+
+    #[test]
+    fn test_changes_since() {
+        env::set_var("CHANGES_SINCE_MODIFIED", "1");
+        env::set_var("CHANGES_SINCE_REMOVED", "1");
+        env::set_var("CHANGES_SINCE_SAME", "1");
+
+        let snapshot = snapshot();
+        let buf = "CHANGES_SINCE_SAME=1\nCHANGES_SINCE_MODIFIED=2\n\
+                   CHANGES_SINCE_ADDED=3";
+        load_from_reader(&mut Cursor::new(buf), true).unwrap();
+        env::remove_var("CHANGES_SINCE_REMOVED");
+
+        let changes = changes_since(&snapshot)
+            .into_iter()
+            .filter(|change| match *change {
+                utils::Change::Added { ref key, .. }
+                | utils::Change::Removed { ref key, .. }
+                | utils::Change::Modified { ref key, .. } => {
+                    key.starts_with("CHANGES_SINCE_")
+                },
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(changes, vec![
+            utils::Change::Modified {
+                key: "CHANGES_SINCE_MODIFIED".to_owned(),
+                old: "1".to_owned(),
+                new: "2".to_owned(),
+            },
+            utils::Change::Removed {
+                key: "CHANGES_SINCE_REMOVED".to_owned(),
+                value: "1".to_owned(),
+            },
+            utils::Change::Added {
+                key: "CHANGES_SINCE_ADDED".to_owned(),
+                value: "3".to_owned(),
+            },
+        ]);
+
+        utils::unload(&[
+            "CHANGES_SINCE_ADDED",
+            "CHANGES_SINCE_MODIFIED",
+            "CHANGES_SINCE_SAME",
+        ]);
+    }
+
+    #[test]
+    fn test_config_macro() {
+        config! {
+            struct MacroConfig {
+                url: String = "MACRO_URL",
+                port: u16 = "MACRO_PORT",
+            }
+        }
+
+        let config = "MACRO_URL=http://localhost\nMACRO_PORT=8080".parse()
+            .unwrap();
+        let bound = MacroConfig::from_config(&config).unwrap();
+        assert_eq!(bound.url, "http://localhost");
+        assert_eq!(bound.port, 8080);
+
+        let config = "MACRO_URL=http://localhost".parse().unwrap();
+        let err = MacroConfig::from_config(&config).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "missing required key MACRO_PORT");
+
+        let config = "MACRO_URL=x\nMACRO_PORT=http".parse().unwrap();
+        assert!(MacroConfig::from_config(&config).is_err());
+
+        // A missing `.env` file in the crate root is treated as empty, so
+        // only the environment is used.
+        let err = MacroConfig::load().err().unwrap();
+        assert_eq!(err.to_string(), "missing required key MACRO_URL");
+
+        env::set_var("MACRO_URL", "http://env");
+        env::set_var("MACRO_PORT", "80");
+        let bound = MacroConfig::load().unwrap();
+        assert_eq!(bound.url, "http://env");
+        assert_eq!(bound.port, 80);
+
+        utils::unload(&["MACRO_URL", "MACRO_PORT"]);
+    }
+
+    #[test]
+    fn test_concurrent_loads() {
+        let threads = (0..8)
+            .map(|i| thread::spawn(move || {
+                let buf = format!(
+                    "CONCURRENT_{0}_A={0}\nCONCURRENT_{0}_B={0}\n\
+                     CONCURRENT_SHARED=1",
+                    i,
+                );
+
+                for _ in 0..50 {
+                    load_from_reader(&mut Cursor::new(&buf), true).unwrap();
+                    unload_from_reader(&mut Cursor::new(&buf)).unwrap();
+                }
+
+                load_from_reader(&mut Cursor::new(&buf), true).unwrap();
+            }))
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        for i in 0..8 {
+            let value = i.to_string();
+            let a = format!("CONCURRENT_{}_A", i);
+            let b = format!("CONCURRENT_{}_B", i);
+            assert_eq!(key(&a), Some(value.clone()));
+            assert_eq!(key(&b), Some(value));
+
+            utils::unload(&[&a, &b]);
+        }
+
+        utils::unload(&["CONCURRENT_SHARED"]);
+    }
+
+    #[test]
+    fn test_concurrent_loads_without_overwrite() {
+        // Stand in for another load between checking that the variable does
+        // not exist and setting it.
+        let locked = LockedEnv::new();
+        assert!(key("CONCURRENT_FIRST").is_none());
+
+        let loading = thread::spawn(|| {
+            let mut cursor = Cursor::new("CONCURRENT_FIRST=second");
+            load_from_reader(&mut cursor, false).unwrap();
+        });
+
+        // The load must neither check nor set the variable until the other
+        // load is done, so that it does not overwrite it.
+        thread::sleep(Duration::from_millis(50));
+        assert!(key("CONCURRENT_FIRST").is_none());
+        locked.set_var("CONCURRENT_FIRST", "first");
+        drop(locked);
+
+        loading.join().unwrap();
+        assert_eq!(key("CONCURRENT_FIRST"), Some("first".to_owned()));
+
+        utils::unload(&["CONCURRENT_FIRST"]);
+    }
+
+    #[test]
+    fn test_conflicts_from_reader() {
+        utils::set_variables(&[("CONFLICTS_EXISTING", "1")], true);
+
+        let mut cursor = Cursor::new(
+            "CONFLICTS_NEW=1\nCONFLICTS_EXISTING=2\nCONFLICTS_EXISTING=3",
+        );
+        let conflicts = conflicts_from_reader(&mut cursor).unwrap();
+
+        assert_eq!(conflicts, vec!["CONFLICTS_EXISTING"]);
+        assert_eq!(key("CONFLICTS_EXISTING"), Some("1".to_owned()));
+        assert!(key("CONFLICTS_NEW").is_none());
+
+        utils::unload(&["CONFLICTS_EXISTING"]);
+    }
+
+    #[test]
+    fn test_drift_from_readers() {
+        let mut baseline = Cursor::new("DRIFT_A=1\nDRIFT_B=2");
+        let mut current = Cursor::new("DRIFT_A=1\nDRIFT_B=3");
+        let changes = drift_from_readers(&mut baseline, &mut current).unwrap();
+
+        assert_eq!(changes, vec![utils::Change::Modified {
+            key: "DRIFT_B".to_owned(),
+            old: "2".to_owned(),
+            new: "3".to_owned(),
+        }]);
+        assert!(key("DRIFT_A").is_none());
+    }
+
+    #[test]
+    fn test_exists() {
+        let dir = env::temp_dir()
+            .join(format!("kankyo-exists-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".env");
+
+        assert!(!exists(&path));
+
+        File::create(&path).unwrap();
+        assert!(exists(&path));
+        assert!(!exists(&dir));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load_checking_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir()
+            .join(format!("kankyo-permissions-{}.env", std::process::id()));
+        fs::write(&path, "PERMISSIONS_SECRET=1").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))
+            .unwrap();
+        let err = load_checking_permissions(&path, true).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(key("PERMISSIONS_SECRET").is_none());
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+            .unwrap();
+        load_checking_permissions(&path, true).unwrap();
+        assert_eq!(key("PERMISSIONS_SECRET"), Some("1".to_owned()));
+
+        utils::unload(&["PERMISSIONS_SECRET"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_from_dir() {
+        let dir = env::temp_dir()
+            .join(format!("kankyo-load-dir-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested.env")).unwrap();
+        fs::write(dir.join("20-local.env"), "DIR_B=local\nDIR_C=1").unwrap();
+        fs::write(dir.join("10-base.env"), "DIR_A=1\nDIR_B=base").unwrap();
+        fs::write(dir.join("README.md"), "DIR_SKIPPED=1").unwrap();
+
+        env::set_var("DIR_C", "0");
+
+        let mut keys = load_from_dir(&dir).unwrap();
+        keys.sort();
+        assert_eq!(keys, &["DIR_A", "DIR_B"]);
+        assert_eq!(key("DIR_B"), Some("local".to_owned()));
+        assert_eq!(key("DIR_C"), Some("0".to_owned()));
+        assert!(key("DIR_SKIPPED").is_none());
+
+        utils::unload(&["DIR_A", "DIR_B", "DIR_C"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_with_extends() {
+        let dir = env::temp_dir()
+            .join(format!("kankyo-extends-{}", std::process::id()));
+        fs::create_dir_all(dir.join("app")).unwrap();
+        fs::write(
+            dir.join("base.env"),
+            "EXTENDS_A=base\nEXTENDS_B=base\nEXTENDS_C=base",
+        ).unwrap();
+        fs::write(
+            dir.join("app/shared.env"),
+            "# kankyo:extends ../base.env\nEXTENDS_B=shared",
+        ).unwrap();
+        fs::write(
+            dir.join("app/child.env"),
+            "EXTENDS_C=child\n#kankyo:extends   shared.env  \nEXTENDS_D=1",
+        ).unwrap();
+
+        let mut keys = load_with_extends(dir.join("app/child.env"), true)
+            .unwrap();
+        keys.sort();
+        assert_eq!(keys, &["EXTENDS_A", "EXTENDS_B", "EXTENDS_C", "EXTENDS_D"]);
+        assert_eq!(key("EXTENDS_A"), Some("base".to_owned()));
+        assert_eq!(key("EXTENDS_B"), Some("shared".to_owned()));
+        assert_eq!(key("EXTENDS_C"), Some("child".to_owned()));
+
+        utils::unload(&["EXTENDS_A", "EXTENDS_B", "EXTENDS_C", "EXTENDS_D"]);
+
+        fs::write(
+            dir.join("base.env"),
+            "# kankyo:extends app/child.env\nEXTENDS_A=base",
+        ).unwrap();
+        let err = load_with_extends(dir.join("app/child.env"), true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(key("EXTENDS_A").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_from_env_path() {
+        let path = env::temp_dir()
+            .join(format!("kankyo-env-path-{}.env", std::process::id()));
+        fs::write(&path, "ENV_PATH_A=1\nENV_PATH_B=2").unwrap();
+
+        let keys = load_from_env_path("ENV_PATH_POINTER").unwrap();
+        assert!(keys.is_none());
+
+        env::set_var("ENV_PATH_POINTER", &path);
+        env::set_var("ENV_PATH_A", "0");
+        let keys = load_from_env_path("ENV_PATH_POINTER").unwrap();
+        assert_eq!(keys.unwrap(), &["ENV_PATH_B"]);
+        assert_eq!(key("ENV_PATH_A"), Some("0".to_owned()));
+        assert_eq!(key("ENV_PATH_B"), Some("2".to_owned()));
+
+        utils::unload(&["ENV_PATH_A", "ENV_PATH_B", "ENV_PATH_POINTER"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_entries_from_reader() {
+        let content = "ENTRIES_A=1\n\n# B=2\nC='x # y'\nD=\"\"\nE=z # c";
+        let mut cursor = Cursor::new(content);
+        let entries = entries_from_reader(&mut cursor).unwrap();
+
+        let summary = entries
+            .iter()
+            .map(|e| (e.key.as_str(), e.value.as_str(), e.line, e.quoted))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            ("ENTRIES_A", "1", 1, false),
+            ("C", "x # y", 4, true),
+            ("D", "", 5, true),
+            ("E", "z", 6, false),
+        ]);
+        assert!(key("ENTRIES_A").is_none());
+
+        let err = entries_from_reader(&mut Cursor::new("A=1\nB=\"x"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unterminated quote");
+    }
+
+    #[test]
+    fn test_open_error_includes_path() {
+        let path = env::temp_dir()
+            .join(format!("kankyo-missing-{}.env", std::process::id()));
+        let err = load_from_path(path.to_str().unwrap(), true).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().starts_with(&format!(
+            "failed to read {}: ",
+            path.display(),
+        )));
+
+        let inner = err.get_ref().unwrap().downcast_ref::<PathError>().unwrap();
+        assert_eq!(inner.path, path);
+        assert_eq!(inner.source.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_read_error_includes_path() {
+        let dir = env::temp_dir()
+            .join(format!("kankyo-read-error-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Opening a directory succeeds on unix, but reading it fails.
+        #[cfg(unix)]
+        {
+            let err = load_from_path(dir.to_str().unwrap(), true).unwrap_err();
+            let inner = err.get_ref().unwrap().downcast_ref::<PathError>();
+            assert_eq!(inner.unwrap().path, dir);
+        }
+
+        let missing = dir.join("missing");
+        let err = load_from_dir(&missing).unwrap_err();
+        let inner = err.get_ref().unwrap().downcast_ref::<PathError>();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(inner.unwrap().path, missing);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_from_path() {
+        let dir = env::temp_dir()
+            .join(format!("kankyo-load-path-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".env"), "LOAD_PATH_KEY=1").unwrap();
+
+        env::set_var("LOAD_PATH_DIR", &dir);
+        load_from_path("${LOAD_PATH_DIR}/.env", true).unwrap();
+        assert_eq!(key("LOAD_PATH_KEY"), Some("1".to_owned()));
+
+        let err = load_from_path("$LOAD_PATH_UNSET/.env", true).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        utils::unload(&["LOAD_PATH_DIR", "LOAD_PATH_KEY"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_into_map() {
+        let mut map = HashMap::new();
+        map.insert("INTO_MAP_A".to_owned(), "old".to_owned());
+        map.insert("INTO_MAP_KEEP".to_owned(), "1".to_owned());
+
+        let buf = "INTO_MAP_A=new\nINTO_MAP_B=2 # c\n# INTO_MAP_C=3";
+        load_into_map(&mut Cursor::new(buf), &mut map).unwrap();
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map["INTO_MAP_A"], "new");
+        assert_eq!(map["INTO_MAP_B"], "2");
+        assert_eq!(map["INTO_MAP_KEEP"], "1");
+        assert!(key("INTO_MAP_B").is_none());
+    }
+
+    #[test]
+    fn test_load_lossy() {
+        let content = &b"LOSSY_A=1\nLOSSY_B=\xff\xfeok\nLOSSY_C=3"[..];
+
+        assert!(load_from_reader(&mut Cursor::new(content), true).is_err());
+        assert!(key("LOSSY_A").is_none());
+
+        load_from_reader_lossy(&mut Cursor::new(content), true).unwrap();
+        assert_eq!(key("LOSSY_A"), Some("1".to_owned()));
+        assert_eq!(key("LOSSY_B"), Some("\u{fffd}\u{fffd}ok".to_owned()));
+        assert_eq!(key("LOSSY_C"), Some("3".to_owned()));
+
+        utils::unload(&["LOSSY_A", "LOSSY_B", "LOSSY_C"]);
+    }
+
+    #[test]
+    fn test_load_raw() {
+        let parser = Parser::new().raw(true);
+        let mut cursor = Cursor::new("RAW_COLOR=#fff");
+        load_from_reader_with(&mut cursor, &parser, true).unwrap();
+
+        assert_eq!(key("RAW_COLOR"), Some("#fff".to_owned()));
+
+        utils::unload(&["RAW_COLOR"]);
+    }
+
+    #[test]
+    fn test_load_with_parse_error() {
+        let parser = Parser::new().quotes(true);
+        let mut cursor = Cursor::new("WITH_ERR_A=1\nWITH_ERR_B=\"unterminated");
+
+        let err = load_from_reader_with(&mut cursor, &parser, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"));
+        assert!(key("WITH_ERR_A").is_none());
+    }
+
+    #[test]
+    fn test_load_interpolated() {
+        let content = "INTERP_A=${INTERP_B}\nINTERP_B=2";
+
+        load_from_reader_interpolated(&mut Cursor::new(content), true, false)
+            .unwrap();
+        assert_eq!(key("INTERP_A"), Some("".to_owned()));
+
+        load_from_reader_interpolated(&mut Cursor::new(content), true, true)
+            .unwrap();
+        assert_eq!(key("INTERP_A"), Some("2".to_owned()));
+
+        utils::unload(&["INTERP_A", "INTERP_B"]);
+    }
+
+    #[test]
+    fn test_load_interpolated_local() {
+        utils::set_variables(&[("LOCAL_INTERP_ENV", "env")], true);
+        let content = "LOCAL_INTERP_A=1\n\
+                       LOCAL_INTERP_B=${LOCAL_INTERP_A}${LOCAL_INTERP_ENV}";
+
+        let mut cursor = Cursor::new(content);
+        load_from_reader_interpolated_local(&mut cursor, true, false).unwrap();
+        assert_eq!(key("LOCAL_INTERP_B"), Some("1".to_owned()));
+        utils::unload(&["LOCAL_INTERP_A", "LOCAL_INTERP_B"]);
+
+        let mut cursor = Cursor::new(content);
+        let err = load_from_reader_interpolated_local(&mut cursor, true, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(key("LOCAL_INTERP_A").is_none());
+
+        utils::unload(&["LOCAL_INTERP_ENV"]);
+    }
+
+    #[test]
+    fn test_load_from_proc_environ() {
+        let mut cursor = Cursor::new("ENVIRON_A=1\0ENVIRON_COLOR=#fff\0");
+        let keys = load_from_proc_environ(&mut cursor, true).unwrap();
+
+        assert_eq!(keys, &["ENVIRON_A", "ENVIRON_COLOR"]);
+        assert_eq!(key("ENVIRON_COLOR"), Some("#fff".to_owned()));
+
+        utils::unload(&["ENVIRON_A", "ENVIRON_COLOR"]);
+    }
+
+    #[test]
+    fn test_load_if_env() {
+        assert!(!load_if_env("LOAD_IF_ENV_GUARD", true).unwrap());
+
+        env::set_var("LOAD_IF_ENV_GUARD", "0");
+        assert!(!load_if_env("LOAD_IF_ENV_GUARD", true).unwrap());
+
+        // The crate root has no `.env`, so a truthy guard attempts to read it
+        // and fails.
+        env::set_var("LOAD_IF_ENV_GUARD", " Yes ");
+        let err = load_if_env("LOAD_IF_ENV_GUARD", true).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        env::remove_var("LOAD_IF_ENV_GUARD");
+    }
+
+    #[test]
+    fn test_flag() {
+        let truthy = ["1", "true", "TRUE", "True", "yes", "YES", "on", "ON"];
+        let falsy = ["", "0", "false", "no", "off", "y", "enabled", "2"];
+
+        for value in truthy.iter() {
+            env::set_var("FLAG_TOKEN", value);
+            assert!(flag("FLAG_TOKEN"), "{:?}", value);
+        }
+
+        for value in falsy.iter() {
+            env::set_var("FLAG_TOKEN", value);
+            assert!(!flag("FLAG_TOKEN"), "{:?}", value);
+        }
+
+        env::remove_var("FLAG_TOKEN");
+        assert!(!flag("FLAG_TOKEN"));
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        for value in &["1", "true", "TRUE", "yes", "On", " true\n"] {
+            assert!(is_truthy(value), "{:?}", value);
+        }
+
+        for value in &["", "0", "false", "no", "off", "y", "2"] {
+            assert!(!is_truthy(value), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_load_from_reader_returning() {
+        utils::set_variables(&[("RETURNING_EXISTING", "0")], true);
+
+        let buf = "RETURNING_A=1\n# comment\nRETURNING_EXISTING=1\n\
+                   RETURNING_B = two words # note";
+        let pairs = load_from_reader_returning(&mut Cursor::new(buf), false)
+            .unwrap();
+
+        assert_eq!(pairs, vec![
+            ("RETURNING_A".to_owned(), "1".to_owned()),
+            ("RETURNING_B".to_owned(), "two words".to_owned()),
+        ]);
+        assert_eq!(key("RETURNING_B"), Some("two words".to_owned()));
+        assert_eq!(key("RETURNING_EXISTING"), Some("0".to_owned()));
+
+        utils::unload(&["RETURNING_A", "RETURNING_B", "RETURNING_EXISTING"]);
+    }
+
+    #[test]
+    fn test_load_from_readers() {
+        utils::set_variables(&[("READERS_EXISTING", "0")], true);
+
+        let first = Cursor::new("READERS_A=1\nREADERS_EXISTING=1\nREADERS_B=1");
+        let second = Cursor::new("READERS_B=2\nREADERS_EXISTING=2");
+        let keys = load_from_readers(vec![first, second], false).unwrap();
+
+        assert_eq!(keys, &["READERS_A", "READERS_B"]);
+        assert_eq!(key("READERS_A"), Some("1".to_owned()));
+        assert_eq!(key("READERS_B"), Some("2".to_owned()));
+        assert_eq!(key("READERS_EXISTING"), Some("0".to_owned()));
+
+        // Lines aren't joined across readers without trailing newlines.
+        let mut first = Cursor::new("READERS_C=1");
+        let mut second = Cursor::new("READERS_D=2");
+        load_from_readers(vec![&mut first, &mut second], true).unwrap();
+        assert_eq!(key("READERS_C"), Some("1".to_owned()));
+
+        utils::unload(&[
+            "READERS_A",
+            "READERS_B",
+            "READERS_C",
+            "READERS_D",
+            "READERS_EXISTING",
+        ]);
+    }
+
+    #[test]
+    fn test_load_strict_allowlist() {
+        let allowed = ["STRICT_A", "STRICT_B"];
+
+        let mut cursor = Cursor::new("STRICT_A=1\nSTRICT_B=2");
+        let keys =
+            load_strict_allowlist_from_reader(&mut cursor, &allowed, true)
+                .unwrap();
+        assert_eq!(keys, &["STRICT_A", "STRICT_B"]);
+        utils::unload(&["STRICT_A", "STRICT_B"]);
+
+        let mut cursor = Cursor::new("STRICT_A=1\nSTRICT_EXTRA=2");
+        let err =
+            load_strict_allowlist_from_reader(&mut cursor, &allowed, true)
+                .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("STRICT_EXTRA"));
+        assert!(key("STRICT_A").is_none());
+
+        let inner = err.get_ref().unwrap();
+        let inner = inner.downcast_ref::<DisallowedKeysError>().unwrap();
+        assert_eq!(inner.keys, &["STRICT_EXTRA"]);
+
+        let mut cursor = Cursor::new("STRICT_C=1\nSTRICT_D=2\nSTRICT_C=3");
+        let err =
+            load_strict_allowlist_from_reader(&mut cursor, &allowed, true)
+                .unwrap_err();
+        let inner = err.get_ref().unwrap();
+        let inner = inner.downcast_ref::<DisallowedKeysError>().unwrap();
+        assert_eq!(inner.keys, &["STRICT_C", "STRICT_D"]);
+    }
+
+    #[test]
+    fn test_load_decoded() {
+        let decoders = Decoders::new()
+            .register("*_SUFFIX", |value| Ok(value.to_uppercase()))
+            .register("DECODE_INVALID", |_| {
+                Err(IoError::new(ErrorKind::InvalidData, "invalid"))
+            });
+
+        let content = "DECODE_A_SUFFIX=abc\nDECODE_B=abc\nDECODE_SUFFIX_C=abc";
+        let mut cursor = Cursor::new(content);
+        load_decoded_from_reader(&mut cursor, &decoders, true).unwrap();
+        assert_eq!(key("DECODE_A_SUFFIX"), Some("ABC".to_owned()));
+        assert_eq!(key("DECODE_B"), Some("abc".to_owned()));
+        assert_eq!(key("DECODE_SUFFIX_C"), Some("abc".to_owned()));
+
+        let mut cursor = Cursor::new("DECODE_D=1\nDECODE_INVALID=1");
+        assert!(load_decoded_from_reader(&mut cursor, &decoders, true)
+            .is_err());
+        assert!(key("DECODE_D").is_none());
+
+        utils::unload(&["DECODE_A_SUFFIX", "DECODE_B", "DECODE_SUFFIX_C"]);
+    }
+
+    #[test]
+    fn test_load_transform() {
+        fn decode_base64(input: &str) -> String {
+            const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                      abcdefghijklmnopqrstuvwxyz0123456789+/";
+            let (mut bits, mut count, mut out) = (0u32, 0, Vec::new());
+
+            for byte in input.bytes().take_while(|&b| b != b'=') {
+                let pos = ALPHABET.iter().position(|&c| c == byte).unwrap();
+                bits = (bits << 6) | pos as u32;
+                count += 6;
+
+                if count >= 8 {
+                    count -= 8;
+                    out.push((bits >> count) as u8);
+                }
+            }
+
+            String::from_utf8(out).unwrap()
+        }
+
+        let mut cursor = Cursor::new(
+            "TRANSFORM_SECRET_B64=aHVudGVyMg==\nTRANSFORM_PLAIN=aHVudGVyMg==",
+        );
+        load_transform_from_reader(&mut cursor, true, |key, value| {
+            match key.strip_suffix("_B64") {
+                Some(key) => Some((key.to_owned(), decode_base64(value))),
+                None => Some((key.to_owned(), value.to_owned())),
+            }
+        }).unwrap();
+
+        assert_eq!(key("TRANSFORM_SECRET"), Some("hunter2".to_owned()));
+        assert!(key("TRANSFORM_SECRET_B64").is_none());
+        assert_eq!(key("TRANSFORM_PLAIN"), Some("aHVudGVyMg==".to_owned()));
+        utils::unload(&["TRANSFORM_SECRET", "TRANSFORM_PLAIN"]);
+    }
+
+    #[test]
+    fn test_key() {
+        utils::set_variables(&[("foo", "1")], true);
+        assert!(key("foo").is_some());
+        utils::unload(&["foo"]);
+    }
+
+    #[test]
+    fn test_key_list() {
+        env::set_var("KEY_LIST_ABC", "a, b ,c");
+        env::set_var("KEY_LIST_EMPTY", "");
+        env::set_var("KEY_LIST_GAPS", " ; a;; ");
+
+        assert_eq!(key_list("KEY_LIST_ABC", ','), Some(vec![
+            "a".to_owned(),
+            "b".to_owned(),
+            "c".to_owned(),
+        ]));
+        assert_eq!(key_list("KEY_LIST_ABC", ';'), Some(vec![
+            "a, b ,c".to_owned(),
+        ]));
+        assert_eq!(key_list("KEY_LIST_EMPTY", ','), Some(vec![]));
+        assert_eq!(key_list("KEY_LIST_GAPS", ';'), Some(vec!["a".to_owned()]));
+        assert_eq!(key_list("KEY_LIST_MISSING", ','), None);
+
+        utils::unload(&["KEY_LIST_ABC", "KEY_LIST_EMPTY", "KEY_LIST_GAPS"]);
+    }
+
+    #[test]
+    fn test_reader_loaders() {
+        let text = "A=B\nC=D".to_owned().into_bytes();
+
+        let mut cursor = Cursor::new(text);
+
+        load_from_reader(&mut cursor, true).unwrap();
+
+        cursor.set_position(0);
         unload_from_reader(&mut cursor).unwrap();
     }
 
+    #[test]
+    fn test_keys_matching() {
+        utils::set_variables(&[
+            ("MATCHING_APP_DB_URL", "1"),
+            ("MATCHING_DB", "2"),
+            ("MATCHING_CACHE_URL", "3"),
+        ], true);
+
+        let keys = keys_matching(|k| {
+            k.starts_with("MATCHING_") && k.contains("DB")
+        });
+        assert_eq!(keys, &["MATCHING_APP_DB_URL", "MATCHING_DB"]);
+        assert!(keys_matching(|k| k.contains("MATCHING_NONE")).is_empty());
+
+        utils::unload(&[
+            "MATCHING_APP_DB_URL",
+            "MATCHING_DB",
+            "MATCHING_CACHE_URL",
+        ]);
+    }
+
+    #[test]
+    fn test_require_keys() {
+        utils::set_variables(&[("REQUIRE_A", "1"), ("REQUIRE_C", "")], true);
+
+        assert_eq!(require_keys(&["REQUIRE_A", "REQUIRE_C"]), Ok(()));
+        assert_eq!(require_keys(&[]), Ok(()));
+        assert_eq!(
+            require_keys(&["REQUIRE_B", "REQUIRE_A", "REQUIRE_D"]),
+            Err(vec!["REQUIRE_B".to_owned(), "REQUIRE_D".to_owned()]),
+        );
+
+        utils::unload(&["REQUIRE_A", "REQUIRE_C"]);
+    }
+
+    #[test]
+    fn test_keys_values() {
+        utils::set_variables(&[("KV_A", "1"), ("KV_B", "2")], true);
+
+        let values = keys_values(&["KV_A", "KV_B", "KV_MISSING"]);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["KV_A"], Some("1".to_owned()));
+        assert_eq!(values["KV_B"], Some("2".to_owned()));
+        assert_eq!(values["KV_MISSING"], None);
+
+        utils::unload(&["KV_A", "KV_B"]);
+    }
+
+    #[test]
+    fn test_load_detailed_summary() {
+        utils::set_variables(&[("SUMMARY_EXISTING", "1")], true);
+
+        let mut cursor = Cursor::new(
+            "SUMMARY_A=1\nSUMMARY_B=2\nSUMMARY_EXISTING=secret",
+        );
+        let details = load_from_reader_detailed(&mut cursor, true).unwrap();
+        let summary = details.summary("./.env", false);
+
+        assert_eq!(
+            summary,
+            "Loaded 3 variables from ./.env (1 overwrote existing)",
+        );
+        assert!(!summary.contains("secret"));
+
+        utils::unload(&["SUMMARY_A", "SUMMARY_B", "SUMMARY_EXISTING"]);
+    }
+
+    #[test]
+    fn test_load_echo() {
+        utils::set_variables(&[("ECHO_EXISTING", "1")], true);
+
+        let mut cursor = Cursor::new(
+            "ECHO_A = a # comment\n#ECHO_B=b\nECHO_EXISTING=2\nECHO_C=",
+        );
+        let mut sink = Vec::new();
+        load_from_reader_echo(&mut cursor, false, &mut sink).unwrap();
+
+        assert_eq!(String::from_utf8(sink).unwrap(), "ECHO_A=a\nECHO_C=\n");
+        assert_eq!(key("ECHO_EXISTING"), Some("1".to_owned()));
+        utils::unload(&["ECHO_A", "ECHO_C", "ECHO_EXISTING"]);
+    }
+
+    #[test]
+    fn test_load_section() {
+        let buf = "SECTION_DEFAULT=1\n[app]\nSECTION_A=1\n[env]\nSECTION_B=2";
+        load_section_from_reader(&mut Cursor::new(buf), "env", true).unwrap();
+
+        assert_eq!(key("SECTION_B"), Some("2".to_owned()));
+        assert!(key("SECTION_A").is_none());
+        assert!(key("SECTION_DEFAULT").is_none());
+
+        utils::unload(&["SECTION_B"]);
+    }
+
+    #[test]
+    fn test_load_sanitized() {
+        let buf = "sanitized-a=1\nsanitized.b = 2\nSANITIZED C=3\n\
+                   sanitized_a=4";
+        load_from_reader_sanitized(&mut Cursor::new(buf), true).unwrap();
+
+        assert_eq!(key("SANITIZED_A"), Some("4".to_owned()));
+        assert_eq!(key("SANITIZED_B"), Some("2".to_owned()));
+        assert_eq!(key("SANITIZED_C"), Some("3".to_owned()));
+        assert!(key("sanitized-a").is_none());
+
+        utils::unload(&["SANITIZED_A", "SANITIZED_B", "SANITIZED_C"]);
+    }
+
+    #[test]
+    fn test_load_keys_from_reader() {
+        utils::set_variables(&[("STDIN_EXISTING", "1")], true);
+
+        let buf = "STDIN_A=1\n# STDIN_B=2\nSTDIN_EXISTING=2\nSTDIN_C=3";
+        let keys = load_keys_from_reader(&mut Cursor::new(buf), false)
+            .unwrap();
+
+        assert_eq!(keys, &["STDIN_A", "STDIN_C"]);
+        assert_eq!(key("STDIN_EXISTING"), Some("1".to_owned()));
+
+        utils::unload(&["STDIN_A", "STDIN_C", "STDIN_EXISTING"]);
+    }
+
+    #[test]
+    fn test_load_on_skipped() {
+        let buf = "# header\nON_SKIPPED_A=1\n\nON_SKIPPED MALFORMED\n\
+                   ON_SKIPPED_B=2";
+        let mut skipped = Vec::new();
+
+        load_from_reader_on_skipped(&mut Cursor::new(buf), true, |line, text| {
+            skipped.push((line, text.to_owned()));
+        }).unwrap();
+
+        assert_eq!(skipped, vec![
+            (1, "# header".to_owned()),
+            (3, "".to_owned()),
+            (4, "ON_SKIPPED MALFORMED".to_owned()),
+        ]);
+        assert_eq!(key("ON_SKIPPED_B"), Some("2".to_owned()));
+
+        utils::unload(&["ON_SKIPPED_A", "ON_SKIPPED_B"]);
+    }
+
+    #[test]
+    fn test_load_timeout() {
+        // A reader that blocks until the sender is dropped.
+        struct Blocking(mpsc::Receiver<()>);
+
+        impl Read for Blocking {
+            fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+                let _ = self.0.recv();
+
+                Ok(0)
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let timeout = Duration::from_millis(50);
+        let err = load_from_reader_timeout(Blocking(rx), timeout, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        drop(tx);
+
+        struct Panicking;
+
+        impl Read for Panicking {
+            fn read(&mut self, _: &mut [u8]) -> Result<usize> {
+                panic!("read failed");
+            }
+        }
+
+        let timeout = Duration::from_secs(5);
+        let err = load_from_reader_timeout(Panicking, timeout, true)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+
+        let cursor = Cursor::new("TIMEOUT_LOADED=1");
+        load_from_reader_timeout(cursor, Duration::from_secs(5), true)
+            .unwrap();
+        assert_eq!(key("TIMEOUT_LOADED"), Some("1".to_owned()));
+
+        utils::unload(&["TIMEOUT_LOADED"]);
+    }
+
+    #[test]
+    fn test_parse_typed() {
+        let parsers = TypedParsers::new()
+            .field::<u16>("TYPED_PORT")
+            .field::<bool>("TYPED_DEBUG");
+        let mut cursor = Cursor::new("TYPED_PORT=8080\nTYPED_DEBUG=maybe");
+        let values = parse_typed_from_reader(&mut cursor, &parsers).unwrap();
+
+        assert_eq!(values.get::<u16>("TYPED_PORT"), Some(&8080));
+        assert_eq!(values.get::<bool>("TYPED_DEBUG"), None);
+        assert_eq!(values.errors().len(), 1);
+        assert_eq!(values.errors()[0].0, "TYPED_DEBUG");
+        assert!(key("TYPED_PORT").is_none());
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let before = snapshot();
+
+        let mut cursor = Cursor::new("TXN_A=1\nTXN_B=2");
+        let transaction = load_from_reader_transaction(&mut cursor, true)
+            .unwrap();
+        assert_eq!(key("TXN_A"), Some("1".to_owned()));
+        assert_eq!(transaction.mutations().len(), 2);
+
+        transaction.rollback();
+        assert!(key("TXN_A").is_none());
+        assert!(key("TXN_B").is_none());
+        assert_eq!(snapshot().get("TXN_A"), before.get("TXN_A"));
+    }
+
+    #[test]
+    fn test_unload_restoring() {
+        utils::set_variables(&[("RESTORE_EXISTING", "3000")], true);
+
+        let mut cursor = Cursor::new(
+            "RESTORE_EXISTING=8080\nRESTORE_NEW=1\nRESTORE_EXISTING=9090",
+        );
+        let captured = load_from_reader_capturing(&mut cursor, true).unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(key("RESTORE_EXISTING"), Some("9090".to_owned()));
+        assert_eq!(key("RESTORE_NEW"), Some("1".to_owned()));
+
+        unload_restoring(&captured);
+        assert_eq!(key("RESTORE_EXISTING"), Some("3000".to_owned()));
+        assert!(key("RESTORE_NEW").is_none());
+
+        utils::unload(&["RESTORE_EXISTING"]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let defaults = map(&[("ALL", "default"), ("DEFAULT_ONLY", "d")]);
+        let file = map(&[("ALL", "file"), ("FILE", "f")]);
+        let overrides = map(&[("ALL", "override")]);
+        let resolved = resolve(&defaults, &file, &overrides);
+
+        assert_eq!(resolved, map(&[
+            ("ALL", "override"),
+            ("DEFAULT_ONLY", "d"),
+            ("FILE", "f"),
+        ]));
+        assert!(key("DEFAULT_ONLY").is_none());
+    }
+
+    #[test]
+    fn test_set_if_matches() {
+        // absent
+        assert!(set_if_matches("CAS_KEY", None, "1").is_ok());
+        assert_eq!(key("CAS_KEY"), Some("1".to_owned()));
+
+        // match
+        assert!(set_if_matches("CAS_KEY", Some("1"), "2").is_ok());
+        assert_eq!(key("CAS_KEY"), Some("2".to_owned()));
+
+        // mismatch
+        let mismatch = CasError::Mismatch {
+            actual: Some("2".to_owned()),
+        };
+        let err = set_if_matches("CAS_KEY", Some("1"), "3").unwrap_err();
+        assert_eq!(err, mismatch);
+        let err = set_if_matches("CAS_KEY", None, "3").unwrap_err();
+        assert_eq!(err, mismatch);
+        assert_eq!(key("CAS_KEY"), Some("2".to_owned()));
+
+        // invalid
+        let invalid = Err(CasError::Invalid);
+        assert_eq!(set_if_matches("", None, "1"), invalid);
+        assert_eq!(set_if_matches("CAS=KEY", None, "1"), invalid);
+        assert_eq!(set_if_matches("CAS_KEY", Some("2"), "a\0b"), invalid);
+        assert_eq!(key("CAS_KEY"), Some("2".to_owned()));
+
+        utils::unload(&["CAS_KEY"]);
+        let err = set_if_matches("CAS_KEY", Some("2"), "3").unwrap_err();
+        assert_eq!(err, CasError::Mismatch { actual: None });
+        assert!(key("CAS_KEY").is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            env::set_var("CAS_NON_UNICODE", OsStr::from_bytes(b"\xff"));
+            let err = set_if_matches("CAS_NON_UNICODE", None, "1").unwrap_err();
+            assert_eq!(err, CasError::Mismatch {
+                actual: Some("\u{fffd}".to_owned()),
+            });
+            assert_eq!(
+                env::var_os("CAS_NON_UNICODE").unwrap(),
+                OsStr::from_bytes(b"\xff"),
+            );
+
+            utils::unload(&["CAS_NON_UNICODE"]);
+        }
+    }
+
+    #[test]
+    fn test_write_env() {
+        let lines = [("B", "2"), ("A", "1"), ("C", "x=y"), ("B", "3")];
+
+        let mut out = Vec::new();
+        write_env(&mut out, &lines).unwrap();
+        assert_eq!(out, b"B=2\nA=1\nC=x=y\nB=3\n");
+
+        let mut out = Vec::new();
+        write_env_sorted(&mut out, &lines).unwrap();
+        assert_eq!(out, b"A=1\nB=3\nC=x=y\n");
+
+        let mut out = Vec::new();
+        write_env_sorted::<_, &str>(&mut out, &[]).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_with_vars() {
+        env::set_var("WITH_VARS_EXISTING", "1");
+
+        let value = with_vars(
+            &[("WITH_VARS_EXISTING", "2"), ("WITH_VARS_NEW", "3")],
+            || {
+                assert_eq!(key("WITH_VARS_EXISTING"), Some("2".to_owned()));
+
+                key("WITH_VARS_NEW")
+            },
+        );
+        assert_eq!(value, Some("3".to_owned()));
+        assert_eq!(key("WITH_VARS_EXISTING"), Some("1".to_owned()));
+        assert!(key("WITH_VARS_NEW").is_none());
+
+        let result = std::panic::catch_unwind(|| {
+            with_vars(&[("WITH_VARS_EXISTING", "4")], || panic!("oops"))
+        });
+        assert!(result.is_err());
+        assert_eq!(key("WITH_VARS_EXISTING"), Some("1".to_owned()));
+
+        utils::unload(&["WITH_VARS_EXISTING"]);
+    }
+
     #[test]
     fn test_snapshot() {
         utils::set_variables(&[("A", "B")], true);
@@ -0,0 +1,87 @@
+/// Defines a struct whose fields are bound to keys of a [`Config`], with
+/// methods to load it.
+///
+/// Each field is declared with its type and the key it is bound to. The value
+/// of the key is parsed via the type's `FromStr` implementation, so any such
+/// type can be used, such as `String` or `u16`.
+///
+/// Two methods are generated:
+///
+/// - `from_config(&Config) -> kankyo::Result<Self>`, which binds the fields
+///   from the given config;
+/// - `load() -> kankyo::Result<Self>`, which binds the fields from the
+///   `./.env` file, with variables in the environment taking precedence. If
+///   the file does not exist, the fields are bound from the environment
+///   alone. The environment is not modified.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// #[macro_use]
+/// extern crate kankyo;
+///
+/// config! {
+///     #[derive(Debug)]
+///     pub struct AppConfig {
+///         pub database_url: String = "DATABASE_URL",
+///         pub port: u16 = "PORT",
+///     }
+/// }
+///
+/// # fn main() {
+/// let config = AppConfig::load().unwrap();
+///
+/// println!("Listening on port {}", config.port);
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// The generated methods return an `std::io::Error` of kind `InvalidData`
+/// wrapping a [`SchemaError::Missing`] if a key does not exist, or a
+/// [`ConfigError`] if a value could not be parsed.
+///
+/// `load` also returns an `std::io::Error` if there was an error reading the
+/// `./.env` file, other than it not existing.
+///
+/// [`Config`]: struct.Config.html
+/// [`ConfigError`]: struct.ConfigError.html
+/// [`SchemaError::Missing`]: enum.SchemaError.html#variant.Missing
+#[macro_export]
+macro_rules! config {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty = $key:expr),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($field_vis $field: $ty,)*
+        }
+
+        impl $name {
+            /// Binds the fields from the given config.
+            #[allow(dead_code)]
+            pub fn from_config(
+                config: &$crate::Config,
+            ) -> $crate::Result<Self> {
+                Ok($name {
+                    $($field: match config.get_int::<$ty>($key)? {
+                        Some(value) => value,
+                        None => return Err($crate::SchemaError::Missing(
+                            ($key).to_owned(),
+                        ).into()),
+                    },)*
+                })
+            }
+
+            /// Binds the fields from the `./.env` file, with variables in the
+            /// environment taking precedence.
+            #[allow(dead_code)]
+            pub fn load() -> $crate::Result<Self> {
+                Self::from_config(&$crate::__private::dotenv_config()?)
+            }
+        }
+    };
+}
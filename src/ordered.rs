@@ -0,0 +1,156 @@
+use sink::LockedEnv;
+use std::env;
+use std::ffi::OsString;
+use utils;
+
+/// The keys set by a load in the order that they were set, as returned by
+/// [`load_from_reader_ordered`], which can be unloaded in the exact reverse
+/// order.
+///
+/// Besides `KEY=value`, such a load supports lines of the form `KEY+=value`,
+/// which append the value to the variable as with [`utils::append_path`]. The
+/// result of an append depends on the value prior to it, so the order of
+/// unloading matters when a variable is changed multiple times.
+///
+/// [`load_from_reader_ordered`]: fn.load_from_reader_ordered.html
+/// [`utils::append_path`]: utils/fn.append_path.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OrderedLoad {
+    keys: Vec<String>,
+    // The value of each key prior to it being set, by the same index.
+    previous: Vec<Option<OsString>>,
+}
+
+impl OrderedLoad {
+    /// Loads the given parsed lines into the environment in order, recording
+    /// each key that was set.
+    pub(crate) fn apply<K: AsRef<str>>(
+        lines: &[(K, &str)],
+        overwrite: bool,
+    ) -> Self {
+        let locked = LockedEnv::new();
+        let mut load = OrderedLoad::default();
+
+        for &(ref key, value) in lines {
+            let key = key.as_ref();
+
+            let (key, append) = match key.strip_suffix('+') {
+                Some(key) => (key.trim_end(), true),
+                None => (key, false),
+            };
+
+            if !utils::is_valid_var(key, value) {
+                continue;
+            }
+
+            let previous = env::var_os(key);
+
+            if append {
+                utils::append_path_locked(&locked, key, value);
+            } else if overwrite || previous.is_none() {
+                locked.set_var(key, value);
+            } else {
+                continue;
+            }
+
+            load.keys.push(key.to_owned());
+            load.previous.push(previous);
+        }
+
+        load
+    }
+
+    /// Returns the keys that were set, in the order that they were set.
+    ///
+    /// A key appears once for each line that set or appended to it, so a key
+    /// may appear multiple times. Keys of lines that were skipped, as the
+    /// variable already existed and overwriting was disabled, do not appear.
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Unloads the keys in the reverse order that they were set, restoring
+    /// each to its value prior to the load.
+    ///
+    /// Each change is undone against the state that it was made in, so a
+    /// variable appended to multiple times is restored to its original value.
+    pub fn unload(self) {
+        let changes = self.keys.into_iter().zip(self.previous).rev();
+        let locked = LockedEnv::new();
+
+        for (key, previous) in changes {
+            match previous {
+                Some(value) => locked.set_var(&key, &value),
+                None => locked.remove_var(&key),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+
+    #[test]
+    fn appends_unload_in_reverse() {
+        env::set_var("ORDERED_PATH", "/usr/bin");
+
+        let lines = utils::parse_lines(
+            "ORDERED_PATH+=/opt/bin\nORDERED_NEW=1\nORDERED_PATH += /home/bin",
+        );
+        let load = OrderedLoad::apply(&lines, false);
+        assert_eq!(
+            load.keys(),
+            &["ORDERED_PATH", "ORDERED_NEW", "ORDERED_PATH"],
+        );
+
+        #[cfg(not(windows))]
+        assert_eq!(
+            env::var("ORDERED_PATH").unwrap(),
+            "/usr/bin:/opt/bin:/home/bin",
+        );
+
+        load.unload();
+        assert_eq!(env::var("ORDERED_PATH").unwrap(), "/usr/bin");
+        assert!(env::var("ORDERED_NEW").is_err());
+
+        env::remove_var("ORDERED_PATH");
+    }
+
+    #[test]
+    fn skip() {
+        env::set_var("ORDERED_SKIP", "before");
+
+        let load = OrderedLoad::apply(&[("ORDERED_SKIP", "after")], false);
+        assert!(load.keys().is_empty());
+
+        load.unload();
+        assert_eq!(env::var("ORDERED_SKIP").unwrap(), "before");
+
+        env::remove_var("ORDERED_SKIP");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restores_non_unicode() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let before = OsStr::from_bytes(b"/\xff");
+        env::set_var("ORDERED_NON_UNICODE", before);
+
+        let load = OrderedLoad::apply(&[("ORDERED_NON_UNICODE", "1")], false);
+        assert!(load.keys().is_empty());
+        assert_eq!(env::var_os("ORDERED_NON_UNICODE").unwrap(), before);
+
+        let lines = [("ORDERED_NON_UNICODE", "1")];
+        let load = OrderedLoad::apply(&lines, true);
+        assert_eq!(env::var("ORDERED_NON_UNICODE").unwrap(), "1");
+
+        load.unload();
+        assert_eq!(env::var_os("ORDERED_NON_UNICODE").unwrap(), before);
+
+        env::remove_var("ORDERED_NON_UNICODE");
+    }
+}
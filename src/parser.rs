@@ -0,0 +1,1663 @@
+use error::{ParseError, ParseErrorKind};
+use interpolate;
+use sink::LockedEnv;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::result::Result as StdResult;
+use utils;
+
+/// An instruction parsed from a .env file line by a [`Parser`].
+///
+/// [`Parser`]: struct.Parser.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Directive<'a> {
+    /// Sets the key to the value.
+    ///
+    /// The key and value borrow from the line unless they had to be unescaped.
+    Set(Cow<'a, str>, Cow<'a, str>),
+    /// Removes the key from the environment.
+    Unset(Cow<'a, str>),
+}
+
+impl<'a> Directive<'a> {
+    /// Returns the key that the directive applies to.
+    pub fn key(&self) -> &str {
+        match *self {
+            Directive::Set(ref key, _) | Directive::Unset(ref key) => key,
+        }
+    }
+}
+
+/// The directives of a buffer split into keys to set and keys to unset, as
+/// returned by [`Parser::parse_overrides`].
+///
+/// Each key appears at most once across both lists, taking its last
+/// directive in the buffer.
+///
+/// [`Parser::parse_overrides`]: struct.Parser.html#method.parse_overrides
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Overrides<'a> {
+    /// The keys to set, with their values, in order.
+    pub set: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    /// The keys to unset, in order.
+    pub unset: Vec<Cow<'a, str>>,
+}
+
+impl<'a> Overrides<'a> {
+    /// Applies the sets and unsets to the environment in one pass.
+    ///
+    /// If `overwrite` is `false`, then existing variables are neither
+    /// overwritten nor removed, as with [`utils::set_directives`].
+    ///
+    /// [`utils::set_directives`]: utils/fn.set_directives.html
+    pub fn apply(&self, overwrite: bool) {
+        let locked = LockedEnv::new();
+
+        for (key, value) in &self.set {
+            if overwrite || env::var_os(&**key).is_none() {
+                locked.set_var(key, value.as_ref());
+            }
+        }
+
+        if overwrite {
+            for key in &self.unset {
+                locked.remove_var(key);
+            }
+        }
+    }
+}
+
+/// The directives and errors of a buffer, as returned by
+/// [`Parser::parse_lines_collect_errors_capped`].
+///
+/// [`Parser::parse_lines_collect_errors_capped`]: struct.Parser.html#method.parse_lines_collect_errors_capped
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ParseReport<'a> {
+    /// The directives of the lines that parsed, in order.
+    pub directives: Vec<Directive<'a>>,
+    /// The errors of the lines that were malformed, in order.
+    pub errors: Vec<ParseError>,
+    /// Whether parsing stopped early as there were more errors than the cap.
+    pub truncated: bool,
+}
+
+/// A preset of [`Parser`] options matching the parsing rules of another .env
+/// implementation, for use with [`Parser::with_dialect`].
+///
+/// [`Parser`]: struct.Parser.html
+/// [`Parser::with_dialect`]: struct.Parser.html#method.with_dialect
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    /// The default rules of this library, identical to [`Parser::new`].
+    ///
+    /// [`Parser::new`]: struct.Parser.html#method.new
+    Kankyo,
+    /// The rules of the Ruby `dotenv` gem:
+    ///
+    /// - a line may begin with `export`, which is ignored;
+    /// - values may be quoted, where single-quoted values are literal and
+    ///   double-quoted values may contain escape sequences such as `\n`;
+    /// - a `#` in an unquoted value only starts a comment when it is preceded
+    ///   by whitespace.
+    ///
+    /// Unlike the gem, references to other variables are not expanded. Use the
+    /// [`interpolate`] module for that.
+    ///
+    /// [`interpolate`]: interpolate/index.html
+    RubyDotenv,
+    /// The rules of Docker Compose:
+    ///
+    /// - quotes and escape sequences have no special meaning, and are part of
+    ///   the value;
+    /// - only a line that begins with a `#` is a comment, and a `#` in a value
+    ///   is kept;
+    /// - references to other variables, such as `${NAME}`, are expanded.
+    Docker,
+}
+
+impl Dialect {
+    /// Heuristically detects the dialect that a buffer is written in.
+    ///
+    /// The buffer is detected as [`RubyDotenv`] if any line begins with
+    /// `export` or has a quoted value, and as [`Docker`] if any value contains
+    /// a `${NAME}` reference. The detection is conservative: if there are
+    /// signs of neither or of both, the buffer is detected as [`Kankyo`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::Dialect;
+    ///
+    /// assert_eq!(Dialect::detect("export A=1"), Dialect::RubyDotenv);
+    /// assert_eq!(Dialect::detect("URL=${HOST}/x"), Dialect::Docker);
+    /// assert_eq!(Dialect::detect("A=1"), Dialect::Kankyo);
+    /// ```
+    ///
+    /// [`Docker`]: #variant.Docker
+    /// [`Kankyo`]: #variant.Kankyo
+    /// [`RubyDotenv`]: #variant.RubyDotenv
+    pub fn detect(buf: &str) -> Self {
+        let mut ruby = false;
+        let mut docker = false;
+
+        for line in buf.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let unexported = strip_export(line);
+            ruby |= unexported.len() != line.len();
+
+            if let Some(equals) = utils::find_separator(unexported) {
+                let value = unexported[equals + 1..].trim_start();
+                ruby |= value.starts_with(['"', '\'']);
+                docker |= value.contains("${");
+            }
+        }
+
+        match (ruby, docker) {
+            (true, false) => Dialect::RubyDotenv,
+            (false, true) => Dialect::Docker,
+            _ => Dialect::Kankyo,
+        }
+    }
+}
+
+/// A configurable parser for .env files.
+///
+/// By default, this parses lines identically to [`utils::parse_line`], with
+/// options to opt in to other behaviour.
+///
+/// # Examples
+///
+/// Parse a buffer where a bare key unsets the variable:
+///
+/// ```rust
+/// use kankyo::{Directive, Parser};
+///
+/// let parser = Parser::new().unset_directives(true);
+/// let directives = parser.parse_lines("FOO=bar\nBAZ").unwrap();
+///
+/// assert_eq!(directives, vec![
+///     Directive::Set("FOO".into(), "bar".into()),
+///     Directive::Unset("BAZ".into()),
+/// ]);
+/// ```
+///
+/// [`utils::parse_line`]: utils/fn.parse_line.html
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    colon_separators: bool,
+    empty_unsets: bool,
+    escapes: bool,
+    export_prefix: bool,
+    ignore_lines: Option<fn(&str) -> bool>,
+    interpolate: bool,
+    lenient_quotes: bool,
+    quotes: bool,
+    raw: bool,
+    skip_comment_values: bool,
+    spaced_comments: bool,
+    trim_chars: Option<&'static [char]>,
+    unset_directives: bool,
+}
+
+impl Parser {
+    /// Creates a new parser with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new parser with the options of the given dialect.
+    ///
+    /// The options can be further changed after creation.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Dialect, Directive, Parser};
+    ///
+    /// let parser = Parser::with_dialect(Dialect::RubyDotenv);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("export TOKEN=abc#123"),
+    ///     Ok(Some(Directive::Set("TOKEN".into(), "abc#123".into()))),
+    /// );
+    /// ```
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        match dialect {
+            Dialect::Docker => Self::new().interpolate(true).raw(true),
+            Dialect::Kankyo => Self::new(),
+            Dialect::RubyDotenv => Self::new()
+                .escapes(true)
+                .export_prefix(true)
+                .quotes(true)
+                .spaced_comments(true),
+        }
+    }
+
+    /// Sets whether a colon (`:`) may separate a key from its value, in
+    /// addition to an equals sign, so that YAML-like lines such as
+    /// `KEY: value` parse.
+    ///
+    /// Whichever of the two separators appears first in a line is used, so
+    /// `URL: http://host/?a=b` parses as the key `URL`. Keys can therefore not
+    /// contain a colon while this is enabled.
+    ///
+    /// Leading whitespace, including tabs, is always ignored regardless of this
+    /// option.
+    ///
+    /// By default this is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().colon_separators(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("\tKEY: value # comment"),
+    ///     Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+    /// );
+    /// ```
+    pub fn colon_separators(mut self, enabled: bool) -> Self {
+        self.colon_separators = enabled;
+
+        self
+    }
+
+    /// Sets whether a line with an empty value, such as `KEY=`, is an
+    /// instruction to unset that key.
+    ///
+    /// A value is empty if nothing but whitespace or a comment follows the
+    /// equals sign. A quoted empty value, such as `KEY=""` while quotes are
+    /// enabled, still sets the key to an empty value.
+    ///
+    /// By default this is disabled, and such lines set the key to an empty
+    /// value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().empty_unsets(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("KEY= # none"),
+    ///     Ok(Some(Directive::Unset("KEY".into()))),
+    /// );
+    /// ```
+    pub fn empty_unsets(mut self, enabled: bool) -> Self {
+        self.empty_unsets = enabled;
+
+        self
+    }
+
+    /// Sets whether escape sequences in double-quoted values are expanded.
+    ///
+    /// The escape sequences are `\n`, `\r`, `\t`, `\"`, and `\\`; any other
+    /// backslash is kept as-is. Single-quoted values are always literal.
+    ///
+    /// This only has an effect when [`quotes`] are enabled.
+    ///
+    /// By default this is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().quotes(true).escapes(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line(r#"KEY="a\nb""#),
+    ///     Ok(Some(Directive::Set("KEY".into(), "a\nb".into()))),
+    /// );
+    /// ```
+    ///
+    /// [`quotes`]: #method.quotes
+    pub fn escapes(mut self, enabled: bool) -> Self {
+        self.escapes = enabled;
+
+        self
+    }
+
+    /// Sets whether a line may begin with `export`, as in a shell script, in
+    /// which case the `export` is ignored.
+    ///
+    /// By default this is disabled, and such lines have a key beginning with
+    /// `export`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().export_prefix(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("export KEY=value"),
+    ///     Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+    /// );
+    /// ```
+    pub fn export_prefix(mut self, enabled: bool) -> Self {
+        self.export_prefix = enabled;
+
+        self
+    }
+
+    /// Sets a predicate for lines that are ignored entirely, like blank and
+    /// comment lines, such as decorative separators made of dashes.
+    ///
+    /// The predicate is given the whole line, before anything else is parsed,
+    /// so an ignored line never produces a directive.
+    ///
+    /// By default this is `None`, and only blank and comment lines are
+    /// ignored. Note that no option makes a line such as `----` an error: it
+    /// has no equals sign, and so is skipped, or is an unset directive if
+    /// [`unset_directives`] is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new()
+    ///     .ignore_lines(Some(|line| line.trim().bytes().all(|b| b == b'-')))
+    ///     .unset_directives(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_lines("A=1\n----------\nB=2"),
+    ///     Ok(vec![
+    ///         Directive::Set("A".into(), "1".into()),
+    ///         Directive::Set("B".into(), "2".into()),
+    ///     ]),
+    /// );
+    /// ```
+    ///
+    /// [`unset_directives`]: #method.unset_directives
+    pub fn ignore_lines(mut self, predicate: Option<fn(&str) -> bool>) -> Self {
+        self.ignore_lines = predicate;
+
+        self
+    }
+
+    /// Sets whether references to other variables in values, such as
+    /// `${NAME}`, are expanded.
+    ///
+    /// References use the syntax of the [`interpolate`] module. When parsing a
+    /// buffer with [`parse_lines`], a reference resolves to the value of the
+    /// closest earlier line with that key, falling back to the environment.
+    /// When parsing a single line with [`parse_line`], a reference always
+    /// resolves from the environment. A reference to a variable that is not
+    /// defined expands to an empty string.
+    ///
+    /// By default this is disabled, and references are kept as-is.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().interpolate(true);
+    /// let directives = parser.parse_lines("HOST=db\nURL=http://${HOST}");
+    ///
+    /// assert_eq!(
+    ///     directives.unwrap()[1],
+    ///     Directive::Set("URL".into(), "http://db".into()),
+    /// );
+    /// ```
+    ///
+    /// [`interpolate`]: interpolate/index.html
+    /// [`parse_line`]: #method.parse_line
+    /// [`parse_lines`]: #method.parse_lines
+    pub fn interpolate(mut self, enabled: bool) -> Self {
+        self.interpolate = enabled;
+
+        self
+    }
+
+    /// Sets whether a quote that is not closed is recovered from, rather than
+    /// being an error.
+    ///
+    /// This only has an effect when [`quotes`] are enabled. When recovering,
+    /// the opening quote is stripped and the rest of the line is used as the
+    /// value, with trailing whitespace trimmed. As the value is still
+    /// considered to be quoted, a `#` in it does _not_ start a comment.
+    /// Characters after a closing quote are also ignored rather than being an
+    /// error.
+    ///
+    /// By default this is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let strict = Parser::new().quotes(true);
+    /// assert!(strict.parse_line("KEY=\"unterminated").is_err());
+    ///
+    /// let lenient = strict.lenient_quotes(true);
+    /// assert_eq!(
+    ///     lenient.parse_line("KEY=\"unterminated # not a comment"),
+    ///     Ok(Some(Directive::Set(
+    ///         "KEY".into(),
+    ///         "unterminated # not a comment".into(),
+    ///     ))),
+    /// );
+    /// ```
+    ///
+    /// [`quotes`]: #method.quotes
+    pub fn lenient_quotes(mut self, enabled: bool) -> Self {
+        self.lenient_quotes = enabled;
+
+        self
+    }
+
+    /// Sets whether values may be surrounded by single (`'`) or double (`"`)
+    /// quotes.
+    ///
+    /// The quotes are not part of the value, and a `#` within them does not
+    /// start a comment. Only whitespace or a comment may follow the closing
+    /// quote.
+    ///
+    /// By default this is disabled, and quotes are part of the value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().quotes(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("COLOR=\"#fff\" # white"),
+    ///     Ok(Some(Directive::Set("COLOR".into(), "#fff".into()))),
+    /// );
+    /// ```
+    pub fn quotes(mut self, enabled: bool) -> Self {
+        self.quotes = enabled;
+
+        self
+    }
+
+    /// Sets whether values are taken verbatim up to the end of the line, with
+    /// no comment handling.
+    ///
+    /// This is useful for files where `#` is a legitimate character in every
+    /// value, such as colors or hashes, without needing to quote each value.
+    /// Lines that begin with a `#` are still skipped as comments.
+    ///
+    /// By default this is disabled, and a `#` in a value starts a comment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().raw(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("COLOR=#fff # white"),
+    ///     Ok(Some(Directive::Set("COLOR".into(), "#fff # white".into()))),
+    /// );
+    /// assert_eq!(parser.parse_line("# COLOR=#000"), Ok(None));
+    /// ```
+    pub fn raw(mut self, enabled: bool) -> Self {
+        self.raw = enabled;
+
+        self
+    }
+
+    /// Sets whether a line whose value is entirely a comment, such as
+    /// `KEY=# comment`, is skipped rather than setting the key to an empty
+    /// value.
+    ///
+    /// A line with no value and no comment, such as `KEY=`, still sets the key
+    /// to an empty value.
+    ///
+    /// By default this is disabled, and such lines set the key to an empty
+    /// value, as with [`utils::parse_line`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().skip_comment_values(true);
+    ///
+    /// assert_eq!(parser.parse_line("KEY=# set me"), Ok(None));
+    /// assert_eq!(
+    ///     parser.parse_line("KEY="),
+    ///     Ok(Some(Directive::Set("KEY".into(), "".into()))),
+    /// );
+    /// ```
+    ///
+    /// [`utils::parse_line`]: utils/fn.parse_line.html
+    pub fn skip_comment_values(mut self, enabled: bool) -> Self {
+        self.skip_comment_values = enabled;
+
+        self
+    }
+
+    /// Sets whether a `#` in an unquoted value only starts a comment when it is
+    /// preceded by whitespace.
+    ///
+    /// A line that begins with a `#` is always a comment.
+    ///
+    /// By default this is disabled, and a `#` anywhere in a value starts a
+    /// comment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().spaced_comments(true);
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("URL=http://host/#anchor # comment"),
+    ///     Ok(Some(Directive::Set(
+    ///         "URL".into(),
+    ///         "http://host/#anchor".into(),
+    ///     ))),
+    /// );
+    /// ```
+    pub fn spaced_comments(mut self, enabled: bool) -> Self {
+        self.spaced_comments = enabled;
+
+        self
+    }
+
+    /// Sets the characters that are trimmed from the start and end of keys and
+    /// unquoted values, rather than all Unicode whitespace.
+    ///
+    /// This allows keeping characters such as non-breaking spaces that are
+    /// part of a value, by only trimming spaces and tabs. The contents of a
+    /// quoted value are never trimmed.
+    ///
+    /// By default this is `None`, and keys and values are trimmed with
+    /// `str::trim`, as with [`utils::parse_line`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::{Directive, Parser};
+    ///
+    /// let parser = Parser::new().trim_chars(Some(&[' ', '\t']));
+    ///
+    /// assert_eq!(
+    ///     parser.parse_line("KEY = \u{a0}value\u{a0} # comment"),
+    ///     Ok(Some(Directive::Set("KEY".into(), "\u{a0}value\u{a0}".into()))),
+    /// );
+    /// ```
+    ///
+    /// [`utils::parse_line`]: utils/fn.parse_line.html
+    pub fn trim_chars(mut self, chars: Option<&'static [char]>) -> Self {
+        self.trim_chars = chars;
+
+        self
+    }
+
+    /// Sets whether a line consisting of only a key, with no equals sign,
+    /// is an instruction to unset that key.
+    ///
+    /// A line such as `KEY=` always sets the key to an empty value; it is only
+    /// the bare `KEY` form that unsets. The key may be followed by a comment,
+    /// but must not contain whitespace.
+    ///
+    /// By default this is disabled, and such lines are ignored.
+    pub fn unset_directives(mut self, enabled: bool) -> Self {
+        self.unset_directives = enabled;
+
+        self
+    }
+
+    /// Parses a .env file line into a directive.
+    ///
+    /// Returns `None` if the line is blank, a comment, or otherwise does not
+    /// parse.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseErrorKind`] if the line is malformed in a way that
+    /// the parser has been configured to not recover from.
+    ///
+    /// [`ParseErrorKind`]: enum.ParseErrorKind.html
+    pub fn parse_line<'a>(
+        &self,
+        line: &'a str,
+    ) -> StdResult<Option<Directive<'a>>, ParseErrorKind> {
+        let directive = self.parse_uninterpolated(line)?;
+
+        Ok(directive.map(|d| self.expand(d, |name| env::var(name).ok())))
+    }
+
+    /// Parses each line of a buffer into directives, skipping lines that do
+    /// not parse.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] for the first line that is malformed in a way
+    /// that the parser has been configured to not recover from.
+    ///
+    /// [`ParseError`]: struct.ParseError.html
+    pub fn parse_lines<'a>(
+        &self,
+        buf: &'a str,
+    ) -> StdResult<Vec<Directive<'a>>, ParseError> {
+        let mut err = None;
+        let directives = self.parse_lines_until(buf, |why| {
+            err = Some(why);
+
+            false
+        });
+
+        match err {
+            Some(err) => Err(err),
+            None => Ok(directives),
+        }
+    }
+
+    /// Parses each line of a buffer like [`parse_lines`], collecting the
+    /// errors of malformed lines rather than returning the first.
+    ///
+    /// At most `max_errors` errors are collected. Parsing stops at the first
+    /// error past the cap, marking the report as truncated, so that linting a
+    /// very large broken buffer stays responsive. The directives of the lines
+    /// before that point are still returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::Parser;
+    ///
+    /// let parser = Parser::new().quotes(true);
+    /// let report = parser.parse_lines_collect_errors_capped(
+    ///     "A=\"1\nB=2\nC=\"3\nD=\"4\nE=5",
+    ///     2,
+    /// );
+    ///
+    /// assert_eq!(report.directives.len(), 1);
+    /// assert_eq!(report.errors[1].line, 3);
+    /// assert!(report.truncated);
+    /// ```
+    ///
+    /// [`parse_lines`]: #method.parse_lines
+    pub fn parse_lines_collect_errors_capped<'a>(
+        &self,
+        buf: &'a str,
+        max_errors: usize,
+    ) -> ParseReport<'a> {
+        let mut errors = Vec::new();
+        let mut truncated = false;
+
+        let directives = self.parse_lines_until(buf, |why| {
+            if errors.len() < max_errors {
+                errors.push(why);
+            } else {
+                truncated = true;
+            }
+
+            !truncated
+        });
+
+        ParseReport {
+            directives,
+            errors,
+            truncated,
+        }
+    }
+
+    /// Parses each line of a buffer like [`parse_lines`], splitting the
+    /// directives into keys to set and keys to unset.
+    ///
+    /// This is useful with [`unset_directives`] or [`empty_unsets`] for
+    /// override files, which both set and unset keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::Parser;
+    ///
+    /// let parser = Parser::new().unset_directives(true);
+    /// let overrides = parser.parse_overrides("HOST=localhost\nDEBUG")
+    ///     .unwrap();
+    ///
+    /// assert_eq!(overrides.set, vec![("HOST".into(), "localhost".into())]);
+    /// assert_eq!(overrides.unset, vec!["DEBUG"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] for the first line that is malformed in a way
+    /// that the parser has been configured to not recover from.
+    ///
+    /// [`ParseError`]: struct.ParseError.html
+    /// [`empty_unsets`]: #method.empty_unsets
+    /// [`parse_lines`]: #method.parse_lines
+    /// [`unset_directives`]: #method.unset_directives
+    pub fn parse_overrides<'a>(
+        &self,
+        buf: &'a str,
+    ) -> StdResult<Overrides<'a>, ParseError> {
+        let mut overrides = Overrides::default();
+        let mut seen = HashSet::new();
+
+        // Only the last directive of each key is kept, so the directives are
+        // visited from last to first and the lists reversed afterwards.
+        for directive in self.parse_lines(buf)?.into_iter().rev() {
+            match directive {
+                Directive::Set(key, value) => {
+                    if seen.insert(key.clone()) {
+                        overrides.set.push((key, value));
+                    }
+                },
+                Directive::Unset(key) => {
+                    if seen.insert(key.clone()) {
+                        overrides.unset.push(key);
+                    }
+                },
+            }
+        }
+
+        overrides.set.reverse();
+        overrides.unset.reverse();
+
+        Ok(overrides)
+    }
+
+    // Parses each line of a buffer into directives, passing the error of each
+    // malformed line to the callback, and stopping if it returns `false`.
+    fn parse_lines_until<'a, F>(
+        &self,
+        buf: &'a str,
+        mut on_error: F,
+    ) -> Vec<Directive<'a>>
+        where F: FnMut(ParseError) -> bool {
+        let mut directives = Vec::new();
+        // The values of the keys defined so far, for interpolation.
+        let mut seen: HashMap<Cow<str>, String> = HashMap::new();
+
+        for (idx, line) in buf.lines().enumerate() {
+            let directive = match self.parse_uninterpolated(line) {
+                Ok(Some(directive)) => directive,
+                Ok(None) => continue,
+                Err(kind) => {
+                    let err = ParseError {
+                        kind,
+                        line: idx + 1,
+                    };
+
+                    if on_error(err) {
+                        continue;
+                    }
+
+                    break;
+                },
+            };
+
+            let directive = self.expand(directive, |name| {
+                seen.get(name).cloned().or_else(|| env::var(name).ok())
+            });
+
+            if self.interpolate {
+                match directive {
+                    Directive::Set(ref key, ref value) => {
+                        seen.insert(key.clone(), value.to_string());
+                    },
+                    Directive::Unset(ref key) => {
+                        seen.remove(key);
+                    },
+                }
+            }
+
+            directives.push(directive);
+        }
+
+        directives
+    }
+
+    // Expands the references in the value of a directive if interpolation is
+    // enabled, only allocating if there are any.
+    fn expand<'a, F>(
+        &self,
+        directive: Directive<'a>,
+        lookup: F,
+    ) -> Directive<'a>
+        where F: FnMut(&str) -> Option<String> {
+        match directive {
+            Directive::Set(key, value) if self.interpolate
+                && value.contains('$') => {
+                let value = interpolate::expand(&value, lookup);
+
+                Directive::Set(key, Cow::Owned(value))
+            },
+            directive => directive,
+        }
+    }
+
+    fn parse_uninterpolated<'a>(
+        &self,
+        line: &'a str,
+    ) -> StdResult<Option<Directive<'a>>, ParseErrorKind> {
+        if self.ignore_lines.map_or(false, |ignore| ignore(line)) {
+            return Ok(None);
+        }
+
+        let line = if self.export_prefix {
+            strip_export(line)
+        } else {
+            line
+        };
+
+        if self.quotes {
+            if let Some(directive) = self.parse_quoted(line)? {
+                return Ok(Some(directive));
+            }
+        }
+
+        if self.colon_separators {
+            if let Some((key, value)) = parse_colon(line) {
+                let directive = self.set(line, key, value);
+
+                return Ok(Some(self.unset_if_empty(directive)));
+            }
+        }
+
+        let pair = if self.raw {
+            parse_raw(line)
+        } else if self.spaced_comments {
+            parse_spaced_comments(line)
+        } else {
+            utils::split_line(line)
+        };
+
+        if let Some((key, value)) = pair {
+            if self.skip_comment_values && is_comment_value(line, value) {
+                return Ok(None);
+            }
+
+            let directive = self.set(line, key, value);
+
+            return Ok(Some(self.unset_if_empty(directive)));
+        }
+
+        if self.unset_directives && utils::find_separator(line).is_none() {
+            let key = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            }.trim();
+
+            if !key.is_empty() && !key.contains(char::is_whitespace) {
+                let key = utils::unescape_key(self.retrim(line, key));
+
+                return Ok(Some(Directive::Unset(key)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Turns a directive setting an empty value into one unsetting the key if
+    // enabled.
+    fn unset_if_empty<'a>(&self, directive: Directive<'a>) -> Directive<'a> {
+        match directive {
+            Directive::Set(key, ref value) if self.empty_unsets
+                && value.is_empty() => Directive::Unset(key),
+            directive => directive,
+        }
+    }
+
+    // Parses a line whose value begins with a quote, returning `None` if it
+    // does not.
+    pub(crate) fn parse_quoted<'a>(
+        &self,
+        line: &'a str,
+    ) -> StdResult<Option<Directive<'a>>, ParseErrorKind> {
+        let equals = match utils::find_separator(line) {
+            Some(equals) => equals,
+            None => return Ok(None),
+        };
+        let key = &line[..equals];
+
+        if key.contains('#') {
+            return Ok(None);
+        }
+
+        let rest = line[equals + 1..].trim_start();
+        let quote = match rest.chars().next() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Ok(None),
+        };
+        let inner = &rest[1..];
+        let escapes = self.escapes && quote == '"';
+
+        let end = if escapes {
+            utils::find_closing_quote(inner)
+        } else {
+            inner.find(quote)
+        };
+
+        let value = match end {
+            Some(end) => {
+                let trailing = inner[end + 1..].trim_start();
+
+                if !self.lenient_quotes
+                    && !trailing.is_empty()
+                    && !trailing.starts_with('#') {
+                    return Err(ParseErrorKind::TrailingCharacters);
+                }
+
+                &inner[..end]
+            },
+            None if self.lenient_quotes => inner.trim_end(),
+            None => return Err(ParseErrorKind::UnterminatedQuote),
+        };
+
+        let value = if escapes {
+            utils::unescape(value)
+        } else {
+            Cow::Borrowed(value)
+        };
+
+        let key = utils::unescape_key(self.retrim(line, key.trim()));
+
+        Ok(Some(Directive::Set(key, value)))
+    }
+
+    // Creates a directive setting the key to the value, both being slices of
+    // the line, with the key unescaped.
+    fn set<'a>(
+        &self,
+        line: &'a str,
+        key: &'a str,
+        value: &'a str,
+    ) -> Directive<'a> {
+        Directive::Set(
+            utils::unescape_key(self.retrim(line, key)),
+            Cow::Borrowed(self.retrim(line, value)),
+        )
+    }
+
+    // Trims a key or value that was trimmed with `str::trim` again with the
+    // configured trim characters, if any.
+    //
+    // The trimmed slice is first widened back over the whitespace that was
+    // trimmed from it within the line.
+    fn retrim<'a>(&self, line: &'a str, trimmed: &'a str) -> &'a str {
+        let chars = match self.trim_chars {
+            Some(chars) => chars,
+            None => return trimmed,
+        };
+
+        let start = trimmed.as_ptr() as usize - line.as_ptr() as usize;
+        let end = start + trimmed.len();
+
+        let start = line[..start].trim_end().len();
+        let end = line.len() - line[end..].trim_start().len();
+
+        line[start..end].trim_matches(chars)
+    }
+}
+
+/// Parses a buffer with the dialect detected by [`Dialect::detect`], returning
+/// the dialect along with the directives.
+///
+/// If the buffer does not parse with the detected dialect, such as due to an
+/// unterminated quote, it falls back to being parsed as [`Dialect::Kankyo`],
+/// which never fails.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{Dialect, Directive};
+///
+/// let (dialect, directives) = kankyo::parse_auto("export TOKEN='a b'");
+///
+/// assert_eq!(dialect, Dialect::RubyDotenv);
+/// assert_eq!(directives, vec![Directive::Set("TOKEN".into(), "a b".into())]);
+/// ```
+///
+/// [`Dialect::Kankyo`]: enum.Dialect.html#variant.Kankyo
+/// [`Dialect::detect`]: enum.Dialect.html#method.detect
+pub fn parse_auto(buf: &str) -> (Dialect, Vec<Directive<'_>>) {
+    let dialect = Dialect::detect(buf);
+
+    match Parser::with_dialect(dialect).parse_lines(buf) {
+        Ok(directives) => (dialect, directives),
+        Err(_) => {
+            let directives = utils::parse_lines(buf)
+                .into_iter()
+                .map(|(key, value)| Directive::Set(key, Cow::Borrowed(value)))
+                .collect();
+
+            (Dialect::Kankyo, directives)
+        },
+    }
+}
+
+// Parses a line whose first separator is a colon, returning `None` if it is
+// not.
+fn parse_colon(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+
+    let key = &line[..colon];
+
+    if key.contains('#') || utils::find_separator(key).is_some() {
+        return None;
+    }
+
+    let value = &line[colon + 1..];
+    let value = match value.find('#') {
+        Some(pos) => &value[..pos],
+        None => value,
+    };
+
+    Some((key.trim(), value.trim()))
+}
+
+// Parses a line where a `#` only starts a comment if it is preceded by
+// whitespace, returning `None` if the line is a comment or has no equals sign.
+fn parse_spaced_comments(line: &str) -> Option<(&str, &str)> {
+    if line.trim_start().starts_with('#') {
+        return None;
+    }
+
+    let equals = utils::find_separator(line)?;
+    let value = &line[equals + 1..];
+    let end = value
+        .char_indices()
+        .find(|&(idx, c)| {
+            c == '#' && value[..idx].ends_with(char::is_whitespace)
+        })
+        .map_or(value.len(), |(idx, _)| idx);
+
+    Some((line[..equals].trim(), value[..end].trim()))
+}
+
+// Returns whether the parsed value of a line is empty only because everything
+// after the equals sign is a comment.
+fn is_comment_value(line: &str, value: &str) -> bool {
+    value.is_empty() && utils::find_separator(line).map_or(false, |equals| {
+        line[equals + 1..].trim_start().starts_with('#')
+    })
+}
+
+// Strips a leading `export` keyword and the whitespace after it from a line,
+// if it has one.
+fn strip_export(line: &str) -> &str {
+    match line.trim_start().strip_prefix("export") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+            rest.trim_start()
+        },
+        _ => line,
+    }
+}
+
+// Parses a line without stripping comments from the value, returning `None`
+// if the line is a comment or has no equals sign.
+fn parse_raw(line: &str) -> Option<(&str, &str)> {
+    if line.trim_start().starts_with('#') {
+        return None;
+    }
+
+    let equals = utils::find_separator(line)?;
+
+    Some((line[..equals].trim(), line[equals + 1..].trim()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_ignores_bare_keys() {
+        let parser = Parser::new();
+
+        assert_eq!(parser.parse_line("KEY"), Ok(None));
+        assert_eq!(
+            parser.parse_line("KEY="),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+    }
+
+    #[test]
+    fn colon_separators() {
+        let parser = Parser::new().colon_separators(true);
+
+        assert_eq!(
+            parser.parse_line("KEY: value"),
+            Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("URL: http://host/?a=b"),
+            Ok(Some(Directive::Set("URL".into(), "http://host/?a=b".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=a:b"),
+            Ok(Some(Directive::Set("KEY".into(), "a:b".into()))),
+        );
+        assert_eq!(parser.parse_line("# KEY: value"), Ok(None));
+        assert_eq!(Parser::new().parse_line("KEY: value"), Ok(None));
+    }
+
+    #[test]
+    fn default_ignores_quotes() {
+        let parser = Parser::new();
+
+        assert_eq!(
+            parser.parse_line("KEY=\"a # b\""),
+            Ok(Some(Directive::Set("KEY".into(), "\"a".into()))),
+        );
+    }
+
+    #[test]
+    fn leading_whitespace() {
+        for parser in &[Parser::new(), Parser::new().colon_separators(true)] {
+            for line in &["    KEY=value", "\tKEY=value", " \t KEY = value"] {
+                assert_eq!(
+                    parser.parse_line(line),
+                    Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+                );
+            }
+
+            assert_eq!(parser.parse_line("\t# KEY=value"), Ok(None));
+        }
+
+        assert_eq!(
+            Parser::new().colon_separators(true).parse_line("\t\tKEY: value"),
+            Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+        );
+    }
+
+    #[test]
+    fn dialects() {
+        let buf = "export A=1\nB=x#y # comment\nC=\"a\\tb\"\nD='a\\tb'";
+
+        assert_eq!(
+            Parser::with_dialect(Dialect::Kankyo).parse_lines(buf),
+            Ok(vec![
+                Directive::Set("export A".into(), "1".into()),
+                Directive::Set("B".into(), "x".into()),
+                Directive::Set("C".into(), "\"a\\tb\"".into()),
+                Directive::Set("D".into(), "'a\\tb'".into()),
+            ]),
+        );
+        assert_eq!(
+            Parser::with_dialect(Dialect::RubyDotenv).parse_lines(buf),
+            Ok(vec![
+                Directive::Set("A".into(), "1".into()),
+                Directive::Set("B".into(), "x#y".into()),
+                Directive::Set("C".into(), "a\tb".into()),
+                Directive::Set("D".into(), "a\\tb".into()),
+            ]),
+        );
+    }
+
+    #[test]
+    fn detect_dialect() {
+        let buf = "# exported for the shell\nexport A=1\nexport B=\"x y\"\n\
+                   export C=2 # comment\n\nD=3";
+        let (dialect, directives) = parse_auto(buf);
+
+        assert_eq!(dialect, Dialect::RubyDotenv);
+        assert_eq!(directives, vec![
+            Directive::Set("A".into(), "1".into()),
+            Directive::Set("B".into(), "x y".into()),
+            Directive::Set("C".into(), "2".into()),
+            Directive::Set("D".into(), "3".into()),
+        ]);
+
+        assert_eq!(Dialect::detect("A='1'"), Dialect::RubyDotenv);
+        assert_eq!(Dialect::detect(r"A\=B='1'"), Dialect::RubyDotenv);
+        assert_eq!(Dialect::detect("A=${B}"), Dialect::Docker);
+        assert_eq!(Dialect::detect("export A=${B}"), Dialect::Kankyo);
+        assert_eq!(Dialect::detect("# export A=1\nexports=1"), Dialect::Kankyo);
+        assert_eq!(Dialect::detect(""), Dialect::Kankyo);
+
+        let (dialect, directives) = parse_auto("export A=\"unterminated");
+        assert_eq!(dialect, Dialect::Kankyo);
+        assert_eq!(directives, vec![
+            Directive::Set("export A".into(), "\"unterminated".into()),
+        ]);
+    }
+
+    #[test]
+    fn docker_dialect() {
+        env::set_var("PARSER_DOCKER_ENV", "env");
+
+        let parser = Parser::with_dialect(Dialect::Docker);
+        let buf = "A=x # not a comment\n# B=1\nC=\"${A}\"\\n\n\
+                   D=$PARSER_DOCKER_ENV-${PARSER_DOCKER_MISSING}-$$";
+
+        assert_eq!(
+            parser.parse_lines(buf),
+            Ok(vec![
+                Directive::Set("A".into(), "x # not a comment".into()),
+                Directive::Set("C".into(), "\"x # not a comment\"\\n".into()),
+                Directive::Set("D".into(), "env--$$".into()),
+            ]),
+        );
+        assert_eq!(
+            parser.parse_line("C=${PARSER_DOCKER_A}"),
+            Ok(Some(Directive::Set("C".into(), "".into()))),
+        );
+
+        env::remove_var("PARSER_DOCKER_ENV");
+    }
+
+    #[test]
+    fn interpolate() {
+        let parser = Parser::new().interpolate(true).unset_directives(true);
+        let buf = "PARSER_INTERP_A=1\nB=$PARSER_INTERP_A\nPARSER_INTERP_A=2\n\
+                   C=${PARSER_INTERP_A}${B}\nPARSER_INTERP_A\n\
+                   D=[$PARSER_INTERP_A]";
+
+        assert_eq!(
+            parser.parse_lines(buf),
+            Ok(vec![
+                Directive::Set("PARSER_INTERP_A".into(), "1".into()),
+                Directive::Set("B".into(), "1".into()),
+                Directive::Set("PARSER_INTERP_A".into(), "2".into()),
+                Directive::Set("C".into(), "21".into()),
+                Directive::Unset("PARSER_INTERP_A".into()),
+                Directive::Set("D".into(), "[]".into()),
+            ]),
+        );
+
+        match parser.parse_line("A=plain") {
+            Ok(Some(Directive::Set(_, Cow::Borrowed("plain")))) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let parser = Parser::new().quotes(true).escapes(true);
+
+        assert_eq!(
+            parser.parse_line(r#"KEY="say \"hi\"" # comment"#),
+            Ok(Some(Directive::Set("KEY".into(), "say \"hi\"".into()))),
+        );
+        assert_eq!(
+            parser.parse_line(r#"KEY='a\nb'"#),
+            Ok(Some(Directive::Set("KEY".into(), r"a\nb".into()))),
+        );
+        assert_eq!(
+            Parser::new().escapes(true).parse_line(r#"KEY="a\nb""#),
+            Ok(Some(Directive::Set("KEY".into(), r#""a\nb""#.into()))),
+        );
+
+        match parser.parse_line("KEY=\"plain\"") {
+            Ok(Some(Directive::Set(_, Cow::Borrowed("plain")))) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn export_prefix() {
+        let parser = Parser::new().export_prefix(true);
+
+        for line in &["export KEY=1", "  export\tKEY = 1", "KEY=1"] {
+            assert_eq!(
+                parser.parse_line(line),
+                Ok(Some(Directive::Set("KEY".into(), "1".into()))),
+            );
+        }
+
+        assert_eq!(
+            parser.parse_line("exported=1"),
+            Ok(Some(Directive::Set("exported".into(), "1".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("export=1"),
+            Ok(Some(Directive::Set("export".into(), "1".into()))),
+        );
+    }
+
+    #[test]
+    fn skip_comment_values() {
+        let parser = Parser::new().skip_comment_values(true);
+
+        assert_eq!(
+            Parser::new().parse_line("KEY=#c"),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(parser.parse_line("KEY=#c"), Ok(None));
+        assert_eq!(parser.parse_line("KEY = \t# c"), Ok(None));
+        assert_eq!(
+            parser.parse_line("KEY="),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=a#c"),
+            Ok(Some(Directive::Set("KEY".into(), "a".into()))),
+        );
+
+        let raw = parser.clone().raw(true);
+        assert_eq!(
+            raw.parse_line("KEY=#c"),
+            Ok(Some(Directive::Set("KEY".into(), "#c".into()))),
+        );
+
+        let quoted = parser.quotes(true);
+        assert_eq!(
+            quoted.parse_line("KEY=\"\" # c"),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+    }
+
+    #[test]
+    fn spaced_comments() {
+        let parser = Parser::new().spaced_comments(true);
+
+        assert_eq!(
+            parser.parse_line("KEY=a#b\t# comment"),
+            Ok(Some(Directive::Set("KEY".into(), "a#b".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=#fff"),
+            Ok(Some(Directive::Set("KEY".into(), "#fff".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY= # comment"),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(parser.parse_line("  # KEY=value"), Ok(None));
+    }
+
+    #[test]
+    fn raw() {
+        let parser = Parser::new().raw(true);
+
+        assert_eq!(
+            parser.parse_lines("COLOR=#fff\n  # X=1\nHASH = a#b#c \nBARE"),
+            Ok(vec![
+                Directive::Set("COLOR".into(), "#fff".into()),
+                Directive::Set("HASH".into(), "a#b#c".into()),
+            ]),
+        );
+        assert_eq!(
+            Parser::new().parse_line("COLOR=#fff"),
+            Ok(Some(Directive::Set("COLOR".into(), "".into()))),
+        );
+    }
+
+    #[test]
+    fn escaped_equals() {
+        let line = r"a\=b=c";
+        let expected = Ok(Some(Directive::Set("a=b".into(), "c".into())));
+
+        assert_eq!(utils::parse_line(line), Some(("a=b".into(), "c")));
+        assert_eq!(Parser::new().parse_line(line), expected);
+        assert_eq!(Parser::new().raw(true).parse_line(line), expected);
+        assert_eq!(
+            Parser::new().spaced_comments(true).parse_line(line),
+            expected,
+        );
+        assert_eq!(
+            Parser::new().colon_separators(true).parse_line(line),
+            expected,
+        );
+        assert_eq!(
+            Parser::new().quotes(true).parse_line(r#"a\=b="c""#),
+            expected,
+        );
+        assert_eq!(
+            Parser::new().colon_separators(true).parse_line(r"a\=b: c"),
+            expected,
+        );
+        assert_eq!(
+            Parser::new().skip_comment_values(true).parse_line(r"a\==#c"),
+            Ok(None),
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        let parser = Parser::new().quotes(true);
+
+        assert_eq!(
+            parser.parse_line("KEY = \" a # b \" # comment"),
+            Ok(Some(Directive::Set("KEY".into(), " a # b ".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY='say \"hi\"'"),
+            Ok(Some(Directive::Set("KEY".into(), "say \"hi\"".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=\"\""),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=unquoted # comment"),
+            Ok(Some(Directive::Set("KEY".into(), "unquoted".into()))),
+        );
+        assert_eq!(parser.parse_line("#KEY=\"value"), Ok(None));
+        assert_eq!(
+            parser.parse_line("KEY=\"value\" trailing"),
+            Err(ParseErrorKind::TrailingCharacters),
+        );
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let strict = Parser::new().quotes(true);
+        assert_eq!(
+            strict.parse_line("KEY=\"unterminated"),
+            Err(ParseErrorKind::UnterminatedQuote),
+        );
+
+        let err = strict.parse_lines("A=1\nKEY=\"unterminated").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.kind, ParseErrorKind::UnterminatedQuote);
+
+        let lenient = strict.lenient_quotes(true);
+        assert_eq!(
+            lenient.parse_line("KEY=\"unterminated  "),
+            Ok(Some(Directive::Set("KEY".into(), "unterminated".into()))),
+        );
+        assert_eq!(
+            lenient.parse_line("KEY='value' trailing"),
+            Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+        );
+    }
+
+    #[test]
+    fn collect_errors_capped() {
+        let parser = Parser::new().quotes(true);
+        let buf = (0..10)
+            .map(|i| format!("K{}=\"unterminated", i))
+            .collect::<Vec<_>>()
+            .join("\nOK=1\n");
+
+        let report = parser.parse_lines_collect_errors_capped(&buf, 3);
+        assert_eq!(report.errors.len(), 3);
+        assert_eq!(report.errors[2].line, 5);
+        assert_eq!(report.directives.len(), 3);
+        assert!(report.truncated);
+
+        let report = parser.parse_lines_collect_errors_capped(&buf, 10);
+        assert_eq!(report.errors.len(), 10);
+        assert_eq!(report.directives.len(), 9);
+        assert!(!report.truncated);
+
+        let report = parser.parse_lines_collect_errors_capped("A=1", 0);
+        assert_eq!(report.directives.len(), 1);
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn trim_chars() {
+        let line = "\u{a0}KEY\u{a0}=\u{a0} value \u{a0}# comment";
+
+        assert_eq!(
+            Parser::new().parse_line(line),
+            Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+        );
+
+        let parser = Parser::new().trim_chars(Some(&[' ', '\t']));
+        assert_eq!(
+            parser.parse_line(line),
+            Ok(Some(Directive::Set(
+                "\u{a0}KEY\u{a0}".into(),
+                "\u{a0} value \u{a0}".into(),
+            ))),
+        );
+        assert_eq!(
+            parser.parse_line("\tKEY =\t\u{a0}\t"),
+            Ok(Some(Directive::Set("KEY".into(), "\u{a0}".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=  "),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+
+        let quoted = parser.clone().quotes(true);
+        assert_eq!(
+            quoted.parse_line("KEY = ' a ' "),
+            Ok(Some(Directive::Set("KEY".into(), " a ".into()))),
+        );
+
+        let exported = Parser::new()
+            .export_prefix(true)
+            .trim_chars(Some(&['\t']));
+        assert_eq!(
+            exported.parse_line("export KEY=v"),
+            Ok(Some(Directive::Set("KEY".into(), "v".into()))),
+        );
+
+        let colon = parser.colon_separators(true);
+        assert_eq!(
+            colon.parse_line("KEY:\u{a0}v\u{a0}"),
+            Ok(Some(Directive::Set("KEY".into(), "\u{a0}v\u{a0}".into()))),
+        );
+    }
+
+    #[test]
+    fn ignore_lines() {
+        fn separator(line: &str) -> bool {
+            let line = line.trim();
+
+            !line.is_empty() && line.bytes().all(|b| b == b'-' || b == b'=')
+        }
+
+        let parser = Parser::new().quotes(true).unset_directives(true);
+        let buf = "A=1\n----\n=====\nB=\"2\"";
+        assert_eq!(
+            parser.parse_lines(buf).unwrap(),
+            vec![
+                Directive::Set("A".into(), "1".into()),
+                Directive::Unset("----".into()),
+                Directive::Set("".into(), "====".into()),
+                Directive::Set("B".into(), "2".into()),
+            ],
+        );
+        assert_eq!(
+            parser.ignore_lines(Some(separator)).parse_lines(buf),
+            Ok(vec![
+                Directive::Set("A".into(), "1".into()),
+                Directive::Set("B".into(), "2".into()),
+            ]),
+        );
+    }
+
+    #[test]
+    fn empty_unsets() {
+        let parser = Parser::new().empty_unsets(true);
+
+        assert_eq!(
+            parser.parse_line("KEY="),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY= # none"),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY=value"),
+            Ok(Some(Directive::Set("KEY".into(), "value".into()))),
+        );
+        assert_eq!(parser.parse_line("KEY"), Ok(None));
+
+        let parser = parser.quotes(true).colon_separators(true);
+        assert_eq!(
+            parser.parse_line("KEY=\"\""),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY:"),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+    }
+
+    #[test]
+    fn parse_overrides() {
+        let parser = Parser::new().unset_directives(true).empty_unsets(true);
+        let overrides = parser
+            .parse_overrides("A=1\nB\nC=\nD=4\nA\nB=2")
+            .unwrap();
+
+        assert_eq!(
+            overrides.set,
+            vec![("D".into(), "4".into()), ("B".into(), "2".into())],
+        );
+        assert_eq!(overrides.unset, vec!["C", "A"]);
+    }
+
+    #[test]
+    fn apply_overrides() {
+        env::set_var("OVERRIDES_UNSET", "1");
+        env::set_var("OVERRIDES_KEPT", "1");
+
+        let parser = Parser::new().unset_directives(true);
+        let overrides = parser
+            .parse_overrides("OVERRIDES_SET=1\nOVERRIDES_UNSET")
+            .unwrap();
+
+        overrides.apply(true);
+        assert_eq!(env::var("OVERRIDES_SET").unwrap(), "1");
+        assert!(env::var("OVERRIDES_UNSET").is_err());
+
+        let overrides = parser
+            .parse_overrides("OVERRIDES_SET=2\nOVERRIDES_KEPT")
+            .unwrap();
+
+        overrides.apply(false);
+        assert_eq!(env::var("OVERRIDES_SET").unwrap(), "1");
+        assert_eq!(env::var("OVERRIDES_KEPT").unwrap(), "1");
+
+        env::remove_var("OVERRIDES_SET");
+        env::remove_var("OVERRIDES_KEPT");
+    }
+
+    #[test]
+    fn unset_directives() {
+        let parser = Parser::new().unset_directives(true);
+
+        assert_eq!(
+            parser.parse_line("KEY="),
+            Ok(Some(Directive::Set("KEY".into(), "".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY"),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+        assert_eq!(
+            parser.parse_line(" KEY "),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+        assert_eq!(
+            parser.parse_line("KEY # unset it"),
+            Ok(Some(Directive::Unset("KEY".into()))),
+        );
+        assert_eq!(parser.parse_line(""), Ok(None));
+        assert_eq!(parser.parse_line("# comment"), Ok(None));
+        assert_eq!(parser.parse_line("KEY#=value"), Ok(None));
+        assert_eq!(parser.parse_line("not a key"), Ok(None));
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use utils;
+
+// Serializes every mutation of the environment made by the library.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// A target that parsed variables can be applied to.
+///
+/// The library's loading functions apply variables to the process' environment
+/// via [`ProcessEnv`], but an implementation can instead apply them elsewhere,
+/// such as to an in-memory map for testing without touching global state.
+///
+/// # Examples
+///
+/// Apply parsed lines to a map rather than the environment:
+///
+/// ```rust
+/// use kankyo::{utils, HashMapSink};
+///
+/// let mut sink = HashMapSink::new();
+/// utils::set_variables_in(&mut sink, &[("FOO", "bar")], true);
+///
+/// assert_eq!(sink["FOO"], "bar");
+/// assert!(kankyo::key("FOO").is_none());
+/// ```
+///
+/// [`ProcessEnv`]: struct.ProcessEnv.html
+pub trait EnvSink {
+    /// Returns the current value of the variable, if it exists.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Sets the variable to the value.
+    fn set(&mut self, key: &str, value: &str);
+
+    /// Removes the variable.
+    fn remove(&mut self, key: &str);
+}
+
+/// An in-memory [`EnvSink`] holding variables in a map.
+///
+/// [`EnvSink`]: trait.EnvSink.html
+pub type HashMapSink = HashMap<String, String>;
+
+impl EnvSink for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<String> {
+        HashMap::get(self, key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        self.insert(key.to_owned(), value.to_owned());
+    }
+
+    fn remove(&mut self, key: &str) {
+        HashMap::remove(self, key);
+    }
+}
+
+/// An [`EnvSink`] applying variables to the current process' environment.
+///
+/// [`EnvSink`]: trait.EnvSink.html
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ProcessEnv;
+
+impl EnvSink for ProcessEnv {
+    fn get(&self, key: &str) -> Option<String> {
+        var_lossy(key)
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        set_var(key, value);
+    }
+
+    fn remove(&mut self, key: &str) {
+        remove_var(key);
+    }
+}
+
+/// An operation applied to a [`RecordingSink`].
+///
+/// [`RecordingSink`]: struct.RecordingSink.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// A variable was set.
+    Set {
+        /// The name of the variable.
+        key: String,
+        /// The value that the variable was set to.
+        value: String,
+    },
+    /// A variable was removed.
+    Remove {
+        /// The name of the variable.
+        key: String,
+    },
+}
+
+/// An in-memory [`EnvSink`] that records every operation applied to it, in
+/// addition to holding the resulting variables.
+///
+/// This allows tests to assert the exact sequence of changes that a load
+/// makes, such as the order in which repeated keys are overwritten, rather
+/// than only the final state.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{utils, EnvSink, Operation, RecordingSink};
+///
+/// let mut sink = RecordingSink::new();
+/// utils::set_variables_in(&mut sink, &[("A", "1"), ("A", "2")], true);
+///
+/// assert_eq!(sink.get("A"), Some("2".to_owned()));
+/// assert_eq!(sink.operations(), &[
+///     Operation::Set { key: "A".to_owned(), value: "1".to_owned() },
+///     Operation::Set { key: "A".to_owned(), value: "2".to_owned() },
+/// ]);
+/// ```
+///
+/// [`EnvSink`]: trait.EnvSink.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RecordingSink {
+    vars: HashMap<String, String>,
+    operations: Vec<Operation>,
+}
+
+impl RecordingSink {
+    /// Creates a new sink with no variables and no recorded operations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the operations that were applied, in order.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Returns the variables resulting from the operations.
+    pub fn vars(&self) -> &HashMap<String, String> {
+        &self.vars
+    }
+}
+
+impl EnvSink for RecordingSink {
+    fn get(&self, key: &str) -> Option<String> {
+        self.vars.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        self.vars.insert(key.to_owned(), value.to_owned());
+        self.operations.push(Operation::Set {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.vars.remove(key);
+        self.operations.push(Operation::Remove {
+            key: key.to_owned(),
+        });
+    }
+}
+
+// Locks the environment against mutations by the library until the guard is
+// dropped.
+//
+// The lock guards no data of its own, so a panic while it was held can not
+// have left anything inconsistent, and poisoning is ignored.
+fn lock() -> MutexGuard<'static, ()> {
+    ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The environment, locked against mutations by the library for as long as
+// this lives.
+//
+// A load checks whether each variable exists before setting it, so the whole
+// load holds this, rather than locking for each change: otherwise two loads
+// could both see a variable as not existing and both set it.
+//
+// The lock is not re-entrant, so nothing that locks it again, such as
+// `set_var`, may be called while this lives.
+pub(crate) struct LockedEnv {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl LockedEnv {
+    pub(crate) fn new() -> Self {
+        LockedEnv {
+            _guard: lock(),
+        }
+    }
+
+    // Sets a variable in the environment, returning whether it was valid to
+    // set.
+    //
+    // Unlike `env::set_var`, this does not panic on an invalid key or value,
+    // and instead does nothing.
+    pub(crate) fn set_var<V: AsRef<OsStr>>(&self, key: &str, value: V) -> bool {
+        let value = value.as_ref();
+
+        if !is_valid_os_var(key, value) {
+            return false;
+        }
+
+        env::set_var(key, value);
+
+        true
+    }
+
+    // Removes a variable from the environment, returning whether the key was
+    // valid.
+    //
+    // Unlike `env::remove_var`, this does not panic on an invalid key, and
+    // instead does nothing.
+    pub(crate) fn remove_var(&self, key: &str) -> bool {
+        if !utils::is_valid_var(key, "") {
+            return false;
+        }
+
+        env::remove_var(key);
+
+        true
+    }
+}
+
+impl EnvSink for LockedEnv {
+    fn get(&self, key: &str) -> Option<String> {
+        var_lossy(key)
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        self.set_var(key, value);
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.remove_var(key);
+    }
+}
+
+// Sets a single variable in the environment, returning whether it was valid to
+// set.
+pub(crate) fn set_var(key: &str, value: &str) -> bool {
+    LockedEnv::new().set_var(key, value)
+}
+
+// Removes a single variable from the environment, returning whether the key
+// was valid.
+pub(crate) fn remove_var(key: &str) -> bool {
+    LockedEnv::new().remove_var(key)
+}
+
+// Returns the value of a variable in the environment, converting a value that
+// is not valid unicode lossily, as such a variable still exists.
+fn var_lossy(key: &str) -> Option<String> {
+    env::var_os(key).map(|value| value.to_string_lossy().into_owned())
+}
+
+// Returns whether the variable can be set, like `utils::is_valid_var`, but for
+// a value that may not be valid unicode.
+fn is_valid_os_var(key: &str, value: &OsStr) -> bool {
+    match value.to_str() {
+        Some(value) => utils::is_valid_var(key, value),
+        None => utils::is_valid_var(key, "")
+            && !value.to_string_lossy().contains('\0'),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use parser::Parser;
+    use super::*;
+
+    #[test]
+    fn hash_map_sink() {
+        let mut sink = HashMapSink::new();
+        sink.set("SINK_KEY", "1");
+        assert_eq!(EnvSink::get(&sink, "SINK_KEY"), Some("1".to_owned()));
+
+        EnvSink::remove(&mut sink, "SINK_KEY");
+        assert!(sink.is_empty());
+        assert!(env::var("SINK_KEY").is_err());
+    }
+
+    #[test]
+    fn recording_sink() {
+        let buf = "REC_A=1\nREC_B=2\nREC_A=3\nREC_B\nREC_C=4";
+        let directives = Parser::new()
+            .unset_directives(true)
+            .parse_lines(buf)
+            .unwrap();
+
+        let mut sink = RecordingSink::new();
+        sink.set("REC_C", "0");
+        utils::set_directives_in(&mut sink, &directives, false);
+
+        let set = |key: &str, value: &str| Operation::Set {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+        assert_eq!(sink.operations(), &[
+            set("REC_C", "0"),
+            set("REC_A", "1"),
+            set("REC_B", "2"),
+        ]);
+
+        let mut sink = RecordingSink::new();
+        utils::set_directives_in(&mut sink, &directives, true);
+
+        assert_eq!(sink.operations(), &[
+            set("REC_A", "1"),
+            set("REC_B", "2"),
+            set("REC_A", "3"),
+            Operation::Remove {
+                key: "REC_B".to_owned(),
+            },
+            set("REC_C", "4"),
+        ]);
+        assert_eq!(sink.vars().len(), 2);
+        assert_eq!(sink.vars()["REC_A"], "3");
+        assert!(env::var("REC_A").is_err());
+    }
+
+    #[test]
+    fn process_env() {
+        let mut sink = ProcessEnv;
+        sink.set("PROCESS_ENV_SINK_KEY", "1");
+        assert_eq!(env::var("PROCESS_ENV_SINK_KEY").unwrap(), "1");
+        assert_eq!(sink.get("PROCESS_ENV_SINK_KEY"), Some("1".to_owned()));
+
+        sink.remove("PROCESS_ENV_SINK_KEY");
+        assert!(env::var("PROCESS_ENV_SINK_KEY").is_err());
+    }
+
+    #[test]
+    fn invalid_vars_do_not_panic() {
+        assert!(!set_var("PROCESS_ENV_NUL", "a\0b"));
+        assert!(!set_var("PROCESS_ENV\0NUL", "a"));
+        assert!(!set_var("", "a"));
+        assert!(!set_var("A=B", "a"));
+        assert!(!remove_var("PROCESS_ENV\0NUL"));
+        assert!(env::var("PROCESS_ENV_NUL").is_err());
+
+        let mut sink = ProcessEnv;
+        sink.set("PROCESS_ENV_NUL", "a\0b");
+        sink.remove("");
+    }
+}
@@ -0,0 +1,115 @@
+use std::future::Future;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use error::PathError;
+use super::Result;
+use tokio::fs;
+use utils;
+
+/// Loads a `.env` file at the given path without blocking the async runtime.
+///
+/// The file is read via `tokio::fs::read_to_string`, and then parsed and
+/// loaded synchronously once read. Unlike [`load_from_path`], references to
+/// environment variables in the path are not expanded.
+///
+/// This requires the `tokio` feature to be enabled.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate kankyo;
+/// # extern crate tokio;
+/// #
+/// # fn main() {
+/// let runtime = tokio::runtime::Builder::new_current_thread()
+///     .build()
+///     .unwrap();
+///
+/// runtime.block_on(kankyo::load_from_path_async(".env", false)).unwrap();
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `NotFound` if the file does not exist.
+///
+/// Returns an `std::io::Error` if there was an error reading the file.
+///
+/// [`load_from_path`]: fn.load_from_path.html
+pub fn load_from_path_async<P: AsRef<Path>>(
+    path: P,
+    overwrite: bool,
+) -> impl Future<Output = Result<()>> + Send {
+    let path = path.as_ref().to_path_buf();
+
+    LoadFromPath {
+        read: Box::pin(fs::read_to_string(path.clone())),
+        path,
+        overwrite,
+    }
+}
+
+struct LoadFromPath {
+    read: Pin<Box<dyn Future<Output = IoResult<String>> + Send>>,
+    path: PathBuf,
+    overwrite: bool,
+}
+
+impl Future for LoadFromPath {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let content = match self.read.as_mut().poll(cx) {
+            Poll::Ready(Ok(content)) => content,
+            Poll::Ready(Err(err)) => {
+                return Poll::Ready(Err(PathError::wrap(&self.path, err)));
+            },
+            Poll::Pending => return Poll::Pending,
+        };
+
+        utils::set_variables(&utils::parse_lines(&content), self.overwrite);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::process;
+    use super::*;
+    use tokio::runtime::{Builder, Runtime};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn load() {
+        let path = env::temp_dir()
+            .join(format!("kankyo-async-{}.env", process::id()));
+        fs::write(&path, "ASYNC_A=1\nASYNC_B=2").unwrap();
+
+        runtime().block_on(load_from_path_async(&path, true)).unwrap();
+        assert_eq!(env::var("ASYNC_A").unwrap(), "1");
+        assert_eq!(env::var("ASYNC_B").unwrap(), "2");
+
+        utils::unload(&["ASYNC_A", "ASYNC_B"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = env::temp_dir()
+            .join(format!("kankyo-async-missing-{}.env", process::id()));
+        let err = runtime()
+            .block_on(load_from_path_async(path, true))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Error as IoError, ErrorKind, Read};
+use super::Result;
+use toml::{Table, Value};
+use utils;
+
+/// Reads a TOML table from a reader and loads its values into the
+/// environment.
+///
+/// The keys of the table are used as the variable names. Strings are used
+/// verbatim, while integers, floats, booleans, and datetimes are converted to
+/// their string form.
+///
+/// Nested tables are flattened by joining the keys with an underscore (`_`),
+/// so that a `[DB]` table containing `HOST = "localhost"` loads the variable
+/// `DB_HOST` with the value `localhost`. This applies recursively to any level
+/// of nesting.
+///
+/// This requires the `toml` feature to be enabled.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("[TOML_DOC]\nPORT = 8080");
+/// kankyo::load_from_toml_reader(&mut cursor, true).unwrap();
+///
+/// assert_eq!(kankyo::key("TOML_DOC_PORT").unwrap(), "8080");
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader,
+/// if the content is not a valid TOML document, or if a value is an array.
+pub fn load_from_toml_reader<R: Read>(
+    reader: &mut R,
+    overwrite: bool,
+) -> Result<()> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let table = content
+        .parse::<Table>()
+        .map_err(|why| invalid_data(&why.to_string()))?;
+
+    let mut pairs = Vec::with_capacity(table.len());
+    flatten(None, table, &mut pairs)?;
+
+    let lines = pairs
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<Vec<_>>();
+    utils::set_variables(&lines, overwrite);
+
+    Ok(())
+}
+
+fn flatten(
+    prefix: Option<&str>,
+    table: Table,
+    pairs: &mut Vec<(String, String)>,
+) -> Result<()> {
+    for (key, value) in table {
+        let key = match prefix {
+            Some(prefix) => format!("{}_{}", prefix, key),
+            None => key,
+        };
+
+        match value {
+            Value::Boolean(v) => pairs.push((key, v.to_string())),
+            Value::Datetime(v) => pairs.push((key, v.to_string())),
+            Value::Float(v) => pairs.push((key, v.to_string())),
+            Value::Integer(v) => pairs.push((key, v.to_string())),
+            Value::String(v) => pairs.push((key, v)),
+            Value::Table(v) => flatten(Some(&key), v, pairs)?,
+            Value::Array(_) => {
+                return Err(invalid_data(&format!("array value for {}", key)));
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid_data(msg: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        let mut cursor = Cursor::new("TOML_STR = \"a\"\n\
+                                      TOML_INT = 1\n\
+                                      TOML_FLOAT = 1.5\n\
+                                      TOML_BOOL = false");
+        load_from_toml_reader(&mut cursor, true).unwrap();
+
+        assert_eq!(env::var("TOML_STR").unwrap(), "a");
+        assert_eq!(env::var("TOML_INT").unwrap(), "1");
+        assert_eq!(env::var("TOML_FLOAT").unwrap(), "1.5");
+        assert_eq!(env::var("TOML_BOOL").unwrap(), "false");
+    }
+
+    #[test]
+    fn nested() {
+        let mut cursor = Cursor::new("[TOML_NESTED]\n\
+                                      HOST = \"localhost\"\n\
+                                      [TOML_NESTED.INNER]\n\
+                                      PORT = 5432");
+        load_from_toml_reader(&mut cursor, true).unwrap();
+
+        assert_eq!(env::var("TOML_NESTED_HOST").unwrap(), "localhost");
+        assert_eq!(env::var("TOML_NESTED_INNER_PORT").unwrap(), "5432");
+    }
+
+    #[test]
+    fn invalid() {
+        let mut array = Cursor::new("TOML_ARRAY = [1, 2]");
+        assert!(load_from_toml_reader(&mut array, true).is_err());
+
+        let mut malformed = Cursor::new("TOML_MALFORMED = ");
+        assert!(load_from_toml_reader(&mut malformed, true).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use sink::LockedEnv;
+use std::env;
+use utils;
+
+/// A single change to the environment recorded by a [`Transaction`].
+///
+/// [`Transaction`]: struct.Transaction.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mutation {
+    /// The variable was set.
+    Set {
+        /// The name of the variable.
+        key: String,
+        /// The value of the variable prior to being set, if it existed.
+        previous: Option<String>,
+    },
+    /// The variable was not set, as it already existed and overwriting was
+    /// disabled.
+    Skip {
+        /// The name of the variable.
+        key: String,
+    },
+}
+
+/// A log of the changes made to the environment by a load, which can be
+/// explicitly rolled back or committed.
+///
+/// Unlike dropping a value to undo its changes, nothing happens if a
+/// transaction is dropped: the changes are only undone when [`rollback`] is
+/// called.
+///
+/// # Examples
+///
+/// Load some variables and then undo the load:
+///
+/// ```rust
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// let mut cursor = Cursor::new("TRANSACTION_DOC=1");
+/// let transaction = kankyo::load_from_reader_transaction(&mut cursor, true)
+///     .unwrap();
+/// assert_eq!(env::var("TRANSACTION_DOC").unwrap(), "1");
+///
+/// transaction.rollback();
+/// assert!(env::var("TRANSACTION_DOC").is_err());
+/// ```
+///
+/// [`rollback`]: #method.rollback
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transaction {
+    mutations: Vec<Mutation>,
+}
+
+impl Transaction {
+    /// Loads the given parsed lines into the environment, recording each
+    /// change.
+    pub(crate) fn apply<K: AsRef<str>>(
+        lines: &[(K, &str)],
+        overwrite: bool,
+    ) -> Self {
+        let locked = LockedEnv::new();
+        let mut mutations = Vec::with_capacity(lines.len());
+
+        for &(ref key, value) in lines {
+            let key = key.as_ref();
+
+            if !utils::is_valid_var(key, value) {
+                continue;
+            }
+
+            let previous = env::var(key).ok();
+
+            if !overwrite && previous.is_some() {
+                mutations.push(Mutation::Skip {
+                    key: key.to_owned(),
+                });
+
+                continue;
+            }
+
+            locked.set_var(key, value);
+
+            mutations.push(Mutation::Set {
+                key: key.to_owned(),
+                previous,
+            });
+        }
+
+        Transaction {
+            mutations,
+        }
+    }
+
+    /// Returns the recorded changes, in the order they were made.
+    pub fn mutations(&self) -> &[Mutation] {
+        &self.mutations
+    }
+
+    /// Makes the changes permanent.
+    ///
+    /// This only stops tracking the changes; the environment is left as-is.
+    pub fn commit(self) {}
+
+    /// Restores the environment to its state prior to the load.
+    ///
+    /// Changes are undone in the reverse order that they were made, so a
+    /// variable set multiple times is restored to its original value.
+    pub fn rollback(self) {
+        let locked = LockedEnv::new();
+
+        for mutation in self.mutations.into_iter().rev() {
+            if let Mutation::Set { key, previous } = mutation {
+                match previous {
+                    Some(value) => locked.set_var(&key, &value),
+                    None => locked.remove_var(&key),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+
+    #[test]
+    fn rollback() {
+        env::set_var("TRANSACTION_EXISTING", "before");
+
+        let lines = [
+            ("TRANSACTION_NEW", "1"),
+            ("TRANSACTION_EXISTING", "after"),
+            ("TRANSACTION_NEW", "2"),
+        ];
+        let transaction = Transaction::apply(&lines, true);
+        assert_eq!(env::var("TRANSACTION_NEW").unwrap(), "2");
+        assert_eq!(env::var("TRANSACTION_EXISTING").unwrap(), "after");
+
+        transaction.rollback();
+        assert!(env::var("TRANSACTION_NEW").is_err());
+        assert_eq!(env::var("TRANSACTION_EXISTING").unwrap(), "before");
+
+        env::remove_var("TRANSACTION_EXISTING");
+    }
+
+    #[test]
+    fn skip() {
+        env::set_var("TRANSACTION_SKIP", "before");
+
+        let lines = [("TRANSACTION_SKIP", "after")];
+        let transaction = Transaction::apply(&lines, false);
+        assert_eq!(transaction.mutations(), &[Mutation::Skip {
+            key: "TRANSACTION_SKIP".to_owned(),
+        }]);
+
+        transaction.commit();
+        assert_eq!(env::var("TRANSACTION_SKIP").unwrap(), "before");
+
+        env::remove_var("TRANSACTION_SKIP");
+    }
+}
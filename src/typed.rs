@@ -0,0 +1,186 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::result::Result as StdResult;
+use std::str::FromStr;
+
+type FieldParser = Box<dyn Fn(&str) -> StdResult<Box<dyn Any>, String>>;
+
+/// A registry of parsers for the values of keys, each producing a value of
+/// its own type, for use with [`parse_typed_from_reader`].
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::TypedParsers;
+/// use std::io::Cursor;
+///
+/// let parsers = TypedParsers::new()
+///     .field::<u16>("PORT")
+///     .field::<bool>("DEBUG")
+///     .field_with("HOSTS", |value| {
+///         Ok(value.split(',').map(str::to_owned).collect::<Vec<_>>())
+///     });
+///
+/// let mut cursor = Cursor::new("PORT=8080\nDEBUG=yes\nHOSTS=a,b");
+/// let values = kankyo::parse_typed_from_reader(&mut cursor, &parsers)
+///     .unwrap();
+///
+/// assert_eq!(values.get::<u16>("PORT"), Some(&8080));
+/// assert_eq!(values.get::<Vec<String>>("HOSTS").unwrap().len(), 2);
+/// assert!(values.error("DEBUG").is_some());
+/// ```
+///
+/// [`parse_typed_from_reader`]: fn.parse_typed_from_reader.html
+#[derive(Default)]
+pub struct TypedParsers {
+    parsers: Vec<(String, FieldParser)>,
+}
+
+impl TypedParsers {
+    /// Creates a new registry with no parsers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a key whose value is parsed via the `FromStr` implementation
+    /// of the type.
+    ///
+    /// If parsing fails, the error's `Display` output is the key's error.
+    pub fn field<T>(self, key: &str) -> Self
+        where T: FromStr + 'static, T::Err: Display {
+        self.field_with(key, |value| {
+            value.parse::<T>().map_err(|why| why.to_string())
+        })
+    }
+
+    /// Registers a key whose value is parsed via the given function, which
+    /// returns the parsed value or an error message.
+    pub fn field_with<T, F>(mut self, key: &str, parser: F) -> Self
+        where T: 'static, F: Fn(&str) -> StdResult<T, String> + 'static {
+        let parser = move |value: &str| {
+            parser(value).map(|value| Box::new(value) as Box<dyn Any>)
+        };
+
+        self.parsers.push((key.to_owned(), Box::new(parser)));
+
+        self
+    }
+
+    /// Applies the parser of each registered key to its value in the map.
+    ///
+    /// Registered keys that are not in the map are skipped.
+    pub(crate) fn parse(&self, map: &HashMap<String, String>) -> TypedValues {
+        let mut values = TypedValues::default();
+
+        for (key, parser) in &self.parsers {
+            let value = match map.get(key) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match parser(value) {
+                Ok(parsed) => {
+                    values.values.insert(key.clone(), parsed);
+                },
+                Err(why) => values.errors.push((key.clone(), why)),
+            }
+        }
+
+        values
+    }
+}
+
+impl Debug for TypedParsers {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_list()
+            .entries(self.parsers.iter().map(|(key, _)| key))
+            .finish()
+    }
+}
+
+/// The values parsed by [`parse_typed_from_reader`], along with the error of
+/// each key whose value failed to parse.
+///
+/// A registered key that is not defined has neither a value nor an error.
+///
+/// [`parse_typed_from_reader`]: fn.parse_typed_from_reader.html
+#[derive(Default)]
+pub struct TypedValues {
+    values: HashMap<String, Box<dyn Any>>,
+    errors: Vec<(String, String)>,
+}
+
+impl TypedValues {
+    /// Returns the parsed value of a key.
+    ///
+    /// Returns `None` if the key was not defined, failed to parse, or was
+    /// parsed into a type other than `T`.
+    pub fn get<T: 'static>(&self, key: &str) -> Option<&T> {
+        self.values.get(key).and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns the error message of a key whose value failed to parse.
+    pub fn error(&self, key: &str) -> Option<&str> {
+        self.errors
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, why)| why.as_str())
+    }
+
+    /// Returns each key whose value failed to parse alongside its error
+    /// message, in the order that the keys were registered.
+    pub fn errors(&self) -> &[(String, String)] {
+        &self.errors
+    }
+
+    /// Returns whether every defined key parsed successfully.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Debug for TypedValues {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let mut keys = self.values.keys().collect::<Vec<_>>();
+        keys.sort();
+
+        f.debug_struct("TypedValues")
+            .field("values", &keys)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utils;
+
+    #[test]
+    fn parse() {
+        let parsers = TypedParsers::new()
+            .field::<u32>("INT")
+            .field::<bool>("BOOL")
+            .field::<i8>("BAD_INT")
+            .field::<bool>("MISSING");
+        let map = utils::parse_to_map("INT=42\nBOOL=true\nBAD_INT=300");
+        let values = parsers.parse(&map);
+
+        assert_eq!(values.get::<u32>("INT"), Some(&42));
+        assert_eq!(values.get::<bool>("BOOL"), Some(&true));
+        assert_eq!(values.get::<i64>("INT"), None);
+        assert_eq!(values.get::<i8>("BAD_INT"), None);
+        assert!(values.error("BAD_INT").is_some());
+        assert_eq!(values.get::<bool>("MISSING"), None);
+        assert_eq!(values.error("MISSING"), None);
+        assert_eq!(values.errors().len(), 1);
+        assert!(!values.is_ok());
+    }
+
+    #[test]
+    fn debug() {
+        let parsers = TypedParsers::new().field::<u8>("A").field::<u8>("B");
+        assert_eq!(format!("{:?}", parsers), r#"["A", "B"]"#);
+    }
+}
@@ -6,11 +6,23 @@
 //!
 //! [root module]: ../index.html
 
+use interpolate;
+use parser::{Directive, Parser};
+use sink::{EnvSink, LockedEnv};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::env;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind, Read};
+use std::rc::Rc;
+use super::Result;
 
 /// A key-value pair of a line from a .env file.
 ///
+/// The key borrows from the line unless it contained an escape, such as `\=`
+/// for a literal equals sign, and had to be unescaped.
+///
 /// # Examples
 ///
 /// In the instance of a line with the content `"FOO=bar"`, after being parsed
@@ -23,7 +35,801 @@ use std::env;
 /// assert_eq!(line.0, "FOO");
 /// assert_eq!(line.1, "bar");
 /// ```
-pub type ParsedLine<'a> = (&'a str, &'a str);
+pub type ParsedLine<'a> = (Cow<'a, str>, &'a str);
+
+/// A key-value pair of a line from a .env file, where the value is only owned
+/// if it had to be unescaped, as returned by [`parse_line_cow`].
+///
+/// [`parse_line_cow`]: fn.parse_line_cow.html
+pub type CowLine<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// A key, value, and raw value of a line from a .env file, as returned by
+/// [`parse_line_with_raw`].
+///
+/// [`parse_line_with_raw`]: fn.parse_line_with_raw.html
+pub type RawLine<'a> = (Cow<'a, str>, &'a str, &'a str);
+
+/// A line from a .env file along with its original text, as returned by
+/// [`parse_lines_with_source`].
+///
+/// [`parse_lines_with_source`]: fn.parse_lines_with_source.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SourceLine<'a> {
+    /// A line that was parsed into a key-value pair.
+    Entry {
+        /// The full original line.
+        raw: &'a str,
+        /// The parsed key.
+        key: Cow<'a, str>,
+        /// The parsed value.
+        value: &'a str,
+    },
+    /// A line that was not parsed into a key-value pair, such as a blank line
+    /// or a comment.
+    Raw(&'a str),
+}
+
+impl<'a> SourceLine<'a> {
+    /// Returns the full original line.
+    pub fn raw(&self) -> &'a str {
+        match *self {
+            SourceLine::Entry { raw, .. } | SourceLine::Raw(raw) => raw,
+        }
+    }
+}
+
+/// A difference between two sets of parsed lines, as returned by [`diff`].
+///
+/// [`diff`]: fn.diff.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// A key that is only defined by the current lines.
+    Added {
+        /// The added key.
+        key: String,
+        /// The value of the key in the current lines.
+        value: String,
+    },
+    /// A key that is only defined by the baseline lines.
+    Removed {
+        /// The removed key.
+        key: String,
+        /// The value of the key in the baseline lines.
+        value: String,
+    },
+    /// A key that is defined by both, but with differing values.
+    Modified {
+        /// The modified key.
+        key: String,
+        /// The value of the key in the baseline lines.
+        old: String,
+        /// The value of the key in the current lines.
+        new: String,
+    },
+}
+
+/// A key that is defined multiple times, as returned by [`find_duplicates`].
+///
+/// [`find_duplicates`]: fn.find_duplicates.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Duplicate<'a> {
+    /// The duplicated key.
+    pub key: &'a str,
+    /// The value of each definition of the key, in order.
+    pub values: Vec<&'a str>,
+}
+
+impl<'a> Duplicate<'a> {
+    /// Returns whether the definitions of the key have differing values.
+    ///
+    /// A duplicate that is not conflicting is a benign repeat, as it does not
+    /// matter which definition is used.
+    pub fn is_conflicting(&self) -> bool {
+        self.values.iter().any(|value| *value != self.values[0])
+    }
+}
+
+/// The encoding details of a .env file, as returned by [`detect_format`].
+///
+/// [`detect_format`]: fn.detect_format.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FileFormat {
+    /// Whether the buffer begins with a UTF-8 byte order mark.
+    pub bom: bool,
+    /// The style of line endings used by the buffer.
+    pub line_endings: LineEndings,
+}
+
+/// The style of line endings used by a buffer, as part of a [`FileFormat`].
+///
+/// [`FileFormat`]: struct.FileFormat.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineEndings {
+    /// Every line ends with `\n`.
+    Lf,
+    /// Every line ends with `\r\n`.
+    Crlf,
+    /// Some lines end with `\n` and others with `\r\n`.
+    Mixed,
+    /// The buffer has no line endings, such as a single line without a
+    /// trailing newline.
+    None,
+}
+
+/// A line of a .env document, as returned by [`parse_document`].
+///
+/// Unlike a [`ParsedLine`], every kind of line is represented, so that a
+/// document can be rewritten while preserving its layout. Formatting a line via
+/// its `Display` implementation produces the line in a normalized form.
+///
+/// [`ParsedLine`]: type.ParsedLine.html
+/// [`parse_document`]: fn.parse_document.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Line {
+    /// A line defining a variable, such as `KEY=value # comment`.
+    KeyValue {
+        /// The key of the variable.
+        key: String,
+        /// The value of the variable.
+        value: String,
+        /// The text of the comment following the value, after the `#`, if any.
+        inline_comment: Option<String>,
+    },
+    /// A line that is only a comment, holding the text after the `#`.
+    Comment(String),
+    /// A line that is empty or only whitespace.
+    Blank,
+    /// Any other line, such as one without an equals sign, holding its
+    /// original text.
+    Other(String),
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Line::KeyValue { ref key, ref value, ref inline_comment } => {
+                write!(f, "{}={}", key, value)?;
+
+                match *inline_comment {
+                    Some(ref comment) if value.is_empty() => {
+                        write!(f, "#{}", comment)
+                    },
+                    Some(ref comment) => write!(f, " #{}", comment),
+                    None => Ok(()),
+                }
+            },
+            Line::Comment(ref comment) => write!(f, "#{}", comment),
+            Line::Blank => Ok(()),
+            Line::Other(ref line) => f.write_str(line),
+        }
+    }
+}
+
+/// A soft issue with a line of a .env file, as returned by
+/// [`parse_with_warnings`].
+///
+/// Formatting a warning via its `Display` implementation produces a message
+/// such as `line 3: key PORT is already defined on an earlier line`.
+///
+/// [`parse_with_warnings`]: fn.parse_with_warnings.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning<'a> {
+    /// The 1-indexed number of the line that the warning is for.
+    pub line: usize,
+    /// The key defined by the line.
+    pub key: Cow<'a, str>,
+    /// The kind of issue.
+    pub kind: WarningKind,
+}
+
+impl<'a> Display for Warning<'a> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "line {}: ", self.line)?;
+
+        match self.kind {
+            WarningKind::TrailingWhitespace => {
+                write!(f, "trailing whitespace was trimmed from {}", self.key)
+            },
+            WarningKind::CommentTruncation => write!(
+                f,
+                "value of {} may be cut short by an unquoted #",
+                self.key,
+            ),
+            WarningKind::DuplicateKey => write!(
+                f,
+                "key {} is already defined on an earlier line",
+                self.key,
+            ),
+            WarningKind::LowercaseKey => {
+                write!(f, "key {} contains lowercase letters", self.key)
+            },
+        }
+    }
+}
+
+/// The kind of a [`Warning`].
+///
+/// [`Warning`]: struct.Warning.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WarningKind {
+    /// The value was followed by whitespace that was trimmed, without a
+    /// comment after it.
+    TrailingWhitespace,
+    /// The value contains a `#` that is not preceded by whitespace, such as in
+    /// `COLOR=#fff`, which starts a comment that was likely meant to be part
+    /// of the value.
+    CommentTruncation,
+    /// The key is already defined on an earlier line, so this definition
+    /// overrides it.
+    DuplicateKey,
+    /// The key contains lowercase letters, rather than being in
+    /// `SCREAMING_SNAKE_CASE`.
+    LowercaseKey,
+}
+
+/// A value with a type inferred from its string content.
+///
+/// Refer to [`Value::infer`] for the rules used to infer the type.
+///
+/// [`Value::infer`]: enum.Value.html#method.infer
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A value of either `true` or `false`.
+    Bool(bool),
+    /// A value with a fractional part, such as `1.5`.
+    Float(f64),
+    /// A value that is a whole number, such as `8080`.
+    Int(i64),
+    /// Any other value.
+    Str(String),
+}
+
+impl Value {
+    /// Infers the type of a value from its string content.
+    ///
+    /// The rules are, in order:
+    ///
+    /// - exactly `true` or `false` is a `Bool`;
+    /// - an optional `-` followed by digits is an `Int`, as long as it fits in
+    ///   an `i64`;
+    /// - an optional `-` followed by digits, a `.`, and more digits is a
+    ///   `Float`;
+    /// - anything else is a `Str`.
+    ///
+    /// Numbers with leading zeros (such as `0755` or `01.5`) are ambiguous, and
+    /// so are always a `Str`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kankyo::utils::Value;
+    ///
+    /// assert_eq!(Value::infer("true"), Value::Bool(true));
+    /// assert_eq!(Value::infer("8080"), Value::Int(8080));
+    /// assert_eq!(Value::infer("1.5"), Value::Float(1.5));
+    /// assert_eq!(Value::infer("0755"), Value::Str("0755".to_owned()));
+    /// ```
+    pub fn infer(value: &str) -> Self {
+        match value {
+            "true" => return Value::Bool(true),
+            "false" => return Value::Bool(false),
+            _ => {},
+        }
+
+        let unsigned = value.strip_prefix('-').unwrap_or(value);
+        let (whole, fraction) = match unsigned.find('.') {
+            Some(pos) => (&unsigned[..pos], Some(&unsigned[pos + 1..])),
+            None => (unsigned, None),
+        };
+
+        if is_digits(whole) && (whole == "0" || !whole.starts_with('0')) {
+            match fraction {
+                None => if let Ok(v) = value.parse() {
+                    return Value::Int(v);
+                },
+                Some(fraction) => if is_digits(fraction) {
+                    if let Ok(v) = value.parse() {
+                        return Value::Float(v);
+                    }
+                },
+            }
+        }
+
+        Value::Str(value.to_owned())
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The default maximum length of a key, in bytes, for use with
+/// [`check_limits`].
+///
+/// This reflects the maximum size of an environment variable on Windows.
+///
+/// [`check_limits`]: fn.check_limits.html
+pub const DEFAULT_MAX_KEY_LEN: usize = 32_767;
+
+/// The default maximum length of a value, in bytes, for use with
+/// [`check_limits`].
+///
+/// This reflects the maximum size of an environment variable on Windows.
+///
+/// [`check_limits`]: fn.check_limits.html
+pub const DEFAULT_MAX_VALUE_LEN: usize = 32_767;
+
+/// Returns the keys of the given lines whose key or value is longer than the
+/// given limits.
+///
+/// Lengths are measured in bytes, which is never less than the number of
+/// UTF-16 code units that Windows measures in.
+///
+/// # Examples
+///
+/// Check for values longer than 8 bytes:
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines("SHORT=abc\nLONG=abcdefghijk");
+/// let keys = utils::check_limits(&lines, utils::DEFAULT_MAX_KEY_LEN, 8);
+///
+/// assert_eq!(keys, vec!["LONG"]);
+/// ```
+pub fn check_limits<'a, K: AsRef<str>>(
+    lines: &'a [(K, &str)],
+    max_key: usize,
+    max_value: usize,
+) -> Vec<&'a str> {
+    lines
+        .iter()
+        .filter(|&&(ref k, v)| {
+            k.as_ref().len() > max_key || v.len() > max_value
+        })
+        .map(|(k, _)| k.as_ref())
+        .collect()
+}
+
+#[cfg(windows)]
+const PATH_SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+const PATH_SEPARATOR: &str = ":";
+
+/// Appends a value to a PATH-like variable in the environment, separated by
+/// the platform's path separator.
+///
+/// The separator is `;` on Windows and `:` elsewhere. If the variable does not
+/// exist or is empty, it is set to the value without a leading separator. A
+/// current value that is not valid unicode is kept as it is.
+///
+/// Nothing is done if the variable would be invalid, as determined by
+/// [`is_valid_var`].
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::env;
+///
+/// utils::append_path("APPEND_PATH_EXAMPLE", "/usr/bin");
+/// utils::append_path("APPEND_PATH_EXAMPLE", "/opt/bin");
+///
+/// # #[cfg(not(windows))]
+/// assert_eq!(env::var("APPEND_PATH_EXAMPLE").unwrap(), "/usr/bin:/opt/bin");
+/// ```
+///
+/// [`is_valid_var`]: fn.is_valid_var.html
+pub fn append_path(key: &str, value: &str) {
+    append_path_locked(&LockedEnv::new(), key, value);
+}
+
+// Appends a value to a PATH-like variable like `append_path`, in an
+// environment that is already locked.
+pub(crate) fn append_path_locked(
+    locked: &LockedEnv,
+    key: &str,
+    value: &str,
+) {
+    if !is_valid_var(key, value) {
+        return;
+    }
+
+    let mut path = match env::var_os(key) {
+        Some(ref current) if !current.is_empty() => {
+            let mut path = current.clone();
+            path.push(PATH_SEPARATOR);
+
+            path
+        },
+        _ => OsString::new(),
+    };
+    path.push(value);
+
+    locked.set_var(key, path);
+}
+
+/// Returns the keys of the given lines that contain lowercase letters.
+///
+/// This can be used to enforce that keys are in `SCREAMING_SNAKE_CASE`. Digits,
+/// underscores, and other characters without case are allowed.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines("PORT=80\nPort=80\nhost=localhost");
+///
+/// assert_eq!(utils::check_uppercase_keys(&lines), vec!["Port", "host"]);
+/// ```
+pub fn check_uppercase_keys<'a, K: AsRef<str>>(
+    lines: &'a [(K, &str)],
+) -> Vec<&'a str> {
+    lines
+        .iter()
+        .map(|(k, _)| k.as_ref())
+        .filter(|k| k.chars().any(char::is_lowercase))
+        .collect()
+}
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// the names referenced by its values that can not be resolved.
+///
+/// A reference, such as `${NAME}` or `$NAME`, resolves if its name is a key
+/// defined on any line of the reader or in the given map, such as a
+/// [`snapshot`] of the environment. This checks that interpolation via
+/// [`interpolate::resolve_two_pass`] would not expand any reference to an
+/// empty string, before loading. Each name is returned once, in the order that
+/// it is first referenced.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::collections::HashMap;
+/// use std::io::Cursor;
+///
+/// let mut against = HashMap::new();
+/// against.insert("HOST".to_owned(), "localhost".to_owned());
+///
+/// let mut cursor = Cursor::new("URL=http://${HOST}:${PORT}/${DB}\nDB=app");
+/// let dangling = utils::check_references(&mut cursor, &against).unwrap();
+///
+/// assert_eq!(dangling, vec!["PORT"]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`interpolate::resolve_two_pass`]: ../interpolate/fn.resolve_two_pass.html
+/// [`snapshot`]: ../fn.snapshot.html
+pub fn check_references<R: Read>(
+    reader: &mut R,
+    against: &HashMap<String, String>,
+) -> Result<Vec<String>> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let lines = parse_lines(&content);
+    let keys = lines.iter().map(|(key, _)| &**key).collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    let mut dangling = Vec::new();
+
+    for &(_, value) in &lines {
+        for name in interpolate::references(value) {
+            let resolves = keys.contains(name) || against.contains_key(name);
+
+            if !resolves && seen.insert(name) {
+                dangling.push(name.to_owned());
+            }
+        }
+    }
+
+    Ok(dangling)
+}
+
+
+/// Computes how the current lines differ from the baseline lines.
+///
+/// If a key is defined multiple times, its last definition is used, as when
+/// loading. Removed and modified keys are returned in the order that they are
+/// first defined in the baseline, followed by added keys in the order that they
+/// are first defined in the current lines. Keys with the same value in both are
+/// not returned.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, Change};
+///
+/// let baseline = utils::parse_lines("HOST=localhost\nPORT=80");
+/// let current = utils::parse_lines("PORT=8080\nDEBUG=1");
+///
+/// assert_eq!(utils::diff(&baseline, &current), vec![
+///     Change::Removed {
+///         key: "HOST".to_owned(),
+///         value: "localhost".to_owned(),
+///     },
+///     Change::Modified {
+///         key: "PORT".to_owned(),
+///         old: "80".to_owned(),
+///         new: "8080".to_owned(),
+///     },
+///     Change::Added {
+///         key: "DEBUG".to_owned(),
+///         value: "1".to_owned(),
+///     },
+/// ]);
+/// ```
+pub fn diff<K: AsRef<str>>(
+    baseline: &[(K, &str)],
+    current: &[(K, &str)],
+) -> Vec<Change> {
+    let old = baseline
+        .iter()
+        .map(|&(ref k, v)| (k.as_ref(), v))
+        .collect::<HashMap<_, _>>();
+    let new = current
+        .iter()
+        .map(|&(ref k, v)| (k.as_ref(), v))
+        .collect::<HashMap<_, _>>();
+    let mut changes = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (key, _) in baseline {
+        let key = key.as_ref();
+
+        if !seen.insert(key) {
+            continue;
+        }
+
+        match new.get(key) {
+            Some(value) if *value == old[key] => {},
+            Some(value) => changes.push(Change::Modified {
+                key: key.to_owned(),
+                old: old[key].to_owned(),
+                new: (*value).to_owned(),
+            }),
+            None => changes.push(Change::Removed {
+                key: key.to_owned(),
+                value: old[key].to_owned(),
+            }),
+        }
+    }
+
+    for (key, _) in current {
+        let key = key.as_ref();
+
+        if !seen.insert(key) {
+            continue;
+        }
+
+        changes.push(Change::Added {
+            key: key.to_owned(),
+            value: new[key].to_owned(),
+        });
+    }
+
+    changes
+}
+
+/// Detects whether a buffer begins with a byte order mark and which style of
+/// line endings it uses.
+///
+/// This is useful for linting that files conform to a house style, such as
+/// having no BOM and only LF line endings.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, LineEndings};
+///
+/// let format = utils::detect_format("\u{feff}A=1\r\nB=2\n");
+///
+/// assert!(format.bom);
+/// assert_eq!(format.line_endings, LineEndings::Mixed);
+/// ```
+pub fn detect_format(buf: &str) -> FileFormat {
+    let mut lf = false;
+    let mut crlf = false;
+
+    for (pos, _) in buf.match_indices('\n') {
+        if buf[..pos].ends_with('\r') {
+            crlf = true;
+        } else {
+            lf = true;
+        }
+    }
+
+    let line_endings = match (lf, crlf) {
+        (true, true) => LineEndings::Mixed,
+        (true, false) => LineEndings::Lf,
+        (false, true) => LineEndings::Crlf,
+        (false, false) => LineEndings::None,
+    };
+
+    FileFormat {
+        bom: buf.starts_with('\u{feff}'),
+        line_endings,
+    }
+}
+
+/// Replaces a leading `~` in a path value with the user's home directory.
+///
+/// Only a value that is exactly `~` or begins with `~/` is expanded, so values
+/// such as `~user/x` or `a~b` are returned unchanged. The home directory is
+/// read from `HOME`, or `USERPROFILE` on Windows; if it is not set, the value
+/// is returned unchanged.
+///
+/// Register it on [`Decoders`] via [`Decoders::expand_tilde`] to apply it to
+/// the values of path keys while loading.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// # std::env::set_var("HOME", "/home/user");
+///
+/// assert_eq!(utils::expand_tilde("~/data"), "/home/user/data");
+/// assert_eq!(utils::expand_tilde("a~b"), "a~b");
+/// ```
+///
+/// [`Decoders`]: ../struct.Decoders.html
+/// [`Decoders::expand_tilde`]: ../struct.Decoders.html#method.expand_tilde
+pub fn expand_tilde(value: &str) -> String {
+    let rest = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => return value.to_owned(),
+    };
+
+    match home_dir() {
+        Some(home) => join_home(&home, rest),
+        None => value.to_owned(),
+    }
+}
+
+// Joins the home directory and the rest of a path after a tilde, without
+// doubling the slash between them, while keeping a home directory of `/`.
+fn join_home(home: &str, rest: &str) -> String {
+    let joined = format!("{}{}", home.trim_end_matches('/'), rest);
+
+    if joined.is_empty() {
+        "/".to_owned()
+    } else {
+        joined
+    }
+}
+
+#[cfg(windows)]
+fn home_dir() -> Option<String> {
+    env::var("USERPROFILE").ok().filter(|home| !home.is_empty())
+}
+
+#[cfg(not(windows))]
+fn home_dir() -> Option<String> {
+    env::var("HOME").ok().filter(|home| !home.is_empty())
+}
+
+/// Finds the keys that are defined more than once in the given lines.
+///
+/// Duplicates are returned in the order that their key is first defined. Use
+/// [`Duplicate::is_conflicting`] to distinguish keys that are repeated with
+/// the same value from those whose values conflict.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines("PORT=80\nHOST=a\nPORT = 80\nHOST=b");
+/// let duplicates = utils::find_duplicates(&lines);
+///
+/// assert_eq!(duplicates[0].key, "PORT");
+/// assert!(!duplicates[0].is_conflicting());
+/// assert_eq!(duplicates[1].values, vec!["a", "b"]);
+/// assert!(duplicates[1].is_conflicting());
+/// ```
+///
+/// [`Duplicate::is_conflicting`]: struct.Duplicate.html#method.is_conflicting
+pub fn find_duplicates<'a, K: AsRef<str>>(
+    lines: &'a [(K, &'a str)],
+) -> Vec<Duplicate<'a>> {
+    let mut defined: Vec<Duplicate> = Vec::new();
+    // The index of each key's entry in `defined`.
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        match positions.get(key) {
+            Some(&idx) => defined[idx].values.push(value),
+            None => {
+                positions.insert(key, defined.len());
+                defined.push(Duplicate {
+                    key,
+                    values: vec![value],
+                });
+            },
+        }
+    }
+
+    defined.retain(|d| d.values.len() > 1);
+
+    defined
+}
+
+/// Returns the keys that a buffer defines, without setting any of them.
+///
+/// Each key is listed once, in the order that it is first defined.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let keys = utils::keys_in("FOO=bar\nBAR=baz\nFOO=qux");
+///
+/// assert_eq!(keys, vec!["FOO", "BAR"]);
+/// ```
+pub fn keys_in(buf: &str) -> Vec<String> {
+    let lines = parse_lines(buf);
+    let mut all = Vec::with_capacity(lines.len());
+    only_keys(&lines, &mut all);
+
+    let mut seen = HashSet::with_capacity(all.len());
+
+    all.into_iter()
+        .filter(|key| seen.insert(*key))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Reads the content of a reader and parses it to find `.env` lines, returning
+/// the keys that are not yet present in the environment, without modifying it.
+///
+/// These are the keys that loading the reader with overwriting disabled would
+/// add, making this the complement of [`conflicts_from_reader`]. Keys that are
+/// not valid to set are excluded. Each key is returned once, in the order that
+/// it is first defined.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::env;
+/// use std::io::Cursor;
+///
+/// env::set_var("MISSING_EXAMPLE_ON", "1");
+///
+/// let mut cursor = Cursor::new("MISSING_EXAMPLE_ON=2\nMISSING_EXAMPLE_NEW=1");
+/// let missing = utils::missing_from_env(&mut cursor).unwrap();
+///
+/// assert_eq!(missing, vec!["MISSING_EXAMPLE_NEW"]);
+/// ```
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if there was an error reading from the reader.
+///
+/// [`conflicts_from_reader`]: ../fn.conflicts_from_reader.html
+pub fn missing_from_env<R: Read>(reader: &mut R) -> Result<Vec<String>> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+
+    for (key, value) in parse_lines(&content) {
+        if is_valid_var(&key, value)
+            && env::var_os(&*key).is_none()
+            && seen.insert(key.clone()) {
+            missing.push(key.into_owned());
+        }
+    }
+
+    Ok(missing)
+}
 
 /// Maps the given slice of [`ParsedLine`] into a vector of their keys.
 ///
@@ -49,42 +855,635 @@ pub type ParsedLine<'a> = (&'a str, &'a str);
 //
 // reason: there are scenarios where this will _slighty_ improve performance,
 // perhaps if the user has a vector of keys they are already working with.
-pub fn only_keys<'a>(lines: &'a [ParsedLine], keys: &mut Vec<&'a str>) {
-    for &(key, _) in lines {
-        keys.push(key);
+pub fn only_keys<'a, K: AsRef<str>>(
+    lines: &'a [(K, &str)],
+    keys: &mut Vec<&'a str>,
+) {
+    for (key, _) in lines {
+        keys.push(key.as_ref());
     }
 }
 
-/// Returns a `Vec` of `ParsedLine`s, each line representing a parsed key-value
-/// pair of the given buffer.
+/// Formats a key-value pair as a .env file line.
 ///
-/// # Examples
+/// This is the inverse of [`parse_line`]: any pair produced by `parse_line`
+/// will be parsed back into the same pair. An equals sign in the key is
+/// escaped as `\=`, as is a backslash that would otherwise be read as the
+/// start of an escape.
 ///
-/// Parses a buffer into parsed lines, which are a key-value pair of string
-/// slices:
+/// # Examples
 ///
 /// ```rust
 /// use kankyo::utils;
 ///
-/// let input = "FOO=bar\nBAZ=qux";
-/// let lines = utils::parse_lines(input);
+/// let line = utils::format_line("FOO", "bar");
+///
+/// assert_eq!(line, "FOO=bar");
+/// assert_eq!(utils::parse_line(&line), Some(("FOO".into(), "bar")));
+///
+/// assert_eq!(utils::format_line("A=B", "C"), r"A\=B=C");
+/// ```
+///
+/// [`parse_line`]: fn.parse_line.html
+pub fn format_line(key: &str, value: &str) -> String {
+    let key = escape_key(key);
+    let mut line = String::with_capacity(key.len() + value.len() + 1);
+    line.push_str(&key);
+    line.push('=');
+    line.push_str(value);
+
+    line
+}
+
+/// Returns a `Vec` of `ParsedLine`s, each line representing a parsed key-value
+/// pair of the given buffer.
+///
+/// # Examples
+///
+/// Parses a buffer into parsed lines, which are a key-value pair of string
+/// slices:
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let input = "FOO=bar\nBAZ=qux";
+/// let lines = utils::parse_lines(input);
 ///
 /// // Make sure there are two pairs in the resultant vector:
 /// assert_eq!(lines.len(), 2);
 /// ```
 #[inline]
-pub fn parse_lines(buf: &str) -> Vec<ParsedLine> {
+pub fn parse_lines<'a>(buf: &'a str) -> Vec<ParsedLine<'a>> {
     buf.lines().filter_map(parse_line).collect()
 }
 
+/// Returns a `Vec` of `ParsedLine`s like [`parse_lines`], but stopping after
+/// the first `max` pairs.
+///
+/// Lines that do not parse into a pair, such as comments and blank lines, do
+/// not count towards the maximum. The rest of the buffer is not parsed once
+/// the maximum is reached.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines_limited("# comment\nA=1\n\nB=2\nC=3", 2);
+///
+/// assert_eq!(lines, vec![("A".into(), "1"), ("B".into(), "2")]);
+/// ```
+///
+/// [`parse_lines`]: fn.parse_lines.html
+#[inline]
+pub fn parse_lines_limited<'a>(
+    buf: &'a str,
+    max: usize,
+) -> Vec<ParsedLine<'a>> {
+    buf.lines().filter_map(parse_line).take(max).collect()
+}
+
+/// Returns an iterator over the `ParsedLine`s of the given buffer, each
+/// alongside its 1-indexed line number, parsed lazily like [`parse_lines`].
+///
+/// Every line, including blank and comment lines, counts towards the
+/// numbering, but only lines that parse into a key-value pair are yielded.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let mut iter = utils::parse_iter_numbered("# comment\n\nFOO=bar");
+///
+/// assert_eq!(iter.next(), Some((3, ("FOO".into(), "bar"))));
+/// assert_eq!(iter.next(), None);
+/// ```
+///
+/// [`parse_lines`]: fn.parse_lines.html
+pub fn parse_iter_numbered<'a>(
+    buf: &'a str,
+) -> impl Iterator<Item = (usize, ParsedLine<'a>)> + 'a {
+    buf.lines()
+        .enumerate()
+        .filter_map(|(idx, line)| parse_line(line).map(|pair| (idx + 1, pair)))
+}
+
+/// Returns a `Vec` of owned key-value pairs, parsed like [`parse_lines`].
+///
+/// This is useful when the parsed lines need to outlive the buffer. See
+/// [`parse_lines_interned`] for a variant that avoids allocating a new key for
+/// each repeated key.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines_owned("A=1\nB=2");
+///
+/// assert_eq!(lines[1], ("B".to_owned(), "2".to_owned()));
+/// ```
+///
+/// [`parse_lines`]: fn.parse_lines.html
+/// [`parse_lines_interned`]: fn.parse_lines_interned.html
+pub fn parse_lines_owned(buf: &str) -> Vec<(String, String)> {
+    buf.lines()
+        .filter_map(parse_line)
+        .map(|(key, value)| (key.into_owned(), value.to_owned()))
+        .collect()
+}
+
+/// Returns a `Vec` of owned key-value pairs like [`parse_lines_owned`], but
+/// with keys interned.
+///
+/// Each distinct key is allocated once, and every line with that key shares
+/// the same storage. This reduces allocations for large files which define
+/// the same keys many times, such as generated files.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::rc::Rc;
+///
+/// let lines = utils::parse_lines_interned("A=1\nB=2\nA=3");
+///
+/// assert_eq!(&*lines[2].0, "A");
+/// assert!(Rc::ptr_eq(&lines[0].0, &lines[2].0));
+/// ```
+///
+/// [`parse_lines_owned`]: fn.parse_lines_owned.html
+pub fn parse_lines_interned(buf: &str) -> Vec<(Rc<str>, String)> {
+    let mut keys: HashMap<Cow<str>, Rc<str>> = HashMap::new();
+
+    buf.lines()
+        .filter_map(parse_line)
+        .map(|(key, value)| {
+            let key = keys
+                .entry(key)
+                .or_insert_with_key(|key| Rc::from(&**key))
+                .clone();
+
+            (key, value.to_owned())
+        })
+        .collect()
+}
+
+/// Parses a buffer in the format of `/proc/self/environ`, where each record is
+/// a `KEY=VALUE` pair terminated by a NUL byte (`\0`).
+///
+/// Unlike [`parse_lines`], keys and values are taken verbatim: `#` does not
+/// start a comment, and whitespace is not trimmed. Records without an equals
+/// sign are skipped, so the empty segment after a trailing NUL byte does not
+/// produce an entry. A record such as `KEY=` is a variable with an empty
+/// value.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_environ("COLOR=#fff\0PADDED= a \0EMPTY=\0");
+///
+/// assert_eq!(lines, vec![
+///     ("COLOR".into(), "#fff"),
+///     ("PADDED".into(), " a "),
+///     ("EMPTY".into(), ""),
+/// ]);
+/// ```
+///
+/// [`parse_lines`]: fn.parse_lines.html
+pub fn parse_environ<'a>(buf: &'a str) -> Vec<ParsedLine<'a>> {
+    buf.split('\0')
+        .filter_map(|record| {
+            record.find('=').map(|pos| {
+                (Cow::Borrowed(&record[..pos]), &record[pos + 1..])
+            })
+        })
+        .collect()
+}
+
+/// Returns a `Vec` of `ParsedLine`s like [`parse_lines`], but splitting records
+/// on the given separator rather than on line endings.
+///
+/// This can be used to parse files with `\r`-only line endings, or
+/// `\0`-separated records such as those of `/proc/self/environ`.
+///
+/// # Examples
+///
+/// Parse a buffer of NUL-separated records:
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = utils::parse_lines_sep("FOO=bar\0BAZ=qux\0", '\0');
+///
+/// assert_eq!(lines, vec![("FOO".into(), "bar"), ("BAZ".into(), "qux")]);
+/// ```
+///
+/// [`parse_lines`]: fn.parse_lines.html
+#[inline]
+pub fn parse_lines_sep<'a>(
+    buf: &'a str,
+    record_sep: char,
+) -> Vec<ParsedLine<'a>> {
+    buf.split(record_sep).filter_map(parse_line).collect()
+}
+
+/// Returns a `Vec` of the `ParsedLine`s under the given section of an INI-like
+/// buffer.
+///
+/// A section begins with a header line of the form `[name]`, and ends at the
+/// next header. Whitespace around the header and its name is ignored, and
+/// names are case-sensitive. Lines before the first header belong to the
+/// default section, whose name is the empty string. If a section has multiple
+/// headers, the lines under each of them are returned.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let buf = "NAME=app\n[server]\nPORT=80\n[env]\nDEBUG=1";
+///
+/// assert_eq!(utils::parse_section(buf, "env"), vec![("DEBUG".into(), "1")]);
+/// assert_eq!(utils::parse_section(buf, ""), vec![("NAME".into(), "app")]);
+/// ```
+pub fn parse_section<'a>(buf: &'a str, section: &str) -> Vec<ParsedLine<'a>> {
+    let mut current = "";
+    let mut lines = Vec::new();
+
+    for line in buf.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            current = trimmed[1..trimmed.len() - 1].trim();
+        } else if current == section {
+            lines.extend(parse_line(line));
+        }
+    }
+
+    lines
+}
+
+/// Parses every line of a buffer into a document of [`Line`]s, keeping comments
+/// and blank lines.
+///
+/// Keys and values are parsed like [`parse_line`]. The document can be turned
+/// back into a buffer via [`format_document`], which normalizes whitespace
+/// around keys, values, and comments, but otherwise reproduces the buffer.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, Line};
+///
+/// let lines = utils::parse_document("# database\nHOST=localhost # local\n\n");
+///
+/// assert_eq!(lines, vec![
+///     Line::Comment(" database".to_owned()),
+///     Line::KeyValue {
+///         key: "HOST".to_owned(),
+///         value: "localhost".to_owned(),
+///         inline_comment: Some(" local".to_owned()),
+///     },
+///     Line::Blank,
+/// ]);
+/// ```
+///
+/// [`Line`]: enum.Line.html
+/// [`format_document`]: fn.format_document.html
+/// [`parse_line`]: fn.parse_line.html
+pub fn parse_document(buf: &str) -> Vec<Line> {
+    buf.lines()
+        .map(|line| {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                return Line::Blank;
+            }
+
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                return Line::Comment(comment.to_owned());
+            }
+
+            match parse_line(line) {
+                Some((key, value)) => {
+                    // The first `#` after the equals sign starts the comment,
+                    // as a `#` before it would have made the line a comment.
+                    let equals = find_separator(line).unwrap_or(0);
+                    let after_equals = &line[equals..];
+                    let inline_comment = after_equals
+                        .find('#')
+                        .map(|pos| after_equals[pos + 1..].to_owned());
+
+                    Line::KeyValue {
+                        key: key.into_owned(),
+                        value: value.to_owned(),
+                        inline_comment,
+                    }
+                },
+                None => Line::Other(line.to_owned()),
+            }
+        })
+        .collect()
+}
+
+/// Formats a document of [`Line`]s into a buffer, with each line terminated by
+/// a newline.
+///
+/// This is the inverse of [`parse_document`].
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let buf = "# database\nHOST=localhost # local\n\nPORT=5432\n";
+///
+/// assert_eq!(utils::format_document(&utils::parse_document(buf)), buf);
+/// ```
+///
+/// [`Line`]: enum.Line.html
+/// [`parse_document`]: fn.parse_document.html
+pub fn format_document(lines: &[Line]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Rewrites a .env buffer into a canonical form.
+///
+/// The buffer is parsed via [`parse_document`] and formatted back via
+/// [`format_document`], so that keys, values, and comments are trimmed of
+/// surrounding whitespace, and every line ends with `\n`. A leading byte order
+/// mark is removed. Comments and blank lines are preserved.
+///
+/// A buffer is in canonical form if normalizing it returns it unchanged, which
+/// can be used to enforce formatting in CI.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let buf = "\u{feff}# database\r\n HOST = localhost  #local\r\nPORT=5432";
+///
+/// assert_eq!(
+///     utils::normalize(buf),
+///     "# database\nHOST=localhost #local\nPORT=5432\n",
+/// );
+/// ```
+///
+/// [`format_document`]: fn.format_document.html
+/// [`parse_document`]: fn.parse_document.html
+pub fn normalize(buf: &str) -> String {
+    let buf = buf.strip_prefix('\u{feff}').unwrap_or(buf);
+
+    format_document(&parse_document(buf))
+}
+
+/// Returns a copy of a .env buffer with the values of the given keys changed
+/// in place, appending any keys that the buffer does not define.
+///
+/// Unlike rewriting the buffer via [`format_document`], every other byte of
+/// the buffer is kept as-is, including comments, blank lines, whitespace, and
+/// line endings, so that an edit produces a minimal diff. If a key is defined
+/// on multiple lines, each of them is changed. New keys are appended in order
+/// via [`format_line`], using the buffer's line endings.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `InvalidInput` if a change can not be
+/// written such that it parses back unchanged, as a .env file has no way of
+/// quoting a value. This is the case for a key or value that contains a `#` or
+/// a line ending, or that begins or ends with whitespace.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let buf = "# server\nHOST = localhost # local\nPORT=80\n";
+/// let changes = [("HOST", "example.com"), ("TLS", "1")];
+/// let patched = utils::patch_env(buf, &changes).unwrap();
+///
+/// assert_eq!(
+///     patched,
+///     "# server\nHOST = example.com # local\nPORT=80\nTLS=1\n",
+/// );
+///
+/// assert!(utils::patch_env(buf, &[("HOST", "a\nB=b")]).is_err());
+/// ```
+///
+/// [`format_document`]: fn.format_document.html
+/// [`format_line`]: fn.format_line.html
+pub fn patch_env(original: &str, changes: &[(&str, &str)]) -> Result<String> {
+    for &(key, value) in changes {
+        let line = format_line(key, value);
+        let multiline = line.contains(['\n', '\r']);
+
+        if multiline || parse_line(&line) != Some((key.into(), value)) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                format!("can not write {:?} as a .env line", line),
+            ));
+        }
+    }
+
+    let ending = match detect_format(original).line_endings {
+        LineEndings::Crlf => "\r\n",
+        _ => "\n",
+    };
+    let mut patched = String::with_capacity(original.len());
+    let mut found = vec![false; changes.len()];
+
+    for line in original.split_inclusive('\n') {
+        let content = line.strip_suffix('\n').unwrap_or(line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+
+        let (key, value) = match parse_line(content) {
+            Some(pair) => pair,
+            None => {
+                patched.push_str(line);
+
+                continue;
+            },
+        };
+
+        // The last change to a key wins.
+        let idx = match changes.iter().rposition(|&(k, _)| k == key) {
+            Some(idx) => idx,
+            None => {
+                patched.push_str(line);
+
+                continue;
+            },
+        };
+
+        for (found, &(k, _)) in found.iter_mut().zip(changes) {
+            *found |= k == key;
+        }
+
+        let start = value.as_ptr() as usize - line.as_ptr() as usize;
+        patched.push_str(&line[..start]);
+        patched.push_str(changes[idx].1);
+        patched.push_str(&line[start + value.len()..]);
+    }
+
+    for (idx, &(key, value)) in changes.iter().enumerate() {
+        let duplicate = changes[idx + 1..].iter().any(|&(k, _)| k == key);
+
+        if found[idx] || duplicate {
+            continue;
+        }
+
+        if !patched.is_empty() && !patched.ends_with('\n') {
+            patched.push_str(ending);
+        }
+
+        patched.push_str(&format_line(key, value));
+        patched.push_str(ending);
+    }
+
+    Ok(patched)
+}
+
+/// Returns a `Vec` of every line of the given buffer, each alongside its
+/// original text.
+///
+/// Unlike [`parse_lines`], lines that do not parse into a key-value pair are
+/// kept as [`SourceLine::Raw`]. The original text of each line is a slice of
+/// the buffer, not including the line ending, so that a line can be replaced
+/// in place.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, SourceLine};
+///
+/// let lines = utils::parse_lines_with_source("# comment\nFOO = bar");
+///
+/// assert_eq!(lines[0], SourceLine::Raw("# comment"));
+/// assert_eq!(lines[1], SourceLine::Entry {
+///     raw: "FOO = bar",
+///     key: "FOO".into(),
+///     value: "bar",
+/// });
+/// ```
+///
+/// [`SourceLine::Raw`]: enum.SourceLine.html#variant.Raw
+/// [`parse_lines`]: fn.parse_lines.html
+pub fn parse_lines_with_source<'a>(buf: &'a str) -> Vec<SourceLine<'a>> {
+    buf.lines()
+        .map(|line| match parse_line(line) {
+            Some((key, value)) => SourceLine::Entry {
+                raw: line,
+                key,
+                value,
+            },
+            None => SourceLine::Raw(line),
+        })
+        .collect()
+}
+
+/// Returns a `Vec` of `ParsedLine`s like [`parse_lines`], along with warnings
+/// about soft issues with the lines that may be worth reporting, such as in a
+/// verbose mode.
+///
+/// Refer to [`WarningKind`] for the issues that are checked. Warnings are
+/// returned in order of their line, and a line may have multiple warnings.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, WarningKind};
+///
+/// let (lines, warnings) = utils::parse_with_warnings("COLOR=#fff\nport=80");
+///
+/// assert_eq!(lines, vec![("COLOR".into(), ""), ("port".into(), "80")]);
+/// assert_eq!(warnings[0].kind, WarningKind::CommentTruncation);
+/// assert_eq!(warnings[1].line, 2);
+/// assert_eq!(
+///     warnings[1].to_string(),
+///     "line 2: key port contains lowercase letters",
+/// );
+/// ```
+///
+/// [`WarningKind`]: enum.WarningKind.html
+/// [`parse_lines`]: fn.parse_lines.html
+pub fn parse_with_warnings<'a>(
+    buf: &'a str,
+) -> (Vec<ParsedLine<'a>>, Vec<Warning<'a>>) {
+    let mut lines: Vec<ParsedLine> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (idx, line) in buf.lines().enumerate() {
+        let (key, value, raw) = match parse_line_with_raw(line) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        let mut warn = |kind| warnings.push(Warning {
+            line: idx + 1,
+            key: key.clone(),
+            kind,
+        });
+
+        match raw.find('#') {
+            Some(pos) if !raw[..pos].ends_with(char::is_whitespace) => {
+                warn(WarningKind::CommentTruncation);
+            },
+            Some(_) => {},
+            None if raw.ends_with(char::is_whitespace) && !value.is_empty() => {
+                warn(WarningKind::TrailingWhitespace);
+            },
+            None => {},
+        }
+
+        if !seen.insert(key.clone()) {
+            warn(WarningKind::DuplicateKey);
+        }
+
+        if key.chars().any(char::is_lowercase) {
+            warn(WarningKind::LowercaseKey);
+        }
+
+        lines.push((key, value));
+    }
+
+    (lines, warnings)
+}
+
 /// Parses a .env file line.
 ///
 /// This will take a line and return a tuple of the key and value, where the
 /// tuple values map to the string `"$0=$1"`.
 ///
+/// The key and value are separated by the first equals sign that is not
+/// escaped. Within the key, `\=` is a literal equals sign and `\\` is a
+/// literal backslash, while any other backslash is kept as-is. The key is
+/// returned unescaped, and only allocates if it contained an escape.
+///
+/// As such, a line such as `KEY\=value` has no separator and is not parsed,
+/// whereas a key ending in a backslash is written as `KEY\\=value`. A key
+/// containing an equals sign can be applied to sinks such as a
+/// [`HashMapSink`], but can not be set in the environment, and is skipped
+/// when loading.
+///
+/// Whitespace, including tabs, is trimmed from both the key and the value, so
+/// indented lines parse the same as unindented ones.
+///
 /// In the event the input string does not match the above format, `None` will
 /// be returned.
 ///
+/// Only the _first_ equals sign splits the key from the value; any further
+/// equals signs are part of the value. A line is considered to be a comment if
+/// a `#` appears before that first equals sign.
+///
+/// A value that is entirely a comment, such as in `KEY=#abc`, is an empty
+/// value rather than no value, and so the key is set to an empty string. Use
+/// [`Parser::skip_comment_values`] to instead skip such lines.
+///
 /// # Examples
 ///
 /// Assert that parsing various strings either properly parse or do not:
@@ -92,77 +1491,674 @@ pub fn parse_lines(buf: &str) -> Vec<ParsedLine> {
 /// ```rust
 /// use kankyo::utils;
 ///
-/// assert!(utils::parse_line("hello").is_none()); // does not properly parse
-/// assert!(utils::parse_line("HELLO=world").is_some()); // does parse
-/// assert!(utils::parse_line("HELLO=world=!").is_some());
-/// assert!(utils::parse_line("HELLO   =world!").is_some());
-/// assert!(utils::parse_line("HELLO=").is_some()); // a 0-length value is valid
+/// assert!(utils::parse_line("hello").is_none()); // does not properly parse
+/// assert!(utils::parse_line("HELLO=world").is_some()); // does parse
+/// assert!(utils::parse_line("HELLO=world=!").is_some());
+/// assert!(utils::parse_line("HELLO   =world!").is_some());
+/// assert!(utils::parse_line("HELLO=").is_some()); // a 0-length value is valid
+///
+/// // Only the first equals sign is a separator:
+/// assert_eq!(
+///     utils::parse_line("HELLO==world"),
+///     Some(("HELLO".into(), "=world")),
+/// );
+///
+/// // A value that is entirely a comment is empty:
+/// assert_eq!(utils::parse_line("HELLO=#world"), Some(("HELLO".into(), "")));
+///
+/// // An escaped equals sign or backslash is part of the key:
+/// let (key, value) = utils::parse_line(r"A\=B=C").unwrap();
+/// assert_eq!((&*key, value), ("A=B", "C"));
+///
+/// let (key, _) = utils::parse_line(r"A\\=B").unwrap();
+/// assert_eq!(key, r"A\");
+/// ```
+///
+/// [`HashMapSink`]: ../type.HashMapSink.html
+/// [`Parser::skip_comment_values`]: ../struct.Parser.html#method.skip_comment_values
+pub fn parse_line<'a>(line: &'a str) -> Option<ParsedLine<'a>> {
+    split_line(line).map(|(key, value)| (unescape_key(key), value))
+}
+
+// Splits a line into its key, still escaped, and its value, as described by
+// `parse_line`.
+pub(crate) fn split_line(line: &str) -> Option<(&str, &str)> {
+    // Both of these are ASCII characters, whose bytes never occur within a
+    // multibyte UTF-8 sequence, so the bytes can be scanned directly and the
+    // positions are always on char boundaries and are safe to slice at.
+    //
+    // A single scan finds whichever of the two comes first: if it is a hash,
+    // then the line is a comment. An escaped equals sign is skipped.
+    let bytes = line.as_bytes();
+    let pos_equals = find_unescaped(bytes, |b| b == b'#' || b == b'=')?;
+
+    if bytes[pos_equals] == b'#' {
+        return None;
+    }
+
+    // We have the position of the equals sign, so we know for sure what the
+    // key is.
+    let key = &line[..pos_equals];
+    // We skip the equals sign, so add one to the position..
+    let post_idx = pos_equals + 1;
+    // Slice the input line after the equals sign and check if there's a hash.
+    //
+    // The hash is where a comment, if there is one, begins.
+    //
+    // If there is a hash, then slice from `post_idx` until its position. If
+    // there is not, slice from `post_idx` until the end.
+    let value = match bytes[post_idx..].iter().position(|&b| b == b'#') {
+        Some(pos_pound) => &line[post_idx..post_idx + pos_pound],
+        None => &line[post_idx..],
+    };
+
+    Some((key.trim(), value.trim()))
+}
+
+/// Parses a .env file line like [`parse_line`], but also returning the raw
+/// value.
+///
+/// The raw value is everything after the first equals sign, before whitespace
+/// is trimmed and any comment is removed. This is useful for tooling that
+/// shows what a file literally contains alongside the value that is used.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// assert_eq!(
+///     utils::parse_line_with_raw("KEY= hello # note"),
+///     Some(("KEY".into(), "hello", " hello # note")),
+/// );
+/// ```
+///
+/// [`parse_line`]: fn.parse_line.html
+pub fn parse_line_with_raw<'a>(line: &'a str) -> Option<RawLine<'a>> {
+    parse_line(line).map(|(key, value)| {
+        let equals = find_separator(line).unwrap_or(0);
+
+        (key, value, &line[equals + 1..])
+    })
+}
+
+/// Parses a .env file line like [`parse_line`], but with support for quoted
+/// values containing escape sequences.
+///
+/// This is the same as parsing the line with a [`Parser`] that has
+/// [`quotes`], [`escapes`], and [`lenient_quotes`] enabled: a value surrounded
+/// by single quotes (`'`) is taken literally, a value surrounded by double
+/// quotes (`"`) may contain escape sequences, and anything after the closing
+/// quote is ignored.
+///
+/// The value borrows from the line unless it contains an escape sequence, so
+/// that only values which need unescaping allocate.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::borrow::Cow;
+///
+/// let (_, value) = utils::parse_line_cow("PLAIN=value # comment").unwrap();
+/// assert_eq!(value, Cow::Borrowed("value"));
+///
+/// let (_, value) = utils::parse_line_cow(r#"ESCAPED="a\nb""#).unwrap();
+/// assert_eq!(value, Cow::<str>::Owned("a\nb".to_owned()));
+/// ```
+///
+/// [`Parser`]: ../struct.Parser.html
+/// [`escapes`]: ../struct.Parser.html#method.escapes
+/// [`lenient_quotes`]: ../struct.Parser.html#method.lenient_quotes
+/// [`parse_line`]: fn.parse_line.html
+/// [`quotes`]: ../struct.Parser.html#method.quotes
+pub fn parse_line_cow<'a>(line: &'a str) -> Option<CowLine<'a>> {
+    let parser = Parser::new()
+        .quotes(true)
+        .escapes(true)
+        .lenient_quotes(true);
+
+    match parser.parse_line(line) {
+        Ok(Some(Directive::Set(key, value))) => Some((key, value)),
+        _ => None,
+    }
+}
+
+// Finds the position of the first equals sign that is not escaped.
+pub(crate) fn find_separator(line: &str) -> Option<usize> {
+    find_unescaped(line.as_bytes(), |b| b == b'=')
+}
+
+// Finds the position of the first byte matching the predicate that is not part
+// of an escape of a key, being `\=` or `\\`.
+fn find_unescaped<F>(bytes: &[u8], predicate: F) -> Option<usize>
+    where F: Fn(u8) -> bool {
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        if is_key_escape(bytes, idx) {
+            idx += 2;
+        } else if predicate(bytes[idx]) {
+            return Some(idx);
+        } else {
+            idx += 1;
+        }
+    }
+
+    None
+}
+
+// Returns whether the byte at the index begins an escape of a key.
+fn is_key_escape(bytes: &[u8], idx: usize) -> bool {
+    bytes[idx] == b'\\'
+        && matches!(bytes.get(idx + 1), Some(&b'\\') | Some(&b'='))
+}
+
+// Replaces each escape of a key with the character that it escapes, only
+// allocating if there are any.
+pub(crate) fn unescape_key(key: &str) -> Cow<'_, str> {
+    let bytes = key.as_bytes();
+
+    if !(0..bytes.len()).any(|idx| is_key_escape(bytes, idx)) {
+        return Cow::Borrowed(key);
+    }
+
+    let mut out = String::with_capacity(key.len());
+    let mut start = 0;
+    let mut idx = 0;
+
+    // Escapes are ASCII, so the positions are always on char boundaries.
+    while idx < bytes.len() {
+        if is_key_escape(bytes, idx) {
+            out.push_str(&key[start..idx]);
+            start = idx + 1;
+            idx += 2;
+        } else {
+            idx += 1;
+        }
+    }
+
+    out.push_str(&key[start..]);
+
+    Cow::Owned(out)
+}
+
+// Escapes each equals sign of a key, and each backslash that would otherwise
+// be read as the start of an escape, so that it is parsed back unchanged.
+fn escape_key(key: &str) -> Cow<'_, str> {
+    let bytes = key.as_bytes();
+    let needs_escape = |idx: usize| match bytes[idx] {
+        b'=' => true,
+        b'\\' => matches!(
+            bytes.get(idx + 1),
+            None | Some(&b'\\') | Some(&b'='),
+        ),
+        _ => false,
+    };
+
+    if !(0..bytes.len()).any(&needs_escape) {
+        return Cow::Borrowed(key);
+    }
+
+    let mut escaped = String::with_capacity(key.len() + 1);
+
+    for (idx, c) in key.char_indices() {
+        if c.is_ascii() && needs_escape(idx) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    Cow::Owned(escaped)
+}
+
+// Finds the position of the first double quote that is not escaped.
+pub(crate) fn find_closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (idx, b) in s.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'"' => return Some(idx),
+            _ => {},
+        }
+    }
+
+    None
+}
+
+// Expands the escape sequences of a double-quoted value, only allocating if
+// there are any.
+pub(crate) fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(c @ '"') | Some(c @ '\\') => out.push(c),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            },
+            None => out.push('\\'),
+        }
+    }
+
+    Cow::Owned(out)
+}
+
+/// Parses a K-V pair of an environment variable OsString name and value into
+/// their String equivalents.
+pub fn parse_kv(pair: (OsString, OsString)) -> Option<(String, String)> {
+    if let (Ok(k), Ok(v)) = (pair.0.into_string(), pair.1.into_string()) {
+        Some((k, v))
+    } else {
+        None
+    }
+}
+
+/// Returns whether a variable with the given key and value can be set in the
+/// environment.
+///
+/// A key is invalid if it is empty, or contains an equals sign (`=`) or a NUL
+/// byte (`\0`). A value is invalid if it contains a NUL byte. Setting an
+/// invalid variable via `std::env::set_var` panics, whereas the functions in
+/// this library skip them.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// assert!(utils::is_valid_var("KEY", "value"));
+/// assert!(!utils::is_valid_var("KEY", "a\0b"));
+/// assert!(!utils::is_valid_var("", "value"));
+/// ```
+pub fn is_valid_var(key: &str, value: &str) -> bool {
+    !key.is_empty() && !key.contains('=') && !key.contains('\0')
+        && !value.contains('\0')
+}
+
+/// Converts a key into a conventional environment variable name.
+///
+/// The rules are, in order:
+///
+/// - ASCII letters are uppercased;
+/// - every character other than an ASCII letter, digit, or underscore, such as
+///   `-`, `.`, or a space, is replaced with an underscore, one per character;
+/// - if the result begins with a digit, it is prefixed with an underscore.
+///
+/// The result of a non-empty key is always a valid name according to POSIX,
+/// and so is also valid according to [`is_valid_var`].
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// assert_eq!(utils::sanitize_key("my-key"), "MY_KEY");
+/// assert_eq!(utils::sanitize_key("db.host name"), "DB_HOST_NAME");
+/// assert_eq!(utils::sanitize_key("2fa"), "_2FA");
+/// ```
+///
+/// [`is_valid_var`]: fn.is_valid_var.html
+pub fn sanitize_key(key: &str) -> String {
+    let mut sanitized = String::with_capacity(key.len() + 1);
+
+    if key.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.push('_');
+    }
+
+    sanitized.extend(key.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c.to_ascii_uppercase(),
+        _ => '_',
+    }));
+
+    sanitized
+}
+
+/// Overlays the values of the current environment on top of a map, so that
+/// the environment takes precedence.
+///
+/// Only the keys already in the map are overlaid: a key that also exists in
+/// the environment has its value replaced by the environment's, while other
+/// keys keep their value. This resolves a parsed file the same way as loading
+/// it without overwriting, without modifying the environment.
+///
+/// Variables whose value is not valid unicode are not overlaid.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::env;
+///
+/// env::set_var("OVERLAY_EXAMPLE_PORT", "3000");
+///
+/// let mut map = utils::parse_to_map(
+///     "OVERLAY_EXAMPLE_HOST=localhost\nOVERLAY_EXAMPLE_PORT=8080",
+/// );
+/// utils::overlay_env(&mut map);
+///
+/// assert_eq!(map["OVERLAY_EXAMPLE_HOST"], "localhost");
+/// assert_eq!(map["OVERLAY_EXAMPLE_PORT"], "3000");
+/// ```
+pub fn overlay_env(base: &mut HashMap<String, String>) {
+    for (key, value) in base.iter_mut() {
+        if let Ok(env_value) = env::var(key) {
+            *value = env_value;
+        }
+    }
+}
+
+/// Parses a buffer into a map of keys to values.
+///
+/// If a key is defined multiple times, the last definition wins.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let map = utils::parse_to_map("FOO=bar\nFOO=baz");
+///
+/// assert_eq!(map.get("FOO").map(|v| v.as_str()), Some("baz"));
+/// ```
+pub fn parse_to_map(buf: &str) -> HashMap<String, String> {
+    parse_lines(buf)
+        .into_iter()
+        .map(|(k, v)| (k.into_owned(), v.to_owned()))
+        .collect()
+}
+
+/// Parses a buffer into a map of keys to values, inferring the type of each
+/// value.
+///
+/// This is like [`parse_to_map`], but the values are inferred via
+/// [`Value::infer`].
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils::{self, Value};
+///
+/// let map = utils::parse_to_typed_map("PORT=8080\nDEBUG=true");
+///
+/// assert_eq!(map.get("PORT"), Some(&Value::Int(8080)));
+/// assert_eq!(map.get("DEBUG"), Some(&Value::Bool(true)));
+/// ```
+///
+/// [`Value::infer`]: enum.Value.html#method.infer
+/// [`parse_to_map`]: fn.parse_to_map.html
+pub fn parse_to_typed_map(buf: &str) -> HashMap<String, Value> {
+    parse_lines(buf)
+        .into_iter()
+        .map(|(k, v)| (k.into_owned(), Value::infer(v)))
+        .collect()
+}
+
+/// Applies the given slice of directives to the environment.
+///
+/// [`Directive::Set`]s are loaded like [`set_variables`], while
+/// [`Directive::Unset`]s remove the key from the environment. If `overwrite` is
+/// `false`, then existing variables are neither overwritten nor removed.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::{utils, Parser};
+/// use std::env;
+///
+/// env::set_var("DIRECTIVES_EXAMPLE", "1");
+///
+/// let parser = Parser::new().unset_directives(true);
+/// let directives = parser.parse_lines("DIRECTIVES_EXAMPLE").unwrap();
+/// utils::set_directives(&directives, true);
+///
+/// assert!(env::var("DIRECTIVES_EXAMPLE").is_err());
+/// ```
+///
+/// [`Directive::Set`]: ../enum.Directive.html#variant.Set
+/// [`Directive::Unset`]: ../enum.Directive.html#variant.Unset
+/// [`set_variables`]: fn.set_variables.html
+#[inline]
+pub fn set_directives(directives: &[Directive], overwrite: bool) {
+    set_directives_in(&mut LockedEnv::new(), directives, overwrite);
+}
+
+/// Applies the given slice of directives to the given sink.
+///
+/// This is like [`set_directives`], but applies to any [`EnvSink`] rather than
+/// the environment.
+///
+/// [`EnvSink`]: ../trait.EnvSink.html
+/// [`set_directives`]: fn.set_directives.html
+pub fn set_directives_in<S: EnvSink + ?Sized>(
+    sink: &mut S,
+    directives: &[Directive],
+    overwrite: bool,
+) {
+    for directive in directives {
+        if !overwrite && sink.get(directive.key()).is_some() {
+            continue;
+        }
+
+        match *directive {
+            Directive::Set(ref key, ref value) => sink.set(key, value),
+            Directive::Unset(ref key) => sink.remove(key),
+        }
+    }
+}
+
+/// Loads the given slice of parsed lines into the environment.
+///
+/// Additionally you can pass whether to overwrite existing variables with the
+/// same name.
+///
+/// Lines that are not valid to set in the environment, such as those containing
+/// a NUL byte, are skipped. Use [`set_variables_checked`] to find out which
+/// were skipped.
+///
+/// # Examples
+///
+/// Parse a buffer into parsed lines, and then load the lines into the
+/// environment:
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let content = "FOO=bar\nBAR=baz";
+///
+/// let lines = utils::parse_lines(content);
+///
+/// utils::set_variables(&lines, true);
+/// ```
+///
+/// [`set_variables_checked`]: fn.set_variables_checked.html
+#[inline]
+pub fn set_variables<K: AsRef<str>>(lines: &[(K, &str)], overwrite: bool) {
+    set_variables_in(&mut LockedEnv::new(), lines, overwrite);
+}
+
+/// Loads the given slice of parsed lines into the environment, returning the
+/// keys whose value was changed.
+///
+/// This is like [`set_variables`], but a key is only reported as changed if it
+/// did not previously exist or its new value differs from its current one.
+/// Setting a key to the value it already has is not a change, and so is not
+/// reported. Each key is reported at most once.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// utils::set_variables(&[("CHANGED_EXAMPLE", "1")], true);
+///
+/// let lines = [("CHANGED_EXAMPLE", "1"), ("CHANGED_EXAMPLE_NEW", "2")];
+/// let changed = utils::set_variables_changed(&lines, true);
+///
+/// assert_eq!(changed, vec!["CHANGED_EXAMPLE_NEW"]);
+/// ```
+///
+/// [`set_variables`]: fn.set_variables.html
+pub fn set_variables_changed<'a, K: AsRef<str>>(
+    lines: &'a [(K, &str)],
+    overwrite: bool,
+) -> Vec<&'a str> {
+    let locked = LockedEnv::new();
+    let mut changed = Vec::new();
+
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        let current = env::var(key).ok();
+
+        if current.is_some() && !overwrite {
+            continue;
+        }
+
+        if current.as_deref() == Some(value) || !locked.set_var(key, value) {
+            continue;
+        }
+
+        if !changed.contains(&key) {
+            changed.push(key);
+        }
+    }
+
+    changed
+}
+
+/// Loads the given slice of parsed lines into the environment, returning the
+/// keys of the lines that were skipped due to being invalid.
+///
+/// This is like [`set_variables`], but reports the lines that
+/// [`is_valid_var`] rejects rather than silently skipping them.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+///
+/// let lines = [("CHECKED_OK", "1"), ("CHECKED_NUL", "a\0b")];
+///
+/// assert_eq!(utils::set_variables_checked(&lines, true), vec!["CHECKED_NUL"]);
+/// ```
+///
+/// [`is_valid_var`]: fn.is_valid_var.html
+/// [`set_variables`]: fn.set_variables.html
+pub fn set_variables_checked<'a, K: AsRef<str>>(
+    lines: &'a [(K, &str)],
+    overwrite: bool,
+) -> Vec<&'a str> {
+    let locked = LockedEnv::new();
+    let mut invalid = Vec::new();
+
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        if !is_valid_var(key, value) {
+            invalid.push(key);
+
+            continue;
+        }
+
+        if !overwrite && env::var_os(key).is_some() {
+            continue;
+        }
+
+        locked.set_var(key, value);
+    }
+
+    invalid
+}
+
+/// Loads the given slice of parsed lines into the environment, only setting
+/// keys that are currently unset or set to an empty string.
+///
+/// This "fills the blanks" of a base environment whose placeholders are left
+/// empty, while keeping any non-empty existing value. If a key is defined on
+/// multiple lines, only its first non-empty value is set.
+///
+/// # Examples
+///
+/// ```rust
+/// use kankyo::utils;
+/// use std::env;
+///
+/// env::set_var("FILL_EXAMPLE_EMPTY", "");
+/// env::set_var("FILL_EXAMPLE_SET", "kept");
+///
+/// utils::set_variables_fill_empty(&[
+///     ("FILL_EXAMPLE_EMPTY", "filled"),
+///     ("FILL_EXAMPLE_SET", "ignored"),
+/// ]);
+///
+/// assert_eq!(env::var("FILL_EXAMPLE_EMPTY").unwrap(), "filled");
+/// assert_eq!(env::var("FILL_EXAMPLE_SET").unwrap(), "kept");
 /// ```
-pub fn parse_line(line: &str) -> Option<ParsedLine> {
-    let (equals, comment) = (line.find('='), line.find('#'));
-
-    if let (Some(comment), Some(equals)) = (comment, equals) {
-        if comment < equals {
-            return None;
-        }
-    }
+pub fn set_variables_fill_empty<K: AsRef<str>>(lines: &[(K, &str)]) {
+    let locked = LockedEnv::new();
 
-    equals.map(|pos_equals| {
-        // We have the position of the equals sign, so we know for sure what the
-        // key is.
-        let key = &line[..pos_equals];
-        // We skip the equals sign, so add one to the position..
-        let post_idx = pos_equals + 1;
-        // Slice the input line and check if there's a hash.
-        //
-        // The hash is where a comment, if there is one, begins.
-        //
-        // If there is a hash, then slice from `post_idx` until its position. If
-        // there is not, slice from `post_idx` until the end.
-        let value = comment
-            .map(|pos_pound| &line[post_idx..pos_pound])
-            .unwrap_or_else(|| &line[post_idx..]);
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
 
-        (key.trim(), value.trim())
-    })
-}
-
-/// Parses a K-V pair of an environment variable OsString name and value into
-/// their String equivalents.
-pub fn parse_kv(pair: (OsString, OsString)) -> Option<(String, String)> {
-    if let (Ok(k), Ok(v)) = (pair.0.into_string(), pair.1.into_string()) {
-        Some((k, v))
-    } else {
-        None
+        if env::var_os(key).map_or(true, |current| current.is_empty()) {
+            locked.set_var(key, value);
+        }
     }
 }
 
-/// Loads the given slice of parsed lines into the environment.
+/// Loads the given slice of parsed lines into the given sink.
 ///
-/// Additionally you can pass whether to overwrite existing variables with the
-/// same name.
+/// This is like [`set_variables`], but applies to any [`EnvSink`] rather than
+/// the environment, such as an in-memory [`HashMapSink`].
 ///
 /// # Examples
 ///
-/// Parse a buffer into parsed lines, and then load the lines into the
-/// environment:
-///
 /// ```rust
-/// use kankyo::utils;
+/// use kankyo::{utils, HashMapSink};
 ///
-/// let content = "FOO=bar\nBAR=baz";
+/// let lines = utils::parse_lines("FOO=bar\nBAR=baz");
+/// let mut sink = HashMapSink::new();
 ///
-/// let lines = utils::parse_lines(content);
+/// utils::set_variables_in(&mut sink, &lines, true);
 ///
-/// utils::set_variables(&lines, true);
+/// assert_eq!(sink.len(), 2);
 /// ```
-pub fn set_variables(lines: &[ParsedLine], overwrite: bool) {
-    for line in lines {
-        if !overwrite && env::var(line.0).is_ok() {
+///
+/// [`EnvSink`]: ../trait.EnvSink.html
+/// [`HashMapSink`]: ../type.HashMapSink.html
+/// [`set_variables`]: fn.set_variables.html
+pub fn set_variables_in<S: EnvSink + ?Sized, K: AsRef<str>>(
+    sink: &mut S,
+    lines: &[(K, &str)],
+    overwrite: bool,
+) {
+    for &(ref key, value) in lines {
+        let key = key.as_ref();
+
+        if !overwrite && sink.get(key).is_some() {
             continue;
         }
 
-        env::set_var(line.0, line.1);
+        sink.set(key, value);
     }
 }
 
@@ -181,8 +2177,10 @@ pub fn set_variables(lines: &[ParsedLine], overwrite: bool) {
 /// utils::unload(&["FOO", "BAR"]);
 /// ```
 pub fn unload(keys: &[&str]) {
+    let locked = LockedEnv::new();
+
     for key in keys {
-        env::remove_var(key);
+        locked.remove_var(key);
     }
 }
 
@@ -215,28 +2213,356 @@ pub fn unload(keys: &[&str]) {
 /// [`only_keys`]: fn.only_keys.html
 /// [`parse_lines`]: fn.parse_lines.html
 /// [`unload`]: fn.unload.html
-pub fn unload_from_parsed_lines(lines: &[ParsedLine]) {
-    for &(key, _) in lines {
-        env::remove_var(key);
+pub fn unload_from_parsed_lines<K: AsRef<str>>(lines: &[(K, &str)]) {
+    let locked = LockedEnv::new();
+
+    for (key, _) in lines {
+        let key = key.as_ref();
+
+        locked.remove_var(key);
     }
 }
 
 #[cfg(test)]
 mod test {
+    use parser::Parser;
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use sink::HashMapSink;
+    use std::env;
     use std::ffi::OsString;
-    use utils;
+    use std::io::{Cursor, ErrorKind};
+    use std::rc::Rc;
+    use utils::{
+        self,
+        Change,
+        Line,
+        LineEndings,
+        SourceLine,
+        Value,
+        WarningKind,
+    };
 
     #[test]
     fn pairings() {
-        assert_eq!(utils::parse_line("key=value"), Some(("key", "value")));
-        assert_eq!(utils::parse_line("key =value"), Some(("key", "value")));
-        assert_eq!(utils::parse_line(" key ="), Some(("key", "")));
+        assert_eq!(
+            utils::parse_line("key=value"),
+            Some(("key".into(), "value")),
+        );
+        assert_eq!(
+            utils::parse_line("key =value"),
+            Some(("key".into(), "value")),
+        );
+        assert_eq!(utils::parse_line(" key ="), Some(("key".into(), "")));
     }
 
     #[test]
     fn comments() {
         assert_eq!(utils::parse_line("key#=value"), None);
-        assert_eq!(utils::parse_line("key=#abc"), Some(("key", "")));
+        assert_eq!(utils::parse_line("key=#abc"), Some(("key".into(), "")));
+    }
+
+    #[test]
+    fn append_path() {
+        #[cfg(windows)]
+        let sep = ";";
+        #[cfg(not(windows))]
+        let sep = ":";
+
+        utils::append_path("APPEND_PATH_ABSENT", "/a");
+        assert_eq!(env::var("APPEND_PATH_ABSENT").unwrap(), "/a");
+
+        env::set_var("APPEND_PATH_EMPTY", "");
+        utils::append_path("APPEND_PATH_EMPTY", "/a");
+        assert_eq!(env::var("APPEND_PATH_EMPTY").unwrap(), "/a");
+
+        env::set_var("APPEND_PATH_SET", "/a");
+        utils::append_path("APPEND_PATH_SET", "/b");
+        utils::append_path("APPEND_PATH_SET", "/c");
+        assert_eq!(
+            env::var("APPEND_PATH_SET").unwrap(),
+            format!("/a{}/b{}/c", sep, sep),
+        );
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+            let current = OsStr::from_bytes(b"/\xff");
+            env::set_var("APPEND_PATH_NON_UNICODE", current);
+            utils::append_path("APPEND_PATH_NON_UNICODE", "/b");
+            assert_eq!(
+                env::var_os("APPEND_PATH_NON_UNICODE").unwrap().into_vec(),
+                b"/\xff:/b",
+            );
+        }
+
+        utils::unload(&[
+            "APPEND_PATH_ABSENT",
+            "APPEND_PATH_EMPTY",
+            "APPEND_PATH_NON_UNICODE",
+            "APPEND_PATH_SET",
+        ]);
+    }
+
+    #[test]
+    fn check_references() {
+        let mut against = HashMap::new();
+        against.insert("REF_ENV".to_owned(), "1".to_owned());
+
+        let mut cursor = Cursor::new(
+            "A=${B}-$REF_ENV\nB=${DANGLING}\nC=$DANGLING$$ $1 ${}",
+        );
+        let dangling = utils::check_references(&mut cursor, &against).unwrap();
+
+        assert_eq!(dangling, vec!["DANGLING"]);
+    }
+
+    #[test]
+    fn check_uppercase_keys() {
+        let lines = utils::parse_lines(
+            "PORT=80\nPort=80\nhost=localhost\nDB_2=x\n\
+             CAF\u{c9}=1\nCAF\u{e9}=1",
+        );
+
+        assert_eq!(
+            utils::check_uppercase_keys(&lines),
+            &["Port", "host", "CAF\u{e9}"],
+        );
+        assert!(utils::check_uppercase_keys(&lines[3..5]).is_empty());
+    }
+
+    #[test]
+    fn check_limits() {
+        let value = "a".repeat(utils::DEFAULT_MAX_VALUE_LEN + 1);
+        let line = format!("LONG_VALUE={}", value);
+        let lines = [("OK".into(), "value"), utils::parse_line(&line).unwrap()];
+
+        assert_eq!(
+            utils::check_limits(
+                &lines,
+                utils::DEFAULT_MAX_KEY_LEN,
+                utils::DEFAULT_MAX_VALUE_LEN,
+            ),
+            &["LONG_VALUE"],
+        );
+        assert_eq!(utils::check_limits(&lines, 2, 100), &["LONG_VALUE"]);
+        assert!(utils::check_limits(&lines, 10, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn format_line() {
+        assert_eq!(utils::format_line("KEY", "value"), "KEY=value");
+        assert_eq!(utils::format_line("KEY", ""), "KEY=");
+        assert_eq!(utils::format_line(r"KEY\", "value"), r"KEY\\=value");
+
+        assert_eq!(utils::format_line("A=B", "C"), r"A\=B=C");
+        assert_eq!(utils::format_line(r"A\B", "C"), r"A\B=C");
+        assert_eq!(utils::format_line(r"A\=B", "C"), r"A\\\=B=C");
+
+        let lines = [
+            "A=B",
+            "A = B=C # comment",
+            "A=",
+            r"A\ =B",
+            r"A\=B=C",
+            r"A\\=B",
+            r"A\\\=B=C",
+        ];
+
+        for line in &lines {
+            let (key, value) = utils::parse_line(line).unwrap();
+            let formatted = utils::format_line(&key, value);
+
+            assert_eq!(utils::parse_line(&formatted), Some((key, value)));
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let baseline = utils::parse_lines("A=1\nB=2\nA=3\nC=4\nD=5");
+        let current = utils::parse_lines("E=6\nA=3\nB=1\nB=7\nD=5\nE=8");
+
+        assert_eq!(utils::diff(&baseline, &current), vec![
+            Change::Modified {
+                key: "B".to_owned(),
+                old: "2".to_owned(),
+                new: "7".to_owned(),
+            },
+            Change::Removed {
+                key: "C".to_owned(),
+                value: "4".to_owned(),
+            },
+            Change::Added {
+                key: "E".to_owned(),
+                value: "8".to_owned(),
+            },
+        ]);
+        assert!(utils::diff(&baseline, &baseline).is_empty());
+    }
+
+    #[test]
+    fn document_round_trip() {
+        let buf = "# header\n\
+                   #\n\
+                   HOST=localhost\n\
+                   \n\
+                   PORT=8080 # the port\n\
+                   EMPTY=\n\
+                   COMMENTED=# only a comment\n\
+                   URL=http://host/?a=b #frag\n\
+                   not a pair\n\
+                   KEY#=value\n";
+        let lines = utils::parse_document(buf);
+
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[1], Line::Comment("".to_owned()));
+        assert_eq!(lines[6], Line::KeyValue {
+            key: "COMMENTED".to_owned(),
+            value: "".to_owned(),
+            inline_comment: Some(" only a comment".to_owned()),
+        });
+        assert_eq!(lines[7], Line::KeyValue {
+            key: "URL".to_owned(),
+            value: "http://host/?a=b".to_owned(),
+            inline_comment: Some("frag".to_owned()),
+        });
+        assert_eq!(lines[8], Line::Other("not a pair".to_owned()));
+        assert_eq!(utils::format_document(&lines), buf);
+    }
+
+    #[test]
+    fn document_normalizes() {
+        let lines = utils::parse_document("  KEY = v  #  c \nK=v#c\n\t# x\n ");
+
+        assert_eq!(
+            utils::format_document(&lines),
+            "KEY=v #  c \nK=v #c\n# x\n\n",
+        );
+        let formatted = utils::format_document(&lines);
+        assert_eq!(utils::parse_document(&formatted), lines);
+    }
+
+    #[test]
+    fn detect_format() {
+        let format = utils::detect_format("A=1\nB=2\n");
+        assert!(!format.bom);
+        assert_eq!(format.line_endings, LineEndings::Lf);
+
+        let format = utils::detect_format("\u{feff}A=1\r\nB=2\r\n");
+        assert!(format.bom);
+        assert_eq!(format.line_endings, LineEndings::Crlf);
+
+        let format = utils::detect_format("A=1\r\nB=2\nC=3");
+        assert_eq!(format.line_endings, LineEndings::Mixed);
+
+        let format = utils::detect_format("A=1\r");
+        assert_eq!(format.line_endings, LineEndings::None);
+        assert_eq!(utils::detect_format("").line_endings, LineEndings::None);
+    }
+
+    #[test]
+    fn normalize() {
+        let buf = "\u{feff}# header \r\n\r\n  A =  1\r\nB= \"x y\" # note\n\
+                   C=#empty\n#c\nnot a pair  ";
+        let normalized = utils::normalize(buf);
+
+        assert_eq!(
+            normalized,
+            "# header\n\nA=1\nB=\"x y\" # note\nC=#empty\n#c\nnot a pair  \n",
+        );
+        assert_eq!(utils::normalize(&normalized), normalized);
+        assert_eq!(utils::normalize(""), "");
+    }
+
+    #[test]
+    fn patch_env() {
+        let buf = "# header\r\n\r\n  HOST = localhost  # local\r\n\
+                   PORT=80\r\nnot a pair\r\nNAME=#unset\r\nPORT=81";
+
+        let patched = utils::patch_env(buf, &[("PORT", "8080")]).unwrap();
+        assert_eq!(
+            patched,
+            "# header\r\n\r\n  HOST = localhost  # local\r\n\
+             PORT=8080\r\nnot a pair\r\nNAME=#unset\r\nPORT=8080",
+        );
+
+        let patched = utils::patch_env(buf, &[
+            ("NAME", "x"),
+            ("NEW", "1"),
+            ("NEW", "2"),
+        ]).unwrap();
+        assert!(patched.contains("\r\nNAME=x#unset\r\n"));
+        assert!(patched.ends_with("PORT=81\r\nNEW=2\r\n"));
+        assert_eq!(utils::parse_to_map(&patched)["NAME"], "x");
+
+        assert_eq!(utils::patch_env(buf, &[]).unwrap(), buf);
+        assert_eq!(utils::patch_env("", &[("A", "1")]).unwrap(), "A=1\n");
+        assert_eq!(
+            utils::patch_env("", &[("A=B", "1")]).unwrap(),
+            "A\\=B=1\n",
+        );
+
+        let invalid = [
+            ("PORT", "80\nINJECTED=1"),
+            ("PORT", "80\r"),
+            ("PORT", "80 # truncated"),
+            ("PORT", " 80"),
+            ("PO#RT", "80"),
+            ("PORT\n", "80"),
+        ];
+
+        for &change in &invalid {
+            let err = utils::patch_env(buf, &[change]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn expand_tilde() {
+        let home = match super::home_dir() {
+            Some(home) => home,
+            None => return,
+        };
+
+        let trimmed = home.trim_end_matches('/');
+        let root = if trimmed.is_empty() { "/" } else { trimmed };
+
+        assert_eq!(utils::expand_tilde("~/x"), format!("{}/x", trimmed));
+        assert_eq!(utils::expand_tilde("~"), root);
+        assert_eq!(utils::expand_tilde("a~b"), "a~b");
+        assert_eq!(utils::expand_tilde("~user/x"), "~user/x");
+        assert_eq!(utils::expand_tilde("/x/~/y"), "/x/~/y");
+
+        assert_eq!(super::join_home("/", ""), "/");
+        assert_eq!(super::join_home("/", "/x"), "/x");
+        assert_eq!(super::join_home("/home/user/", ""), "/home/user");
+        assert_eq!(super::join_home("/home/user/", "/x"), "/home/user/x");
+    }
+
+    #[test]
+    fn find_duplicates() {
+        let lines = utils::parse_lines(
+            "A=1\nB=x\nC=2\nA=1 # same\nB=y\nA = 1\nD=3",
+        );
+        let duplicates = utils::find_duplicates(&lines);
+
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].key, "A");
+        assert_eq!(duplicates[0].values, vec!["1", "1", "1"]);
+        assert!(!duplicates[0].is_conflicting());
+        assert_eq!(duplicates[1].key, "B");
+        assert!(duplicates[1].is_conflicting());
+        assert!(utils::find_duplicates(&[("A", "1")]).is_empty());
+    }
+
+    #[test]
+    fn keys_in() {
+        let keys = utils::keys_in("A=1\n# B=2\nC=3\nA=4\n\nC=5\nD=6");
+
+        assert_eq!(keys, vec!["A", "C", "D"]);
+        assert!(utils::keys_in("").is_empty());
     }
 
     #[test]
@@ -250,18 +2576,546 @@ mod test {
 
     #[test]
     fn parse_line() {
-        assert_eq!(utils::parse_line("KEY=value"), Some(("KEY", "value")));
-        assert_eq!(utils::parse_line("KEY=value#test"), Some(("KEY", "value")));
+        assert_eq!(
+            utils::parse_line("KEY=value"),
+            Some(("KEY".into(), "value")),
+        );
+        assert_eq!(
+            utils::parse_line("KEY=value#test"),
+            Some(("KEY".into(), "value")),
+        );
         assert!(utils::parse_line("KEY").is_none());
-        assert_eq!(utils::parse_line("KEY="), Some(("KEY", "")));
+        assert_eq!(utils::parse_line("KEY="), Some(("KEY".into(), "")));
         assert!(utils::parse_line("KEY#B=C#").is_none());
     }
 
+    #[test]
+    fn multiple_equals() {
+        assert_eq!(utils::parse_line("A==B"), Some(("A".into(), "=B")));
+        assert_eq!(utils::parse_line("A=B=C=D"), Some(("A".into(), "B=C=D")));
+        assert_eq!(utils::parse_line("A = B = C"), Some(("A".into(), "B = C")));
+        assert_eq!(utils::parse_line("A="), Some(("A".into(), "")));
+        assert_eq!(utils::parse_line("A=="), Some(("A".into(), "=")));
+    }
+
+    #[test]
+    fn escaped_equals_in_key() {
+        assert_eq!(utils::parse_line(r"a\=b=c"), Some(("a=b".into(), "c")));
+        assert_eq!(utils::parse_line("a=b=c"), Some(("a".into(), "b=c")));
+        assert_eq!(utils::parse_line(r"a\\=b"), Some((r"a\".into(), "b")));
+        assert_eq!(utils::parse_line(r"a\\\=b=c"), Some((r"a\=b".into(), "c")));
+        assert_eq!(utils::parse_line(r"a\b=c"), Some((r"a\b".into(), "c")));
+
+        assert_eq!(utils::parse_line(r"a=b\=c"), Some(("a".into(), r"b\=c")));
+        assert_eq!(utils::parse_line(r"a\=b"), None);
+        assert_eq!(
+            utils::parse_line_with_raw(r"a\=b= c"),
+            Some(("a=b".into(), "c", " c")),
+        );
+        assert_eq!(utils::parse_line_cow(r"a\=b='c'").unwrap().0, "a=b");
+    }
+
+    #[test]
+    fn comment_before_first_equals() {
+        assert!(utils::parse_line("A#=B=C").is_none());
+        assert_eq!(utils::parse_line("A=B#=C"), Some(("A".into(), "B")));
+        assert_eq!(utils::parse_line("A=B=C#D=E"), Some(("A".into(), "B=C")));
+        assert!(utils::parse_line("#A=B=C").is_none());
+    }
+
+    #[test]
+    fn indentation() {
+        assert_eq!(
+            utils::parse_line("    KEY=value"),
+            Some(("KEY".into(), "value")),
+        );
+        assert_eq!(
+            utils::parse_line("\tKEY=value"),
+            Some(("KEY".into(), "value")),
+        );
+        assert_eq!(
+            utils::parse_line("\t KEY\t=\tvalue\t"),
+            Some(("KEY".into(), "value")),
+        );
+        assert!(utils::parse_line("\t# KEY=value").is_none());
+        assert!(utils::parse_line("    # KEY=value").is_none());
+    }
+
+    #[test]
+    fn parse_line_matches_find() {
+        let lines = [
+            "KEY=value",
+            "KEY=value#comment",
+            " KÉY = välue # cömment ",
+            "ключ=значение",
+            "#KEY=value",
+            "KEY#=value",
+            "KEY=a=b#c#d",
+            "KEY=日本#語",
+            "日本語",
+            "=",
+            "#",
+            "",
+            "é=#",
+        ];
+
+        for line in &lines {
+            let parsed = utils::parse_line(line);
+            let parsed = parsed.as_ref().map(|&(ref k, v)| (&**k, v));
+
+            assert_eq!(parsed, ::__private::parse_line_find(line));
+        }
+    }
+
+    #[test]
+    fn parse_line_with_raw() {
+        assert_eq!(
+            utils::parse_line_with_raw("KEY= hello # note"),
+            Some(("KEY".into(), "hello", " hello # note")),
+        );
+        assert_eq!(
+            utils::parse_line_with_raw(" KEY =a=b"),
+            Some(("KEY".into(), "a=b", "a=b")),
+        );
+        assert_eq!(
+            utils::parse_line_with_raw("KEY="),
+            Some(("KEY".into(), "", "")),
+        );
+        assert!(utils::parse_line_with_raw("# KEY=value").is_none());
+        assert!(utils::parse_line_with_raw("KEY").is_none());
+    }
+
+    #[test]
+    fn parse_line_cow_borrowed() {
+        let borrowed = [
+            ("A=plain # comment", "plain"),
+            ("A='single # \\n'", "single # \\n"),
+            ("A=\"double # x\" # comment", "double # x"),
+            ("A=\"unterminated # comment ", "unterminated # comment"),
+        ];
+
+        for &(line, expected) in &borrowed {
+            match utils::parse_line_cow(line) {
+                Some((ref key, Cow::Borrowed(value))) if key == "A" => {
+                    assert_eq!(value, expected);
+                },
+                other => panic!("{:?} parsed as {:?}", line, other),
+            }
+        }
+
+        assert!(utils::parse_line_cow("# A=\"x\"").is_none());
+        assert!(utils::parse_line_cow("A").is_none());
+    }
+
+    #[test]
+    fn parse_line_cow_owned() {
+        let owned = [
+            (r#"A="a\nb""#, "a\nb"),
+            (r#"A="tab\there""#, "tab\there"),
+            (r#"A="say \"hi\"" # comment"#, "say \"hi\""),
+            (r#"A="back\\slash\q""#, "back\\slash\\q"),
+            (r#"A="é\\""#, "é\\"),
+        ];
+
+        for &(line, expected) in &owned {
+            match utils::parse_line_cow(line) {
+                Some((ref key, Cow::Owned(ref value))) if key == "A" => {
+                    assert_eq!(value, expected);
+                },
+                other => panic!("{:?} parsed as {:?}", line, other),
+            }
+        }
+    }
+
+    #[test]
+    fn multibyte() {
+        assert_eq!(
+            utils::parse_line("GRÜẞE=héllo#cömment"),
+            Some(("GRÜẞE".into(), "héllo")),
+        );
+        assert_eq!(utils::parse_line("キー=値"), Some(("キー".into(), "値")));
+        assert_eq!(utils::parse_line("é=ü=ö"), Some(("é".into(), "ü=ö")));
+        assert!(utils::parse_line("ü#é=ö").is_none());
+
+        // Combining characters on either side of the separators:
+        assert_eq!(
+            utils::parse_line("e\u{301}=a\u{308}#o\u{308}"),
+            Some(("e\u{301}".into(), "a\u{308}")),
+        );
+        assert_eq!(
+            utils::parse_line("\u{301}=\u{301}"),
+            Some(("\u{301}".into(), "\u{301}")),
+        );
+    }
+
     #[test]
     fn parse_lines() {
         assert_eq!(utils::parse_lines("A=B\nC=D\nE=F#").len(), 3);
     }
 
+    #[test]
+    fn parse_lines_limited() {
+        let buf = "A=1\n# B=2\n\nC=3\nD=4\nE=5";
+
+        assert_eq!(
+            utils::parse_lines_limited(buf, 3),
+            vec![("A".into(), "1"), ("C".into(), "3"), ("D".into(), "4")],
+        );
+        assert!(utils::parse_lines_limited(buf, 0).is_empty());
+        assert_eq!(utils::parse_lines_limited(buf, 10).len(), 4);
+    }
+
+    #[test]
+    fn parse_iter_numbered() {
+        let buf = "# header\nA=1\n\nnot a pair\n  B = 2\r\n#C=3\nD=4";
+        let numbered = utils::parse_iter_numbered(buf).collect::<Vec<_>>();
+
+        assert_eq!(
+            numbered,
+            vec![
+                (2, ("A".into(), "1")),
+                (5, ("B".into(), "2")),
+                (7, ("D".into(), "4")),
+            ],
+        );
+        assert_eq!(utils::parse_iter_numbered("").next(), None);
+    }
+
+    #[test]
+    fn parse_lines_interned() {
+        let buf = "A=1\nB=2\n# A=x\nA=3\nB=4 # c\nC=5";
+        let interned = utils::parse_lines_interned(buf);
+        let owned = utils::parse_lines_owned(buf);
+
+        assert_eq!(interned.len(), owned.len());
+
+        for (interned, owned) in interned.iter().zip(&owned) {
+            assert_eq!(&*interned.0, owned.0);
+            assert_eq!(interned.1, owned.1);
+        }
+
+        assert!(Rc::ptr_eq(&interned[0].0, &interned[2].0));
+        assert!(Rc::ptr_eq(&interned[1].0, &interned[3].0));
+        assert!(!Rc::ptr_eq(&interned[0].0, &interned[1].0));
+    }
+
+    #[test]
+    fn parse_section() {
+        let buf = "DEFAULT=1\n\n[ env ]\nA=1 # c\n[other]\nB=2\n\
+                   [env]\nC=3\n[]\nD=4\n[[nested]]\nE=5";
+
+        assert_eq!(
+            utils::parse_section(buf, "env"),
+            &[("A".into(), "1"), ("C".into(), "3")],
+        );
+        assert_eq!(utils::parse_section(buf, "other"), &[("B".into(), "2")]);
+        assert_eq!(
+            utils::parse_section(buf, ""),
+            &[("DEFAULT".into(), "1"), ("D".into(), "4")],
+        );
+        assert_eq!(utils::parse_section(buf, "[nested]"), &[("E".into(), "5")]);
+        assert!(utils::parse_section(buf, "ENV").is_empty());
+    }
+
+    #[test]
+    fn parse_with_warnings() {
+        let buf = "# header\nA=1\nB=value  \nC=ab#cd\nA = 2 # again\n\
+                   lower=x\nD= # empty\nE=\t";
+        let (lines, warnings) = utils::parse_with_warnings(buf);
+
+        assert_eq!(lines, utils::parse_lines(buf));
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.line, &*w.key, w.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, "B", WarningKind::TrailingWhitespace),
+                (4, "C", WarningKind::CommentTruncation),
+                (5, "A", WarningKind::DuplicateKey),
+                (6, "lower", WarningKind::LowercaseKey),
+            ],
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "line 5: key A is already defined on an earlier line",
+        );
+    }
+
+    #[test]
+    fn parse_environ() {
+        let lines = utils::parse_environ("A=1#2\0B= # \0C\0D=x=y\0");
+        assert_eq!(
+            lines,
+            &[("A".into(), "1#2"), ("B".into(), " # "), ("D".into(), "x=y")],
+        );
+    }
+
+    #[test]
+    fn parse_environ_trailing_and_empty() {
+        assert_eq!(utils::parse_environ("A=1\0"), &[("A".into(), "1")]);
+        assert_eq!(utils::parse_environ("A=1\0\0"), &[("A".into(), "1")]);
+        assert_eq!(utils::parse_environ("A=\0"), &[("A".into(), "")]);
+        assert_eq!(
+            utils::parse_environ("A=\0B=\0C=3"),
+            &[("A".into(), ""), ("B".into(), ""), ("C".into(), "3")],
+        );
+        assert!(utils::parse_environ("").is_empty());
+        assert!(utils::parse_environ("\0").is_empty());
+
+        assert_eq!(utils::parse_lines_sep("A=\0B=2\0", '\0'), &[
+            ("A".into(), ""),
+            ("B".into(), "2"),
+        ]);
+    }
+
+    #[test]
+    fn parse_lines_sep() {
+        let lines = utils::parse_lines_sep("A=1\0B=2\0\0# c\0C=3#x", '\0');
+        assert_eq!(
+            lines,
+            &[("A".into(), "1"), ("B".into(), "2"), ("C".into(), "3")],
+        );
+
+        let lines = utils::parse_lines_sep("A=1\rB=2\r", '\r');
+        assert_eq!(lines, &[("A".into(), "1"), ("B".into(), "2")]);
+
+        // A newline is not a separator here, and so is part of the value.
+        let lines = utils::parse_lines_sep("A=1\nB=2", ';');
+        assert_eq!(lines, &[("A".into(), "1\nB=2")]);
+    }
+
+    #[test]
+    fn parse_lines_with_source() {
+        let buf = "A = 1 # one\r\n\n# comment\nB=2";
+        let lines = utils::parse_lines_with_source(buf);
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], SourceLine::Entry {
+            raw: "A = 1 # one",
+            key: "A".into(),
+            value: "1",
+        });
+        assert_eq!(lines[1], SourceLine::Raw(""));
+        assert_eq!(lines[2], SourceLine::Raw("# comment"));
+        assert_eq!(lines[3].raw(), "B=2");
+
+        // Each raw slice points into the original buffer.
+        let start = buf.as_ptr() as usize;
+        let offset = lines[3].raw().as_ptr() as usize - start;
+        assert_eq!(&buf[offset..offset + 3], "B=2");
+        let offset = lines[2].raw().as_ptr() as usize - start;
+        assert_eq!(&buf[offset..offset + 9], "# comment");
+    }
+
+    #[test]
+    fn sanitize_key() {
+        assert_eq!(utils::sanitize_key("my-key"), "MY_KEY");
+        assert_eq!(utils::sanitize_key("my.key"), "MY_KEY");
+        assert_eq!(utils::sanitize_key("my key"), "MY_KEY");
+        assert_eq!(utils::sanitize_key("ALREADY_VALID_1"), "ALREADY_VALID_1");
+        assert_eq!(utils::sanitize_key("a=b\0c"), "A_B_C");
+        assert_eq!(utils::sanitize_key("caf\u{e9}"), "CAF_");
+        assert_eq!(utils::sanitize_key("1st"), "_1ST");
+        assert_eq!(utils::sanitize_key(""), "");
+    }
+
+    #[test]
+    fn overlay_env() {
+        env::set_var("OVERLAY_BOTH", "env");
+
+        let mut map = utils::parse_to_map("OVERLAY_BOTH=file\nOVERLAY_FILE=1");
+        utils::overlay_env(&mut map);
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["OVERLAY_BOTH"], "env");
+        assert_eq!(map["OVERLAY_FILE"], "1");
+
+        env::remove_var("OVERLAY_BOTH");
+    }
+
+    #[test]
+    fn missing_from_env() {
+        utils::set_variables(&[("MISSING_EXISTING", "1")], true);
+
+        let mut cursor = Cursor::new(
+            "MISSING_NEW=1\nMISSING_EXISTING=2\nMISSING_NEW=3\nMISSING\0=4",
+        );
+        let missing = utils::missing_from_env(&mut cursor).unwrap();
+
+        assert_eq!(missing, vec!["MISSING_NEW"]);
+        assert!(env::var("MISSING_NEW").is_err());
+
+        utils::unload(&["MISSING_EXISTING"]);
+
+        // A value that is not valid unicode is present, and so is not
+        // overwritten by a load either.
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let value = OsStr::from_bytes(b"\xff");
+            env::set_var("MISSING_NON_UNICODE", value);
+
+            let mut cursor = Cursor::new("MISSING_NON_UNICODE=1");
+            assert!(utils::missing_from_env(&mut cursor).unwrap().is_empty());
+
+            utils::set_variables(&[("MISSING_NON_UNICODE", "1")], false);
+            assert_eq!(env::var_os("MISSING_NON_UNICODE").unwrap(), value);
+
+            utils::unload(&["MISSING_NON_UNICODE"]);
+        }
+    }
+
+    #[test]
+    fn parse_to_map() {
+        let map = utils::parse_to_map("A=1\nB=2\n# C=3\nA=4");
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["A"], "4");
+        assert_eq!(map["B"], "2");
+
+        let map = utils::parse_to_map(r"A\=B=1");
+        assert_eq!(map["A=B"], "1");
+    }
+
+    #[test]
+    fn parse_to_typed_map() {
+        let map = utils::parse_to_typed_map(
+            "PORT=8080\nDEBUG=true\nRATE=1.5\nNAME=abc",
+        );
+
+        assert_eq!(map["PORT"], Value::Int(8080));
+        assert_eq!(map["DEBUG"], Value::Bool(true));
+        assert_eq!(map["RATE"], Value::Float(1.5));
+        assert_eq!(map["NAME"], Value::Str("abc".to_owned()));
+    }
+
+    #[test]
+    fn value_infer_ambiguous() {
+        assert_eq!(Value::infer("-12"), Value::Int(-12));
+        assert_eq!(Value::infer("0"), Value::Int(0));
+        assert_eq!(Value::infer("0.5"), Value::Float(0.5));
+        assert_eq!(Value::infer("-0.5"), Value::Float(-0.5));
+
+        for s in &[
+            "0755", "01.5", "99999999999999999999", "1.", ".5", "1e5", "+1",
+            "-", "", "inf", "NaN", "True", "1.2.3",
+        ] {
+            assert_eq!(Value::infer(s), Value::Str(s.to_string()));
+        }
+    }
+
+    #[test]
+    fn set_directives() {
+        env::set_var("DIRECTIVE_UNSET", "1");
+        env::set_var("DIRECTIVE_EMPTY", "1");
+
+        let parser = Parser::new().unset_directives(true);
+        let buf = "DIRECTIVE_UNSET\nDIRECTIVE_EMPTY=";
+        let directives = parser.parse_lines(buf).unwrap();
+
+        utils::set_directives(&directives, false);
+        assert_eq!(env::var("DIRECTIVE_UNSET").unwrap(), "1");
+        assert_eq!(env::var("DIRECTIVE_EMPTY").unwrap(), "1");
+
+        utils::set_directives(&directives, true);
+        assert!(env::var("DIRECTIVE_UNSET").is_err());
+        assert_eq!(env::var("DIRECTIVE_EMPTY").unwrap(), "");
+
+        env::remove_var("DIRECTIVE_EMPTY");
+    }
+
+    #[test]
+    fn set_variables_fill_empty() {
+        env::set_var("FILL_EMPTY", "");
+        env::set_var("FILL_SET", "1");
+
+        utils::set_variables_fill_empty(&[
+            ("FILL_UNSET", "a"),
+            ("FILL_EMPTY", "b"),
+            ("FILL_SET", "c"),
+        ]);
+
+        assert_eq!(env::var("FILL_UNSET").unwrap(), "a");
+        assert_eq!(env::var("FILL_EMPTY").unwrap(), "b");
+        assert_eq!(env::var("FILL_SET").unwrap(), "1");
+
+        utils::unload(&["FILL_UNSET", "FILL_EMPTY", "FILL_SET"]);
+    }
+
+    #[test]
+    fn set_variables_changed() {
+        env::set_var("CHANGED_SAME", "1");
+        env::set_var("CHANGED_DIFFERENT", "1");
+
+        let lines = [("CHANGED_SAME", "1")];
+        assert!(utils::set_variables_changed(&lines, true).is_empty());
+
+        let lines = [
+            ("CHANGED_SAME", "1"),
+            ("CHANGED_DIFFERENT", "2"),
+            ("CHANGED_NEW", "3"),
+            ("CHANGED_NEW", "4"),
+        ];
+        assert_eq!(
+            utils::set_variables_changed(&lines, true),
+            vec!["CHANGED_DIFFERENT", "CHANGED_NEW"],
+        );
+        assert_eq!(env::var("CHANGED_NEW").unwrap(), "4");
+
+        let lines = [("CHANGED_DIFFERENT", "5")];
+        assert!(utils::set_variables_changed(&lines, false).is_empty());
+        assert_eq!(env::var("CHANGED_DIFFERENT").unwrap(), "2");
+
+        utils::unload(&["CHANGED_SAME", "CHANGED_DIFFERENT", "CHANGED_NEW"]);
+    }
+
+    #[test]
+    fn set_variables_in() {
+        let mut sink = HashMapSink::new();
+        sink.insert("EXISTING".to_owned(), "1".to_owned());
+
+        let lines = utils::parse_lines("EXISTING=2\nNEW=3");
+        utils::set_variables_in(&mut sink, &lines, false);
+        assert_eq!(sink["EXISTING"], "1");
+        assert_eq!(sink["NEW"], "3");
+
+        utils::set_variables_in(&mut sink, &lines, true);
+        assert_eq!(sink["EXISTING"], "2");
+        assert!(env::var("NEW").is_err());
+
+        let parser = Parser::new().unset_directives(true);
+        let directives = parser.parse_lines("EXISTING").unwrap();
+        utils::set_directives_in(&mut sink, &directives, true);
+        assert!(!sink.contains_key("EXISTING"));
+
+        utils::set_variables_in(&mut sink, &[("A=B", "1")], true);
+        assert_eq!(sink["A=B"], "1");
+
+        let directives = parser.parse_lines(r"A\=B").unwrap();
+        utils::set_directives_in(&mut sink, &directives, true);
+        assert!(!sink.contains_key("A=B"));
+    }
+
+    #[test]
+    fn nul_bytes() {
+        let buf = "NUL_OK=1\nNUL_VALUE=a\0b\nNUL\0KEY=1";
+        let lines = utils::parse_lines(buf);
+        assert_eq!(lines.len(), 3);
+
+        utils::set_variables(&lines, true);
+        assert_eq!(env::var("NUL_OK").unwrap(), "1");
+        assert!(env::var("NUL_VALUE").is_err());
+
+        let invalid = utils::set_variables_checked(&lines, true);
+        assert_eq!(invalid, &["NUL_VALUE", "NUL\0KEY"]);
+
+        utils::unload_from_parsed_lines(&lines);
+        assert!(env::var("NUL_OK").is_err());
+    }
+
     #[test]
     fn test_parse_kv() {
         let mut key = OsString::new();